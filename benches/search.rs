@@ -0,0 +1,213 @@
+//! How much work the chain search does: time and heap allocations per search, on the demo
+//! equipment and on bigger made-up inventories. Run with `cargo bench`.
+//!
+//! For comparison it also runs a search that copies the chain and the used set for every branch,
+//! the way `make_chain` used to, built on `Chain::add`. It doesn't prune chains that come back to
+//! a thread they've been through, so `make_chain` is run with `allow_repeats` to match, and the
+//! two explore the same states.
+//!
+//! It also times one simple search three ways, with threads as interned symbols (`Thread`), as
+//! `&'static str` names, and as owned `String` names, to see what interning saves.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::HashSet,
+    hash::Hash,
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use adapter_party::{equipment, make_chain_with_options, Adapter, Chain, SearchOptions, Thread};
+
+/// The system allocator, counting allocations.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Every chain from `start` to `end`, each partial chain a state with its own copies.
+fn cloning_search(start: Thread, end: Thread, equipment: &[Adapter]) -> (Vec<Chain>, usize) {
+    let start = Chain::new(Adapter::new(Thread::male("nil"), start).with_name("start"));
+    let mut states = vec![(start, HashSet::new())];
+    let mut found = vec![];
+    let mut explored = 0;
+    while let Some((chain, used)) = states.pop() {
+        explored += 1;
+        for (i, a) in equipment.iter().enumerate() {
+            if used.contains(&i) {
+                continue;
+            }
+            let Some(next) = chain.add(a.clone()) else { continue };
+            if next.adapters().last().unwrap().threads().1.mates(end) {
+                found.push(next);
+            } else {
+                let mut used = used.clone();
+                used.insert(i);
+                states.push((next, used));
+            }
+        }
+    }
+    found.sort_by(Chain::default_cmp);
+    (found, explored)
+}
+
+/// A thread as its name and whether it's male, for searching without interning.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Named<S>(S, bool);
+
+/// Explore every chain from `exposed` to `end`, with threads of any type, noting each thread
+/// reached along the way. Only threads of the same name and opposite genders mate, so all the
+/// search does with threads is compare and hash them. On step rings, which go on either way
+/// round, it explores the same states as `make_chain` with `allow_repeats`.
+fn simple_search<T: Clone + Eq + Hash>(
+    exposed: &T,
+    end: &T,
+    equipment: &[(T, T)],
+    mates: &impl Fn(&T, &T) -> bool,
+    used: &mut [bool],
+    reached: &mut HashSet<T>,
+) -> usize {
+    let mut explored = 1;
+    for i in 0..equipment.len() {
+        if used[i] {
+            continue;
+        }
+        let (a, b) = &equipment[i];
+        for (near, far) in [(a, b), (b, a)] {
+            if !mates(exposed, near) || mates(far, end) {
+                continue;
+            }
+            if !reached.contains(far) {
+                reached.insert(far.clone());
+            }
+            used[i] = true;
+            explored += simple_search(far, end, equipment, mates, used, reached);
+            used[i] = false;
+        }
+    }
+    explored
+}
+
+/// `simple_search` from the start, with threads converted by `convert`, returning the number of
+/// states explored.
+fn search_with<T: Clone + Eq + Hash>(
+    start: Thread,
+    end: Thread,
+    equipment: &[Adapter],
+    convert: impl Fn(Thread) -> T,
+    mates: impl Fn(&T, &T) -> bool,
+) -> impl FnMut() -> usize {
+    let equipment = equipment.iter()
+        .map(|a| (convert(a.threads().0), convert(a.threads().1)))
+        .collect::<Vec<_>>();
+    let (start, end) = (convert(start), convert(end));
+    move || {
+        let mut used = vec![false; equipment.len()];
+        let mut reached = HashSet::from([start.clone()]);
+        simple_search(&start, &end, &equipment, &mates, &mut used, &mut reached)
+    }
+}
+
+struct Run {
+    states: usize,
+    allocations: usize,
+    time: Duration,
+}
+
+/// Run a search enough times to time it, and count what one run allocates.
+fn measure(mut search: impl FnMut() -> usize) -> Run {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let states = search();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+
+    let mut runs = 0;
+    let started = Instant::now();
+    while runs < 3 || started.elapsed() < Duration::from_secs(1) {
+        black_box(search());
+        runs += 1;
+    }
+    Run { states, allocations, time: started.elapsed() / runs }
+}
+
+fn report(name: &str, run: Run) {
+    println!("{:<32} {:>8} states {:>10} allocations ({:>5.1} per state) {:>12.3?}", name,
+        run.states, run.allocations, run.allocations as f64 / run.states as f64, run.time);
+}
+
+fn bench(name: &str, start: Thread, end: Thread, equipment: &[Adapter]) {
+    let options = SearchOptions { allow_repeats: true, ..SearchOptions::default() };
+    report(&format!("{}: make_chain", name), measure(|| {
+        make_chain_with_options(start, end, equipment, &options).1.states_explored
+    }));
+    report(&format!("{}: cloning", name), measure(|| cloning_search(start, end, equipment).1));
+
+    let interned = search_with(start, end, equipment, |t| t, |a: &Thread, b: &Thread| {
+        a.sym() == b.sym() && a.is_male() != b.is_male()
+    });
+    let named = |t: Thread| Named(t.name(), t.is_male());
+    let owned = |t: Thread| Named(t.name().to_owned(), t.is_male());
+    let static_str = search_with(start, end, equipment, named, |a, b| a.0 == b.0 && a.1 != b.1);
+    let string = search_with(start, end, equipment, owned, |a, b| a.0 == b.0 && a.1 != b.1);
+    report(&format!("{}: interned", name), measure(interned));
+    report(&format!("{}: &'static str", name), measure(static_str));
+    report(&format!("{}: String", name), measure(string));
+}
+
+/// A step ring from every filter size to every other, and a way onto the camera from the last:
+/// a drawer full of rings.
+fn rings(sizes: &[&str]) -> Vec<Adapter> {
+    let mut rings = sizes.iter()
+        .flat_map(|&a| sizes.iter().filter(move |&&b| a != b).map(move |&b| (a, b)))
+        .map(|(a, b)| Adapter::new(Thread::male(a), Thread::female(b)))
+        .collect::<Vec<_>>();
+    rings.push(Adapter::new(Thread::male("EF"), Thread::female(sizes[sizes.len() - 1])));
+    rings
+}
+
+fn main() {
+    let sample = equipment![
+        "EF(M) -> 58(F)",
+        "EF(M) -> LTM(F)",
+        "EF(M) -> M42(F)",
+        "EF(M) -> FD(F)",
+        "58(M) -> 58(M)",
+        "Bay1(M) -> 46mm(F)",
+        "40.5(M) -> 46(F)",
+        "46(M) -> 52(F)",
+        "46(M) -> 77(F)",
+        "52(M) -> 77(F)",
+        "55(M) -> 77(F)",
+        "58(M) -> 77(F)",
+        "62(M) -> 77(F)",
+        "72(M) -> 77(F)",
+        "72(M) -> 52(F)",
+        "58(M) -> 52(F)",
+        "LTM(M) -> 40.5(F)" as "Rodenstock Rodagon 50mm f/2.8" @ Lens,
+        "LTM(M) -> 43(F)" as "Schneider Componon-S 80mm f/4" @ Lens,
+    ];
+    bench("sample", Thread::female("EF"), Thread::male("52"), &sample);
+
+    let (ef, to) = (Thread::female("EF"), Thread::male);
+    bench("rings", ef, to("40.5"), &rings(&["40.5", "46", "49", "52", "58"]));
+    // Nothing gets to 77mm, so it's all search and no results.
+    bench("rings, no way there", ef, to("77"), &rings(&["40.5", "46", "49", "52"]));
+}
@@ -0,0 +1,58 @@
+#ifndef ADAPTER_PARTY_H
+#define ADAPTER_PARTY_H
+
+/* Generated by cbindgen from src/ffi.rs; don't edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// A parsed inventory, opaque to C.
+typedef struct ApInventory ApInventory;
+
+// Parse an inventory from TOML text, the same as an inventory file. Returns null on failure,
+// with the reason from `ap_last_error`. Free the result with `ap_inventory_free`.
+//
+// # Safety
+//
+// `toml` must be null or a NUL-terminated string.
+ApInventory *ap_inventory_parse(const char *toml);
+
+// Free an inventory from `ap_inventory_parse`. Null is ignored.
+//
+// # Safety
+//
+// `inventory` must be null or from `ap_inventory_parse`, and not already freed.
+void ap_inventory_free(ApInventory *inventory);
+
+// Every chain from `from` to `to`, each a thread like `EF(F)` or the name of a terminal item in
+// the inventory. `options` is null or a JSON object with any of `via` and `avoid` (lists of
+// thread names), `loops` and `allow_repeats` (booleans), `max_states` (a number), and `kits` (a
+// list of kit names to use, rather than everything). The inventory's `[roles]` and
+// `hermaphroditic` threads apply too.
+//
+// Returns `{"chains": [...], "stats": {...}}`, or `{"error": "..."}` if something's wrong, never
+// null. Free it with `ap_free`.
+//
+// # Safety
+//
+// `inventory` must be from `ap_inventory_parse` and not freed. The strings must be null or
+// NUL-terminated.
+char *ap_find_chains(const ApInventory *inventory,
+                     const char *from,
+                     const char *to,
+                     const char *options);
+
+// Free a string from `ap_find_chains`. Null is ignored.
+//
+// # Safety
+//
+// `s` must be null or a string from `ap_find_chains`, and not already freed.
+void ap_free(char *s);
+
+// Why the last `ap_inventory_parse` on this thread failed, or null if none has. The string
+// belongs to the library and lasts until the next failure on this thread.
+const char *ap_last_error(void);
+
+#endif /* ADAPTER_PARTY_H */
@@ -0,0 +1,32 @@
+/*
+ * Finds the chains from an EF body to a 52mm male thread, and prints them as JSON.
+ *
+ *     cargo rustc --lib --release --features ffi --crate-type cdylib
+ *     cc ffi/example.c -Iffi -Ltarget/release -ladapter_party -o example
+ *     LD_LIBRARY_PATH=target/release ./example
+ */
+
+#include <stdio.h>
+
+#include "adapter_party.h"
+
+static const char *INVENTORY =
+    "[[adapter]]\n"
+    "threads = [\"EF(M)\", \"58(F)\"]\n"
+    "[[adapter]]\n"
+    "threads = [\"58(M)\", \"52(F)\"]\n";
+
+int main(void) {
+    ApInventory *inventory = ap_inventory_parse(INVENTORY);
+    if (inventory == NULL) {
+        fprintf(stderr, "%s\n", ap_last_error());
+        return 1;
+    }
+
+    char *json = ap_find_chains(inventory, "EF(F)", "52(M)", "{\"max_states\": 1000}");
+    printf("%s\n", json);
+    ap_free(json);
+
+    ap_inventory_free(inventory);
+    return 0;
+}
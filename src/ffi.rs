@@ -0,0 +1,194 @@
+//! A C interface to the chain search, for calling it from C or anything else that can call C,
+//! without bindings for each language. Enabled by the `ffi` feature; build a shared library with
+//! `cargo rustc --lib --release --features ffi --crate-type cdylib`. The header is
+//! `ffi/adapter_party.h`, generated by `cbindgen --config cbindgen.toml --output
+//! ffi/adapter_party.h`, and `ffi/example.c` shows it in use (`tests/ffi.rs` builds and runs it).
+//!
+//! Results are UTF-8 JSON, the same as the command line's `--format json`. Ownership is simple:
+//!
+//! - Strings passed in are borrowed for the length of the call, and must be NUL-terminated UTF-8.
+//! - Strings returned (from `ap_find_chains`) belong to the caller, who frees them with `ap_free`.
+//! - Inventories returned by `ap_inventory_parse` belong to the caller, who frees them with
+//!   `ap_inventory_free`.
+//! - `ap_last_error`'s string belongs to the library, and lasts until the next failed call on the
+//!   same thread.
+//!
+//! Nothing panics across the boundary: a panic inside is reported as an error, the same as any
+//! other.
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    inventory::{Inventory, LoadOptions, Selection},
+    make_chain_with_options,
+    ParseThreadError,
+    SearchOptions,
+    Thread,
+};
+
+/// A parsed inventory, opaque to C.
+pub struct ApInventory(Inventory);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    // Messages with a NUL in them are cut short rather than lost.
+    let message = message.split('\0').next().unwrap_or_default().to_owned();
+    LAST_ERROR.with(|e| *e.borrow_mut() = CString::new(message).ok());
+}
+
+/// Run `f`, turning a panic into an error message.
+fn guard<T>(f: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_owned());
+        Err(format!("internal error: {}", message))
+    })
+}
+
+/// A borrowed C string as a `&str`, or what's wrong with it.
+///
+/// # Safety
+///
+/// `s` must be null or point to a NUL-terminated string that outlives `'a`.
+unsafe fn borrow<'a>(s: *const c_char, what: &str) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err(format!("{} is null", what));
+    }
+    CStr::from_ptr(s).to_str().map_err(|_| format!("{} isn't UTF-8", what))
+}
+
+/// What `ap_find_chains` takes as options: a subset of `SearchOptions`, as JSON.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct Options {
+    via: Vec<String>,
+    avoid: Vec<String>,
+    loops: bool,
+    allow_repeats: bool,
+    max_states: Option<usize>,
+    kits: Vec<String>,
+}
+
+/// Parse an inventory from TOML text, the same as an inventory file. Returns null on failure,
+/// with the reason from `ap_last_error`. Free the result with `ap_inventory_free`.
+///
+/// # Safety
+///
+/// `toml` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ap_inventory_parse(toml: *const c_char) -> *mut ApInventory {
+    let result = guard(|| {
+        let toml = borrow(toml, "the inventory")?;
+        Inventory::from_toml_str(toml, &LoadOptions::default()).map_err(|e| e.to_string())
+    });
+    match result {
+        Ok(inventory) => Box::into_raw(Box::new(ApInventory(inventory))),
+        Err(message) => {
+            set_last_error(message);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Free an inventory from `ap_inventory_parse`. Null is ignored.
+///
+/// # Safety
+///
+/// `inventory` must be null or from `ap_inventory_parse`, and not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn ap_inventory_free(inventory: *mut ApInventory) {
+    if !inventory.is_null() {
+        drop(Box::from_raw(inventory));
+    }
+}
+
+/// Every chain from `from` to `to`, each a thread like `EF(F)` or the name of a terminal item in
+/// the inventory. `options` is null or a JSON object with any of `via` and `avoid` (lists of
+/// thread names), `loops` and `allow_repeats` (booleans), `max_states` (a number), and `kits` (a
+/// list of kit names to use, rather than everything). The inventory's `[roles]` and
+/// `hermaphroditic` threads apply too.
+///
+/// Returns `{"chains": [...], "stats": {...}}`, or `{"error": "..."}` if something's wrong, never
+/// null. Free it with `ap_free`.
+///
+/// # Safety
+///
+/// `inventory` must be from `ap_inventory_parse` and not freed. The strings must be null or
+/// NUL-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn ap_find_chains(
+    inventory: *const ApInventory,
+    from: *const c_char,
+    to: *const c_char,
+    options: *const c_char,
+) -> *mut c_char {
+    let result = guard(|| {
+        let Some(ApInventory(inventory)) = inventory.as_ref() else {
+            return Err("the inventory is null".to_owned());
+        };
+        let from = end(inventory, borrow(from, "from")?)?;
+        let to = end(inventory, borrow(to, "to")?)?;
+        let options = if options.is_null() {
+            Options::default()
+        } else {
+            serde_json::from_str(borrow(options, "options")?).map_err(|e| e.to_string())?
+        };
+        let registry = inventory.registry();
+        let search = SearchOptions {
+            via: options.via.iter().map(|name| registry.canonical(name)).collect(),
+            avoid: options.avoid.iter().map(|name| registry.canonical(name)).collect(),
+            loops: options.loops,
+            allow_repeats: options.allow_repeats,
+            max_states: options.max_states.or(SearchOptions::default().max_states),
+            ..inventory.search_options()
+        };
+        let selection = Selection { kits: options.kits, ..Selection::default() };
+        let equipment = inventory.select(&selection);
+        let (chains, stats) = make_chain_with_options(from, to, &equipment, &search);
+        Ok(serde_json::json!({ "chains": chains, "stats": stats }))
+    });
+    let json = result.unwrap_or_else(|message| serde_json::json!({ "error": message }));
+    // JSON escapes any NUL in a string, so there can't be one in the output.
+    CString::new(json.to_string()).expect("JSON has no NULs").into_raw()
+}
+
+/// A chain end: a terminal item by name, or a thread.
+fn end(inventory: &Inventory, s: &str) -> Result<Thread, String> {
+    match inventory.terminal(s) {
+        Some(terminal) => Ok(terminal.thread),
+        None => {
+            let thread = s.parse().map_err(|e: ParseThreadError| e.to_string())?;
+            Ok(inventory.registry().canonical_thread(thread))
+        }
+    }
+}
+
+/// Free a string from `ap_find_chains`. Null is ignored.
+///
+/// # Safety
+///
+/// `s` must be null or a string from `ap_find_chains`, and not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn ap_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Why the last `ap_inventory_parse` on this thread failed, or null if none has. The string
+/// belongs to the library and lasts until the next failure on this thread.
+#[no_mangle]
+pub extern "C" fn ap_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
@@ -0,0 +1,1680 @@
+//! Loading equipment lists from an inventory file.
+//!
+//! The file is TOML, with one `[[adapter]]` table per piece of equipment:
+//!
+//! ```toml
+//! hermaphroditic = ["PF25"]
+//!
+//! [aliases]
+//! 46mm = "46"
+//!
+//! [roles]
+//! EF = "body"
+//!
+//! [[adapter]]
+//! threads = ["EF(M)", "58(F)"]
+//! category = "mount_adapter"
+//!
+//! [[adapter]]
+//! name = "Rodenstock Rodagon 50mm f/2.8"
+//! threads = ["LTM(M)", "40.5(F)"]
+//! category = "lens"
+//! quantity = 1
+//! notes = "aperture ring is stiff"
+//! loaned_to = "Sam"
+//! loaned_on = "2024-05-01"
+//! kit = "darkroom"
+//! collides_with = ["52(M) -> 58(F)"]
+//! outer_diameter = 46.0
+//!
+//! [[terminal]]
+//! name = "Canon 5D"
+//! thread = "EF(F)"
+//! kind = "body"
+//! ```
+//!
+//! `[[terminal]]` tables list things with only one thread (see `Terminal`); their gender is
+//! always required. For adapters, the first thread is the end that faces the camera, same as
+//! `Adapter::new`. Thread names are normalized through the `Registry` as they're loaded,
+//! including any `[aliases]` in the file. `[roles]` says where in a chain a thread may appear
+//! (see `ThreadRole`), and `hermaphroditic` lists threads that mate with their own gender (see
+//! `SearchOptions::hermaphroditic`). Adapters without a category get one worked out from
+//! their threads where it's obvious (see `registry::infer_category`). Adapters with a bayonet on
+//! either end are only used the way round they're listed, unless they say `reversible = true`
+//! (and anything else can be pinned with `reversible = false`). `collides_with` lists items, by
+//! name or threads, that it mustn't go directly against in a chain. `outer_diameter` and
+//! `recess_diameter` (how wide it is, and how wide a recessed female thread's opening is, in mm)
+//! are used to warn about pieces that can't seat in each other (see `Chain::interferences`), and
+//! `male_length` and `female_depth` (how long its male thread is, and how deep its female one, in
+//! mm) about threads that bottom out (see `Chain::seating_problems`). `cost` and `length` (what
+//! it's worth using, in any unit as long as it's the same one throughout, and how much it adds to
+//! the stack, in mm) are what `optimal_chain` weighs up. `price` is what one is worth, for
+//! `Inventory::valuation`; it and `length` are also totted up by `Inventory::stats_by_category`.
+//!
+//! The same structure can be written as JSON instead. There's also a CSV form, with a header line
+//! naming the columns `name`, `from`, `to`, `category`, `quantity`, `notes`, `loaned_to`,
+//! `loaned_on`, `kit`, `reversible`, `outer_diameter`, `recess_diameter`, `male_length`,
+//! `female_depth`, `cost`, `length`, and `price` (only `from` and `to` are required), which has no
+//! way to give aliases, roles, hermaphroditic threads, terminal items, or collisions.
+
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fmt::{self, Display, Formatter},
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    registry::{self, Registry, KNOWN_MOUNTS},
+    Adapter,
+    Category,
+    Coupling,
+    ParseThreadError,
+    SearchOptions,
+    Terminal,
+    TerminalKind,
+    Thread,
+    ThreadRole,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Fill in missing genders on known mounts (see `KNOWN_MOUNTS`).
+    pub infer_gender: bool,
+    /// Merge an entry that's an earlier one listed backwards into that earlier one, instead of
+    /// just warning about it.
+    pub dedup_reversed: bool,
+}
+
+/// The most problems reported from one file; past this they're only counted.
+pub const MAX_DIAGNOSTICS: usize = 20;
+
+/// A problem with an inventory file, and where it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Counting from 1, or 0 if the position isn't known.
+    pub line: usize,
+    /// In characters, counting from 1.
+    pub column: usize,
+    pub message: String,
+    /// The text of the line, to echo with a caret under the column.
+    pub source_line: String,
+}
+
+impl Display for Diagnostic {
+    /// `line:column: message`, then the line with a caret under the column.
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            return f.write_str(&self.message);
+        }
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)?;
+        if !self.source_line.is_empty() {
+            // Keep tabs so the caret lines up however they're shown.
+            let indent: String = self.source_line.chars()
+                .take(self.column - 1)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            write!(f, "\n    {}\n    {}^", self.source_line, indent)?;
+        }
+        Ok(())
+    }
+}
+
+fn diagnostics(mut diagnostics: Vec<Diagnostic>) -> LoadError {
+    let omitted = diagnostics.len().saturating_sub(MAX_DIAGNOSTICS);
+    diagnostics.truncate(MAX_DIAGNOSTICS);
+    LoadError::Diagnostics { diagnostics, omitted }
+}
+
+/// Where a thread that didn't parse was written.
+#[derive(Debug, Clone, Copy)]
+enum Site {
+    Adapter { entry: usize, end: usize },
+    Terminal(usize),
+}
+
+/// A thread that didn't parse, before it's been found in the file.
+#[derive(Debug)]
+struct Problem {
+    site: Site,
+    /// As written.
+    thread: String,
+    message: String,
+}
+
+impl Problem {
+    fn new(site: Site, thread: &str, error: LoadError) -> Self {
+        let message = match error {
+            // The position says which entry.
+            LoadError::AmbiguousGender { thread, .. } => {
+                format!("thread {:?} needs a gender, like \"{}(M)\" or \"{}(F)\"",
+                    thread, thread, thread)
+            }
+            e => e.to_string(),
+        };
+        Self { site, thread: thread.to_owned(), message }
+    }
+}
+
+/// The text an inventory was read from, for pointing at where problems are.
+struct Source<'a> {
+    text: &'a str,
+    format: Format,
+    /// For CSV, the byte offset of each adapter's row.
+    rows: Vec<usize>,
+}
+
+impl Source<'_> {
+    fn at(&self, offset: usize, message: impl Into<String>) -> Diagnostic {
+        let mut offset = offset.min(self.text.len());
+        while !self.text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let start = self.text[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let end = self.text[offset..].find('\n').map_or(self.text.len(), |i| offset + i);
+        Diagnostic {
+            line: self.text[..start].matches('\n').count() + 1,
+            column: self.text[start..offset].chars().count() + 1,
+            message: message.into(),
+            source_line: self.text[start..end].trim_end_matches('\r').to_owned(),
+        }
+    }
+
+    /// Byte offset of a line and column as counted by the parsers: both from 1, column in bytes.
+    fn offset(&self, line: usize, column: usize) -> usize {
+        let start = self.text.split_inclusive('\n')
+            .take(line.saturating_sub(1))
+            .map(str::len)
+            .sum::<usize>();
+        let len = self.text[start..].find('\n').unwrap_or(self.text.len() - start);
+        start + column.saturating_sub(1).min(len)
+    }
+
+    fn unplaced(message: impl Into<String>) -> Diagnostic {
+        Diagnostic { line: 0, column: 0, message: message.into(), source_line: String::new() }
+    }
+
+    fn toml_error(&self, e: &toml::de::Error) -> Diagnostic {
+        match e.span() {
+            Some(span) => self.at(span.start, e.message()),
+            None => Self::unplaced(e.message()),
+        }
+    }
+
+    fn json_error(&self, e: &serde_json::Error) -> Diagnostic {
+        let message = e.to_string();
+        // The position goes in front instead.
+        let message = message.rsplit_once(" at line ").map_or(&*message, |(m, _)| m);
+        if e.line() == 0 {
+            return Self::unplaced(message);
+        }
+        self.at(self.offset(e.line(), e.column()), message)
+    }
+
+    fn csv_error(&self, e: &csv::Error) -> Diagnostic {
+        let (message, field) = match e.kind() {
+            csv::ErrorKind::Deserialize { err, .. } => (err.to_string(), err.field()),
+            _ => (e.to_string(), None),
+        };
+        let Some(pos) = e.position() else {
+            return Self::unplaced(message);
+        };
+        let start = pos.byte() as usize;
+        let offset = match field {
+            Some(field) => start + csv_field_offset(&self.text[start..], field as usize),
+            None => start,
+        };
+        self.at(offset, message)
+    }
+
+    /// Byte offsets of where each value of `key` is written, in file order.
+    fn keys(&self, key: &str) -> Vec<usize> {
+        if self.format == Format::Json {
+            let quoted = format!("\"{}\"", key);
+            return self.text.match_indices(&quoted)
+                .filter(|(i, _)| self.text[i + quoted.len()..].trim_start().starts_with(':'))
+                .map(|(i, _)| i)
+                .collect();
+        }
+        let mut offsets = vec![];
+        let mut start = 0;
+        for line in self.text.split_inclusive('\n') {
+            let body = line.trim_start();
+            if body.strip_prefix(key).is_some_and(|rest| rest.trim_start().starts_with('=')) {
+                offsets.push(start + line.len() - body.len());
+            }
+            start += line.len();
+        }
+        offsets
+    }
+
+    /// Byte offset of where a problem thread is written, as near as can be told.
+    fn locate(&self, site: Site, thread: &str) -> Option<usize> {
+        let (starts, nth, end) = match (site, self.format) {
+            (Site::Adapter { entry, end }, Format::Csv) => (self.rows.clone(), entry, end),
+            (Site::Terminal(_), Format::Csv) => return None,
+            (Site::Adapter { entry, end }, _) => (self.keys("threads"), entry, end),
+            (Site::Terminal(idx), _) => (self.keys("thread"), idx, 0),
+        };
+        let from = *starts.get(nth)?;
+        let to = starts.get(nth + 1).copied().unwrap_or(self.text.len());
+        let hits: Vec<usize> = self.text[from..to].match_indices(thread)
+            .map(|(i, _)| from + i)
+            .collect();
+        Some(hits.get(end).or(hits.last()).copied().unwrap_or(from))
+    }
+
+    fn diagnose(&self, problems: Vec<Problem>) -> LoadError {
+        diagnostics(problems.into_iter()
+            .map(|problem| match self.locate(problem.site, &problem.thread) {
+                Some(offset) => self.at(offset, problem.message),
+                None => Self::unplaced(problem.message),
+            })
+            .collect())
+    }
+}
+
+/// Byte offset of the start of a field in a CSV record.
+fn csv_field_offset(record: &str, field: usize) -> usize {
+    let mut quoted = false;
+    let mut seen = 0;
+    for (i, c) in record.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                seen += 1;
+                if seen == field {
+                    return i + 1;
+                }
+            }
+            '\n' if !quoted => break,
+            _ => {}
+        }
+    }
+    0
+}
+
+/// Something suspicious about an inventory file that doesn't stop it from loading.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadWarning {
+    /// Entry `duplicate` is entry `first` listed the other way around, so it's probably the same
+    /// piece listed twice. Entries are numbered from 0 in file order.
+    ReversedDuplicate { first: usize, duplicate: usize, merged: bool },
+    /// An entry's category isn't what its threads say it is (see `registry::infer_category`).
+    /// Lenses aren't checked, since their threads look like anything.
+    CategoryMismatch { entry: usize, explicit: Category, inferred: Category },
+    /// An entry is listed as a step ring, but one of its ends is a bayonet, which a step ring
+    /// can't have.
+    BayonetStepRing { entry: usize, bayonet: Thread },
+    /// Something in an entry's `collides_with` doesn't match anything in the inventory.
+    UnknownCollision { entry: usize, item: String },
+}
+
+impl Display for LoadWarning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReversedDuplicate { first, duplicate, merged } => {
+                write!(f, "entry {} is entry {} reversed", duplicate + 1, first + 1)?;
+                if *merged {
+                    f.write_str("; merged them")
+                } else {
+                    f.write_str("; is it listed twice?")
+                }
+            }
+            Self::CategoryMismatch { entry, explicit, inferred } => {
+                write!(f, "entry {} is listed as a {}, but its threads make it a {}", entry + 1,
+                    explicit, inferred)
+            }
+            Self::BayonetStepRing { entry, bayonet } => {
+                write!(f, "entry {} is listed as a step ring, but {} is a bayonet, not a screw \
+                    thread", entry + 1, bayonet.name())
+            }
+            Self::UnknownCollision { entry, item } => {
+                write!(f, "entry {} collides with {:?}, but nothing in the inventory matches that",
+                    entry + 1, item)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    Csv(csv::Error),
+    Thread(ParseThreadError),
+    /// A thread had no gender and it couldn't be inferred.
+    AmbiguousGender { entry: usize, thread: String },
+    /// Problems found in the file, with where they are. There are at most `MAX_DIAGNOSTICS`;
+    /// `omitted` says how many more there were.
+    Diagnostics { diagnostics: Vec<Diagnostic>, omitted: usize },
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {}", e),
+            Self::Toml(e) => write!(f, "invalid inventory file: {}", e),
+            Self::Json(e) => write!(f, "invalid JSON inventory: {}", e),
+            Self::Csv(e) => write!(f, "invalid CSV inventory: {}", e),
+            Self::Thread(e) => e.fmt(f),
+            Self::AmbiguousGender { entry, thread } => {
+                write!(f, "entry {}: thread {:?} needs a gender, like \"{}(M)\" or \"{}(F)\"",
+                    entry + 1, thread, thread, thread)
+            }
+            Self::Diagnostics { diagnostics, omitted } => {
+                for (i, diagnostic) in diagnostics.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", diagnostic)?;
+                }
+                if *omitted > 0 {
+                    write!(f, "\n...and {} more", omitted)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Toml(e) => Some(e),
+            Self::Json(e) => Some(e),
+            Self::Csv(e) => Some(e),
+            Self::Thread(e) => Some(e),
+            Self::AmbiguousGender { .. } | Self::Diagnostics { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for LoadError {
+    fn from(e: toml::de::Error) -> Self {
+        Self::Toml(e)
+    }
+}
+
+impl From<serde_json::Error> for LoadError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+impl From<csv::Error> for LoadError {
+    fn from(e: csv::Error) -> Self {
+        Self::Csv(e)
+    }
+}
+
+impl From<ParseThreadError> for LoadError {
+    fn from(e: ParseThreadError) -> Self {
+        Self::Thread(e)
+    }
+}
+
+/// Which of an inventory's entries to search with. The default is everything that isn't lent out.
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    /// Only use what's in these kits, if any are given.
+    pub kits: Vec<String>,
+    /// Leave out what's in these kits.
+    pub without_kits: Vec<String>,
+    /// Use things that are lent out too.
+    pub include_loaned: bool,
+}
+
+/// Totals for one category of equipment (see `Inventory::stats_by_category`).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CategoryStats {
+    /// Lines in the inventory.
+    pub entries: usize,
+    /// Pieces of equipment, counting every copy.
+    pub count: u32,
+    /// How many of those are lent out.
+    pub loaned: u32,
+    /// What they're worth, by each entry's `price` times its quantity. Entries without a price
+    /// aren't counted.
+    pub total_price: f64,
+    /// How long they'd be all stacked up, in mm, by each entry's `length` times its quantity.
+    /// Entries without a length aren't counted.
+    pub total_length: f64,
+}
+
+/// Where the price of an item in a `Valuation` came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceSource {
+    /// Its own `price`.
+    Recorded,
+    /// An entry with the same threads in a replacement price catalog.
+    Replacement,
+    /// Neither, so it isn't counted.
+    Unpriced,
+}
+
+impl Display for PriceSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Recorded => "recorded",
+            Self::Replacement => "replacement",
+            Self::Unpriced => "unpriced",
+        })
+    }
+}
+
+/// One entry's part in a `Valuation`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValuedItem {
+    pub item: String,
+    pub category: String,
+    pub quantity: u32,
+    /// For one of them.
+    pub price: Option<f64>,
+    /// For all of them.
+    pub value: f64,
+    pub source: PriceSource,
+}
+
+/// What an inventory is worth (see `Inventory::valuation`). Prices are in whatever currency the
+/// inventory uses.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Valuation {
+    pub total: f64,
+    /// The total for each category, by its name, with entries without one under "uncategorized".
+    pub by_category: BTreeMap<String, f64>,
+    /// Every entry, most valuable first.
+    pub items: Vec<ValuedItem>,
+    /// How many pieces, counting every copy, have no price, so aren't in the total.
+    pub unpriced: u32,
+}
+
+/// What `Inventory::merge` does with something the other inventory has too.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Keep both: quantities of matching entries are added up, and both copies of a terminal item
+    /// are kept.
+    #[default]
+    KeepBoth,
+    /// Keep what's already there and drop the other one.
+    KeepFirst,
+    /// Fail without changing anything.
+    Error,
+}
+
+/// Something both inventories have, found by `Inventory::merge` with `MergePolicy::Error`. Indices
+/// are into the other inventory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeError {
+    Entry(usize),
+    Terminal(usize),
+    /// The alias points somewhere else in each inventory.
+    Alias(String),
+    /// The thread has a different role in each inventory.
+    Role(String),
+}
+
+impl Display for MergeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Entry(idx) => write!(f, "entry {} is already in the inventory", idx + 1),
+            Self::Terminal(idx) => write!(f, "terminal {} is already in the inventory", idx + 1),
+            Self::Alias(alias) => write!(f, "alias {:?} already means something else", alias),
+            Self::Role(thread) => write!(f, "{} already has a different role", thread),
+        }
+    }
+}
+
+impl Error for MergeError {}
+
+/// One line item in the inventory: an adapter plus the bookkeeping that doesn't affect chaining.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub adapter: Adapter,
+    pub quantity: u32,
+    /// The adapter's category was worked out from its threads rather than given in the file, so
+    /// it isn't written back.
+    pub inferred_category: bool,
+}
+
+impl Entry {
+    pub fn new(adapter: Adapter) -> Self {
+        Self { adapter, quantity: 1, inferred_category: false }
+    }
+
+    /// Ordering used when formatting the file: by category (uncategorized last), then threads,
+    /// then name.
+    fn canonical_cmp(&self, other: &Self) -> Ordering {
+        let key = |e: &Self| {
+            let category = e.adapter.category();
+            (category.is_none(), category, e.adapter.0.to_string(), e.adapter.1.to_string())
+        };
+        key(self).cmp(&key(other)).then_with(|| self.adapter.2.cmp(&other.adapter.2))
+    }
+
+    /// Same piece of equipment, written the same way: only the quantity may differ.
+    fn same_item(&self, other: &Self) -> bool {
+        self.adapter.0 == other.adapter.0
+            && self.adapter.1 == other.adapter.1
+            && self.adapter.2 == other.adapter.2
+            && self.adapter.info() == other.adapter.info()
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        // Stricter than `Adapter`'s equality, which ignores names and direction.
+        self.same_item(other) && self.quantity == other.quantity
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct File {
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    hermaphroditic: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    aliases: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    roles: BTreeMap<String, ThreadRole>,
+    #[serde(default)]
+    adapter: Vec<FileEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    terminal: Vec<FileTerminal>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FileTerminal {
+    name: String,
+    thread: String,
+    kind: TerminalKind,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FileEntry {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    name: String,
+    threads: [String; 2],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    category: Option<Category>,
+    #[serde(default = "one", skip_serializing_if = "is_one")]
+    quantity: u32,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    notes: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    loaned_to: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    loaned_on: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reversible: Option<bool>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    collides_with: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    outer_diameter: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    recess_diameter: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    male_length: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    female_depth: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cost: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    length: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    price: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CsvRow {
+    #[serde(default)]
+    name: String,
+    from: String,
+    to: String,
+    #[serde(default)]
+    category: Option<Category>,
+    #[serde(default)]
+    quantity: Option<u32>,
+    #[serde(default)]
+    notes: String,
+    #[serde(default)]
+    loaned_to: Option<String>,
+    #[serde(default)]
+    loaned_on: Option<String>,
+    #[serde(default)]
+    kit: Option<String>,
+    #[serde(default)]
+    reversible: Option<bool>,
+    #[serde(default)]
+    outer_diameter: Option<f64>,
+    #[serde(default)]
+    recess_diameter: Option<f64>,
+    #[serde(default)]
+    male_length: Option<f64>,
+    #[serde(default)]
+    female_depth: Option<f64>,
+    #[serde(default)]
+    cost: Option<f64>,
+    #[serde(default)]
+    length: Option<f64>,
+    #[serde(default)]
+    price: Option<f64>,
+}
+
+impl From<CsvRow> for FileEntry {
+    fn from(row: CsvRow) -> Self {
+        Self {
+            name: row.name,
+            threads: [row.from, row.to],
+            category: row.category,
+            quantity: row.quantity.unwrap_or(1),
+            notes: row.notes,
+            loaned_to: row.loaned_to,
+            loaned_on: row.loaned_on,
+            kit: row.kit.filter(|kit| !kit.is_empty()),
+            reversible: row.reversible,
+            collides_with: vec![],
+            outer_diameter: row.outer_diameter,
+            recess_diameter: row.recess_diameter,
+            male_length: row.male_length,
+            female_depth: row.female_depth,
+            cost: row.cost,
+            length: row.length,
+            price: row.price,
+        }
+    }
+}
+
+/// The file formats an inventory can be read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Toml,
+    Json,
+    Csv,
+}
+
+impl Format {
+    /// Guess the format from a file's extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "toml" => Some(Self::Toml),
+            "json" => Some(Self::Json),
+            "csv" => Some(Self::Csv),
+            _ => None,
+        }
+    }
+}
+
+fn one() -> u32 {
+    1
+}
+
+fn is_one(n: &u32) -> bool {
+    *n == 1
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Inventory {
+    entries: Vec<Entry>,
+    terminals: Vec<Terminal>,
+    registry: Registry,
+    /// By canonical thread name.
+    roles: BTreeMap<String, ThreadRole>,
+    /// Canonical names.
+    hermaphroditic: BTreeSet<String>,
+    warnings: Vec<LoadWarning>,
+}
+
+impl PartialEq for Inventory {
+    fn eq(&self, other: &Self) -> bool {
+        // Warnings are about how the file was written, not what's in it.
+        self.entries == other.entries
+            && self.terminals == other.terminals
+            && self.registry == other.registry
+            && self.roles == other.roles
+            && self.hermaphroditic == other.hermaphroditic
+    }
+}
+
+impl Inventory {
+    pub fn from_reader(
+        mut reader: impl Read,
+        format: Format,
+        options: &LoadOptions,
+    ) -> Result<Self, LoadError> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        let mut source = Source { text: &text, format, rows: vec![] };
+        let file = match format {
+            Format::Toml => {
+                toml::from_str(&text).map_err(|e| diagnostics(vec![source.toml_error(&e)]))?
+            }
+            Format::Json => {
+                serde_json::from_str(&text).map_err(|e| diagnostics(vec![source.json_error(&e)]))?
+            }
+            Format::Csv => {
+                let mut reader = csv::Reader::from_reader(text.as_bytes());
+                let headers = reader.headers()
+                    .map_err(|e| diagnostics(vec![source.csv_error(&e)]))?
+                    .clone();
+                let mut adapter = vec![];
+                let mut errors = vec![];
+                for record in reader.records() {
+                    let row = record.and_then(|record| {
+                        let row = record.deserialize::<CsvRow>(Some(&headers))?;
+                        Ok((record.position().map_or(0, |pos| pos.byte() as usize), row))
+                    });
+                    match row {
+                        Ok((start, row)) => {
+                            source.rows.push(start);
+                            adapter.push(FileEntry::from(row));
+                        }
+                        Err(e) => errors.push(source.csv_error(&e)),
+                    }
+                }
+                if !errors.is_empty() {
+                    return Err(diagnostics(errors));
+                }
+                File {
+                    hermaphroditic: BTreeSet::new(),
+                    aliases: BTreeMap::new(),
+                    roles: BTreeMap::new(),
+                    adapter,
+                    terminal: vec![],
+                }
+            }
+        };
+        Self::from_file(file, options).map_err(|problems| source.diagnose(problems))
+    }
+
+    pub fn from_toml_str(s: &str, options: &LoadOptions) -> Result<Self, LoadError> {
+        Self::from_reader(s.as_bytes(), Format::Toml, options)
+    }
+
+    /// Fails with every thread that doesn't parse, not just the first.
+    fn from_file(file: File, options: &LoadOptions) -> Result<Self, Vec<Problem>> {
+        let mut registry = Registry::new();
+        for (alias, canonical) in file.aliases {
+            registry.add_alias(alias, canonical);
+        }
+        let hermaphroditic: BTreeSet<String> = file.hermaphroditic.iter()
+            .map(|thread| registry.canonical(thread).to_owned())
+            .collect();
+        let mut entries: Vec<Entry> = vec![];
+        let mut warnings = vec![];
+        // Which file entry each of `entries` came from.
+        let mut file_idx = vec![];
+        let mut problems = vec![];
+        for (idx, entry) in file.adapter.into_iter().enumerate() {
+            let [a, b] = [0, 1].map(|end| {
+                let thread = &entry.threads[end];
+                parse_thread(thread, end, idx, &registry, options)
+                    .map_err(|e| Problem::new(Site::Adapter { entry: idx, end }, thread, e))
+            });
+            let (a, b) = match (a, b) {
+                (Ok(a), Ok(b)) => (a, b),
+                (a, b) => {
+                    problems.extend(a.err().into_iter().chain(b.err()));
+                    continue;
+                }
+            };
+            if a != b {
+                if let Some(j) = entries.iter().position(|e| e.adapter.0 == b && e.adapter.1 == a) {
+                    warnings.push(LoadWarning::ReversedDuplicate {
+                        first: file_idx[j],
+                        duplicate: idx,
+                        merged: options.dedup_reversed,
+                    });
+                    if options.dedup_reversed {
+                        entries[j].quantity += entry.quantity;
+                        continue;
+                    }
+                }
+            }
+            let inferred = registry::infer_category(a, b);
+            if let (Some(explicit), Some(inferred)) = (entry.category, inferred) {
+                if explicit != inferred && explicit != Category::Lens {
+                    warnings.push(LoadWarning::CategoryMismatch { entry: idx, explicit, inferred });
+                }
+            }
+            if matches!(entry.category, Some(Category::StepUp | Category::StepDown)) {
+                let is_bayonet = |t: Thread| registry::coupling(t) == Coupling::Bayonet;
+                if let Some(bayonet) = [a, b].into_iter().find(|&t| is_bayonet(t)) {
+                    warnings.push(LoadWarning::BayonetStepRing { entry: idx, bayonet });
+                }
+            }
+            file_idx.push(idx);
+            entries.push(Entry {
+                inferred_category: entry.category.is_none() && inferred.is_some(),
+                adapter: Adapter::new(a, b)
+                    .with_name(entry.name)
+                    .with_category(entry.category.or(inferred))
+                    .with_notes(entry.notes)
+                    .with_loan(entry.loaned_to, entry.loaned_on)
+                    .with_kit(entry.kit)
+                    .with_reversible(entry.reversible)
+                    .with_collisions(entry.collides_with.iter()
+                        .map(|item| canonical_item(item, &registry))
+                        .collect())
+                    .with_outer_diameter(entry.outer_diameter)
+                    .with_recess_diameter(entry.recess_diameter)
+                    .with_male_length(entry.male_length)
+                    .with_female_depth(entry.female_depth)
+                    .with_cost(entry.cost)
+                    .with_length(entry.length)
+                    .with_price(entry.price),
+                quantity: entry.quantity,
+            });
+        }
+        for (entry, &idx) in entries.iter().zip(&file_idx) {
+            for item in &entry.adapter.info().collides_with {
+                if !entries.iter().any(|e| e.adapter.answers_to(item)) {
+                    warnings.push(LoadWarning::UnknownCollision { entry: idx, item: item.clone() });
+                }
+            }
+        }
+        let mut terminals = vec![];
+        for (idx, t) in file.terminal.into_iter().enumerate() {
+            match t.thread.parse() {
+                Ok(thread) => {
+                    let thread = registry.canonical_thread(thread);
+                    terminals.push(Terminal::new(t.name, thread, t.kind));
+                }
+                Err(e) => {
+                    let e = LoadError::Thread(e);
+                    problems.push(Problem::new(Site::Terminal(idx), &t.thread, e));
+                }
+            }
+        }
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+        let roles = file.roles.into_iter()
+            .map(|(thread, role)| (registry.canonical(&thread).to_owned(), role))
+            .collect();
+        Ok(Self { entries, terminals, registry, roles, hermaphroditic, warnings })
+    }
+
+    /// Problems noticed while loading.
+    pub fn warnings(&self) -> &[LoadWarning] {
+        &self.warnings
+    }
+
+    pub fn to_toml_string(&self) -> String {
+        let file = File {
+            hermaphroditic: self.hermaphroditic.clone(),
+            aliases: self.registry.aliases().clone(),
+            roles: self.roles.clone(),
+            adapter: self.entries.iter()
+                .map(|e| FileEntry {
+                    name: e.adapter.2.clone().into_owned(),
+                    threads: [e.adapter.0.to_string(), e.adapter.1.to_string()],
+                    category: e.adapter.category().filter(|_| !e.inferred_category),
+                    quantity: e.quantity,
+                    notes: e.adapter.notes().to_owned(),
+                    loaned_to: e.adapter.info().loaned_to.clone(),
+                    loaned_on: e.adapter.info().loaned_on.clone(),
+                    kit: e.adapter.info().kit.clone(),
+                    reversible: e.adapter.info().reversible,
+                    collides_with: e.adapter.info().collides_with.clone(),
+                    outer_diameter: e.adapter.info().outer_diameter,
+                    recess_diameter: e.adapter.info().recess_diameter,
+                    male_length: e.adapter.info().male_length,
+                    female_depth: e.adapter.info().female_depth,
+                    cost: e.adapter.info().cost,
+                    length: e.adapter.info().length,
+                    price: e.adapter.info().price,
+                })
+                .collect(),
+            terminal: self.terminals.iter()
+                .map(|t| FileTerminal {
+                    name: t.name.clone(),
+                    thread: t.thread.to_string(),
+                    kind: t.kind,
+                })
+                .collect(),
+        };
+        toml::to_string(&file).expect("inventory should always serialize")
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn terminals(&self) -> &[Terminal] {
+        &self.terminals
+    }
+
+    /// The terminal items of one kind, like all the camera bodies.
+    pub fn terminals_of(&self, kind: TerminalKind) -> impl Iterator<Item = &Terminal> {
+        self.terminals.iter().filter(move |t| t.kind == kind)
+    }
+
+    /// The terminal item with this name, ignoring case.
+    pub fn terminal(&self, name: &str) -> Option<&Terminal> {
+        self.terminals.iter().find(|t| t.name.eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Where in a chain threads may appear, by canonical name, for `SearchOptions::roles`. Threads
+    /// that aren't listed can be anywhere.
+    pub fn roles(&self) -> &BTreeMap<String, ThreadRole> {
+        &self.roles
+    }
+
+    /// The threads this file says mate with their own gender, by canonical name, for
+    /// `SearchOptions::hermaphroditic`.
+    pub fn hermaphroditic(&self) -> &BTreeSet<String> {
+        &self.hermaphroditic
+    }
+
+    /// Default search options, with what this file says about its threads filled in: their roles,
+    /// and which are hermaphroditic.
+    pub fn search_options(&self) -> SearchOptions {
+        SearchOptions {
+            roles: self.roles.iter()
+                .map(|(thread, role)| (self.registry.canonical(thread), *role))
+                .collect(),
+            hermaphroditic: self.hermaphroditic.iter()
+                .map(|thread| self.registry.canonical(thread))
+                .collect(),
+            ..SearchOptions::default()
+        }
+    }
+
+    pub fn entries_mut(&mut self) -> &mut [Entry] {
+        &mut self.entries
+    }
+
+    /// Everything available for building chains, which doesn't include anything lent out.
+    pub fn equipment(&self) -> Vec<Adapter> {
+        self.select(&Selection::default())
+    }
+
+    /// Everything, including things that are lent out.
+    pub fn all_equipment(&self) -> Vec<Adapter> {
+        self.select(&Selection { include_loaned: true, ..Selection::default() })
+    }
+
+    /// The equipment a selection picks out, with one adapter per copy owned.
+    pub fn select(&self, selection: &Selection) -> Vec<Adapter> {
+        self.entries.iter()
+            .filter(|e| selection.include_loaned || e.adapter.loaned_to().is_none())
+            .filter(|e| {
+                let in_any = |kits: &[String]| {
+                    e.adapter.kit().is_some_and(|kit| kits.iter().any(|k| k == kit))
+                };
+                (selection.kits.is_empty() || in_any(&selection.kits))
+                    && !in_any(&selection.without_kits)
+            })
+            .flat_map(|e| std::iter::repeat_n(e.adapter.clone(), e.quantity as usize))
+            .collect()
+    }
+
+    /// Totals for each category, by its name. Entries without a category are counted under
+    /// "uncategorized".
+    pub fn stats_by_category(&self) -> BTreeMap<String, CategoryStats> {
+        let mut stats = BTreeMap::<_, CategoryStats>::new();
+        for entry in &self.entries {
+            let category = entry.adapter.category()
+                .map_or_else(|| "uncategorized".to_owned(), |c| c.to_string());
+            let totals = stats.entry(category).or_default();
+            totals.entries += 1;
+            totals.count += entry.quantity;
+            if entry.adapter.loaned_to().is_some() {
+                totals.loaned += entry.quantity;
+            }
+            let info = entry.adapter.info();
+            totals.total_price += info.price.unwrap_or(0.) * f64::from(entry.quantity);
+            totals.total_length += info.length.unwrap_or(0.) * f64::from(entry.quantity);
+        }
+        stats
+    }
+
+    /// What everything is worth, going by each entry's `price` times its quantity. Entries without
+    /// a price can take one from the first priced entry with the same threads (either way round)
+    /// in a `replacement` catalog, which is just another inventory; those are marked as such.
+    pub fn valuation(&self, replacement: Option<&Inventory>) -> Valuation {
+        let catalog_price = |adapter: &Adapter| {
+            replacement?.entries.iter()
+                .filter(|e| e.adapter == *adapter)
+                .find_map(|e| e.adapter.info().price)
+        };
+        let mut items = self.entries.iter()
+            .map(|entry| {
+                let (price, source) = match entry.adapter.info().price {
+                    Some(price) => (Some(price), PriceSource::Recorded),
+                    None => match catalog_price(&entry.adapter) {
+                        Some(price) => (Some(price), PriceSource::Replacement),
+                        None => (None, PriceSource::Unpriced),
+                    },
+                };
+                ValuedItem {
+                    item: entry.adapter.to_string(),
+                    category: entry.adapter.category()
+                        .map_or_else(|| "uncategorized".to_owned(), |c| c.to_string()),
+                    quantity: entry.quantity,
+                    price,
+                    value: price.unwrap_or(0.) * f64::from(entry.quantity),
+                    source,
+                }
+            })
+            .collect::<Vec<_>>();
+        items.sort_by(|a, b| b.value.total_cmp(&a.value));
+        let mut by_category = BTreeMap::new();
+        for item in items.iter().filter(|item| item.price.is_some()) {
+            *by_category.entry(item.category.clone()).or_default() += item.value;
+        }
+        Valuation {
+            total: items.iter().map(|item| item.value).sum(),
+            by_category,
+            unpriced: items.iter()
+                .filter(|item| item.source == PriceSource::Unpriced)
+                .map(|item| item.quantity)
+                .sum(),
+            items,
+        }
+    }
+
+    /// The names of all the kits, and what's in each.
+    pub fn kits(&self) -> BTreeMap<&str, Vec<&Entry>> {
+        let mut kits = BTreeMap::<_, Vec<_>>::new();
+        for entry in &self.entries {
+            if let Some(kit) = entry.adapter.kit() {
+                kits.entry(kit).or_default().push(entry);
+            }
+        }
+        kits
+    }
+
+    /// Indexes of the entries an item description refers to: either an entry's name (ignoring
+    /// case), or its threads like `EF(M) -> 58(F)` (in either order).
+    pub fn find(&self, item: &str) -> Vec<usize> {
+        let threads = item.split_once("->")
+            .and_then(|(a, b)| Some((a.parse::<Thread>().ok()?, b.parse::<Thread>().ok()?)))
+            .map(|(a, b)| {
+                Adapter::new(self.registry.canonical_thread(a), self.registry.canonical_thread(b))
+            });
+        self.entries.iter()
+            .enumerate()
+            .filter(|(_, e)| {
+                e.adapter.name().eq_ignore_ascii_case(item.trim())
+                    || threads.as_ref().is_some_and(|t| *t == e.adapter)
+            })
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    /// Names of all the threads on all the entries and terminal items.
+    pub fn thread_names(&self) -> BTreeSet<&'static str> {
+        self.entries.iter()
+            .flat_map(|e| [e.adapter.0.name(), e.adapter.1.name()])
+            .chain(self.terminals.iter().map(|t| t.thread.name()))
+            .collect()
+    }
+
+    /// Rename a thread everywhere it appears, keeping the gender of each end. Both names are
+    /// normalized first, and aliases that pointed at the old name are repointed at the new one.
+    /// Adapters that weren't given a category have theirs worked out again from the new threads.
+    /// Returns how many adapter ends and terminal items were changed.
+    pub fn rename_thread(&mut self, from: &str, to: &str) -> usize {
+        let from = self.registry.canonical(from);
+        let to = self.registry.canonical(to);
+        if from == to {
+            return 0;
+        }
+        let mut count = 0;
+        for entry in &mut self.entries {
+            let before = count;
+            for end in [&mut entry.adapter.0, &mut entry.adapter.1] {
+                if end.name() == from {
+                    *end = end.renamed(to);
+                    count += 1;
+                }
+            }
+            let given = entry.adapter.category().is_some() && !entry.inferred_category;
+            if count != before && !given {
+                let inferred = registry::infer_category(entry.adapter.0, entry.adapter.1);
+                entry.adapter = entry.adapter.clone().with_category(inferred);
+                entry.inferred_category = inferred.is_some();
+            }
+        }
+        for terminal in &mut self.terminals {
+            if terminal.thread.name() == from {
+                terminal.thread = terminal.thread.renamed(to);
+                count += 1;
+            }
+        }
+        self.registry.repoint_aliases(from, to);
+        if let Some(role) = self.roles.remove(from) {
+            self.roles.entry(to.to_owned()).or_insert(role);
+        }
+        if self.hermaphroditic.remove(from) {
+            self.hermaphroditic.insert(to.to_owned());
+        }
+        count
+    }
+
+    /// Groups of entries that are interchangeable for chaining purposes: the same pair of threads,
+    /// in either direction. Only groups with more than one physical item (counting quantities) are
+    /// returned, in order of first appearance.
+    pub fn equivalence_classes(&self) -> Vec<Vec<&Entry>> {
+        let mut classes: Vec<Vec<&Entry>> = vec![];
+        for entry in &self.entries {
+            match classes.iter_mut().find(|class| class[0].adapter == entry.adapter) {
+                Some(class) => class.push(entry),
+                None => classes.push(vec![entry]),
+            }
+        }
+        classes.retain(|class| class.iter().map(|e| e.quantity).sum::<u32>() > 1);
+        classes
+    }
+
+    /// Sort entries into canonical order and merge exact duplicates into one entry with their
+    /// quantities added up. Terminal items are sorted by kind, then name.
+    pub fn normalize(&mut self) {
+        self.terminals.sort_by(|a, b| (a.kind, &a.name).cmp(&(b.kind, &b.name)));
+        self.entries.sort_by(Entry::canonical_cmp);
+        let mut merged: Vec<Entry> = Vec::with_capacity(self.entries.len());
+        for entry in self.entries.drain(..) {
+            match merged.last_mut() {
+                Some(last) if last.same_item(&entry) => last.quantity += entry.quantity,
+                _ => merged.push(entry),
+            }
+        }
+        self.entries = merged;
+    }
+
+    /// Add everything from another inventory. Entries count as the same if only their quantities
+    /// differ, and terminal items if they're identical; `policy` says what to do with those. An
+    /// alias or thread role both inventories define differently keeps this inventory's meaning,
+    /// except with `MergePolicy::Error`. The other inventory's warnings are dropped, since their
+    /// indices wouldn't mean anything here.
+    pub fn merge(&mut self, other: Inventory, policy: MergePolicy) -> Result<(), MergeError> {
+        if policy == MergePolicy::Error {
+            if let Some(idx) = other.entries.iter()
+                .position(|e| self.entries.iter().any(|mine| mine.same_item(e)))
+            {
+                return Err(MergeError::Entry(idx));
+            }
+            if let Some(idx) = other.terminals.iter().position(|t| self.terminals.contains(t)) {
+                return Err(MergeError::Terminal(idx));
+            }
+            let aliases = self.registry.aliases();
+            if let Some((alias, _)) = other.registry.aliases().iter()
+                .find(|(alias, canonical)| aliases.get(*alias).is_some_and(|c| c != *canonical))
+            {
+                return Err(MergeError::Alias(alias.clone()));
+            }
+            if let Some((thread, _)) = other.roles.iter()
+                .find(|(thread, role)| self.roles.get(*thread).is_some_and(|r| r != *role))
+            {
+                return Err(MergeError::Role(thread.clone()));
+            }
+        }
+        for entry in other.entries {
+            match self.entries.iter_mut().find(|mine| mine.same_item(&entry)) {
+                Some(mine) if policy == MergePolicy::KeepBoth => mine.quantity += entry.quantity,
+                Some(_) => (),
+                None => self.entries.push(entry),
+            }
+        }
+        for terminal in other.terminals {
+            if policy == MergePolicy::KeepBoth || !self.terminals.contains(&terminal) {
+                self.terminals.push(terminal);
+            }
+        }
+        for (alias, canonical) in other.registry.aliases() {
+            if !self.registry.aliases().contains_key(alias) {
+                self.registry.add_alias(alias.clone(), canonical.clone());
+            }
+        }
+        for (thread, role) in other.roles {
+            self.roles.entry(thread).or_insert(role);
+        }
+        self.hermaphroditic.extend(other.hermaphroditic);
+        Ok(())
+    }
+}
+
+/// Adds each adapter as an entry of its own, or as one more of an entry that's the same item.
+/// Thread names are normalized through the inventory's registry, and adapters without a category
+/// get one worked out from their threads, as if they'd been loaded from its file.
+impl Extend<Adapter> for Inventory {
+    fn extend<I: IntoIterator<Item = Adapter>>(&mut self, iter: I) {
+        for mut adapter in iter {
+            adapter.0 = self.registry.canonical_thread(adapter.0);
+            adapter.1 = self.registry.canonical_thread(adapter.1);
+            let mut entry = Entry::new(adapter);
+            if entry.adapter.category().is_none() {
+                let inferred = registry::infer_category(entry.adapter.0, entry.adapter.1);
+                entry.adapter = entry.adapter.with_category(inferred);
+                entry.inferred_category = inferred.is_some();
+            }
+            match self.entries.iter_mut().find(|mine| mine.same_item(&entry)) {
+                Some(mine) => mine.quantity += 1,
+                None => self.entries.push(entry),
+            }
+        }
+    }
+}
+
+/// An inventory with just these adapters (see the `Extend` impl), and no aliases, roles, or
+/// terminal items.
+impl FromIterator<Adapter> for Inventory {
+    fn from_iter<I: IntoIterator<Item = Adapter>>(iter: I) -> Self {
+        let mut inventory = Self::default();
+        inventory.extend(iter);
+        inventory
+    }
+}
+
+/// Load an inventory file, in the format its extension says, or TOML if it doesn't say.
+pub fn load_equipment(
+    path: impl AsRef<Path>,
+    options: &LoadOptions,
+) -> Result<Inventory, LoadError> {
+    let path = path.as_ref();
+    let format = Format::from_path(path).unwrap_or(Format::Toml);
+    Inventory::from_reader(fs::File::open(path)?, format, options)
+}
+
+/// A wants file: the connections someone would like to be able to make, one `[[want]]` table
+/// each, with `from` and `to` given as threads or the names of terminal items.
+#[derive(Debug, Deserialize)]
+struct WantsFile {
+    #[serde(default)]
+    want: Vec<FileWant>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FileWant {
+    from: String,
+    to: String,
+}
+
+/// Load a wants file (TOML, see `WantsFile`), reading any terminal item names and normalizing
+/// thread names with the inventory the wants are for.
+pub fn load_wants(
+    path: impl AsRef<Path>,
+    inventory: &Inventory,
+) -> Result<Vec<(Thread, Thread)>, LoadError> {
+    let file: WantsFile = toml::from_str(&fs::read_to_string(path)?)?;
+    let end = |s: &str| -> Result<Thread, LoadError> {
+        match inventory.terminal(s) {
+            Some(terminal) => Ok(terminal.thread),
+            None => Ok(inventory.registry.canonical_thread(s.parse()?)),
+        }
+    };
+    file.want.iter()
+        .map(|want| Ok((end(&want.from)?, end(&want.to)?)))
+        .collect()
+}
+
+/// Load a CSV inventory file, whatever its extension.
+pub fn load_equipment_csv(
+    path: impl AsRef<Path>,
+    options: &LoadOptions,
+) -> Result<Inventory, LoadError> {
+    Inventory::from_reader(fs::File::open(path)?, Format::Csv, options)
+}
+
+/// An item description from a `collides_with` list, with its threads (if it's given by threads)
+/// normalized through the registry like the entries' own.
+fn canonical_item(item: &str, registry: &Registry) -> String {
+    let threads = item.split_once("->")
+        .and_then(|(a, b)| Some((a.parse::<Thread>().ok()?, b.parse::<Thread>().ok()?)));
+    match threads {
+        Some((a, b)) => {
+            format!("{} -> {}", registry.canonical_thread(a), registry.canonical_thread(b))
+        }
+        None => item.trim().to_owned(),
+    }
+}
+
+/// Parse one end of an inventory entry. `end` is 0 for the camera-facing end, 1 for the other.
+fn parse_thread(
+    s: &str,
+    end: usize,
+    entry: usize,
+    registry: &Registry,
+    options: &LoadOptions,
+) -> Result<Thread, LoadError> {
+    let s = s.trim();
+    if s.ends_with(')') {
+        return Ok(registry.canonical_thread(s.parse()?));
+    }
+    match infer_gender(registry.canonical(s), end) {
+        Some(thread) if options.infer_gender => Ok(thread),
+        _ => Err(LoadError::AmbiguousGender { entry, thread: s.to_owned() }),
+    }
+}
+
+fn infer_gender(name: &str, end: usize) -> Option<Thread> {
+    let name = KNOWN_MOUNTS.iter().find(|mount| **mount == name)?;
+    Some(if end == 0 { Thread::male(name) } else { Thread::female(name) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(toml: &str, options: &LoadOptions) -> Inventory {
+        Inventory::from_toml_str(toml, options).unwrap()
+    }
+
+    #[test]
+    fn genderless_mount_on_camera_end_is_male() {
+        assert_eq!(infer_gender("EF", 0), Some(Thread::male("EF")));
+        assert_eq!(infer_gender("EF", 1), Some(Thread::female("EF")));
+        assert_eq!(infer_gender("58", 0), None);
+
+        let registry = Registry::new();
+        let options = LoadOptions { infer_gender: true, ..LoadOptions::default() };
+        let thread = parse_thread("EF", 0, 0, &registry, &options).unwrap();
+        assert_eq!(thread, Thread::male("EF"));
+
+        let inventory = load("[[adapter]]\nthreads = [\"EF\", \"58(F)\"]\n", &options);
+        assert_eq!(inventory.equipment(), [Adapter::new(Thread::male("EF"), Thread::female("58"))]);
+    }
+
+    #[test]
+    fn genderless_mount_needs_inference_turned_on() {
+        let registry = Registry::new();
+        let error = parse_thread("EF", 0, 3, &registry, &LoadOptions::default()).unwrap_err();
+        assert!(matches!(
+            error,
+            LoadError::AmbiguousGender { entry: 3, ref thread } if thread == "EF"
+        ));
+
+        let options = LoadOptions { infer_gender: true, ..LoadOptions::default() };
+        assert!(parse_thread("58", 0, 0, &registry, &options).is_err());
+    }
+
+    #[test]
+    fn errors_display_and_chain_their_source() {
+        let io = LoadError::from(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+        assert_eq!(io.to_string(), "I/O error: no such file");
+        assert_eq!(io.source().unwrap().to_string(), "no such file");
+
+        let thread = LoadError::from("58".parse::<Thread>().unwrap_err());
+        let message = "invalid thread \"58\": expected a name followed by (M) or (F)";
+        assert_eq!(thread.to_string(), message);
+        let source = thread.source().unwrap().downcast_ref::<ParseThreadError>().unwrap();
+        assert_eq!(source, &ParseThreadError("58".to_owned()));
+
+        let ambiguous = LoadError::AmbiguousGender { entry: 0, thread: "58".to_owned() };
+        assert_eq!(ambiguous.to_string(),
+            "entry 1: thread \"58\" needs a gender, like \"58(M)\" or \"58(F)\"");
+        assert!(ambiguous.source().is_none());
+    }
+
+    #[test]
+    fn same_inventory_from_every_format() {
+        let toml = r#"
+            [[adapter]]
+            name = "Rodagon"
+            threads = ["LTM(M)", "40.5(F)"]
+            category = "lens"
+            quantity = 2
+            notes = "stiff"
+            kit = "darkroom"
+            price = 80.0
+
+            [[adapter]]
+            threads = ["EF(M)", "58(F)"]
+        "#;
+        let json = r#"{
+            "adapter": [
+                {
+                    "name": "Rodagon",
+                    "threads": ["LTM(M)", "40.5(F)"],
+                    "category": "lens",
+                    "quantity": 2,
+                    "notes": "stiff",
+                    "kit": "darkroom",
+                    "price": 80.0
+                },
+                { "threads": ["EF(M)", "58(F)"] }
+            ]
+        }"#;
+        let csv = "name,from,to,category,quantity,notes,kit,price\n\
+            Rodagon,LTM(M),40.5(F),lens,2,stiff,darkroom,80.0\n\
+            ,EF(M),58(F),,,,,\n";
+        let options = LoadOptions::default();
+        let from_toml = Inventory::from_reader(toml.as_bytes(), Format::Toml, &options).unwrap();
+        let from_json = Inventory::from_reader(json.as_bytes(), Format::Json, &options).unwrap();
+        let from_csv = Inventory::from_reader(csv.as_bytes(), Format::Csv, &options).unwrap();
+        assert_eq!(from_toml.entries().len(), 2);
+        assert_eq!(from_json, from_toml);
+        assert_eq!(from_csv, from_toml);
+    }
+
+    #[test]
+    fn reversed_pair_warns_or_merges() {
+        let toml = r#"
+            [[adapter]]
+            threads = ["52(M)", "58(F)"]
+
+            [[adapter]]
+            threads = ["EF(M)", "58(F)"]
+
+            [[adapter]]
+            threads = ["58(F)", "52(M)"]
+            quantity = 2
+        "#;
+        let inventory = load(toml, &LoadOptions::default());
+        assert_eq!(inventory.entries().len(), 3);
+        assert_eq!(inventory.warnings(), [
+            LoadWarning::ReversedDuplicate { first: 0, duplicate: 2, merged: false },
+        ]);
+        assert_eq!(inventory.warnings()[0].to_string(),
+            "entry 3 is entry 1 reversed; is it listed twice?");
+
+        let options = LoadOptions { dedup_reversed: true, ..LoadOptions::default() };
+        let inventory = load(toml, &options);
+        assert_eq!(inventory.entries().len(), 2);
+        assert_eq!(inventory.entries()[0].quantity, 3);
+        assert_eq!(inventory.warnings(), [
+            LoadWarning::ReversedDuplicate { first: 0, duplicate: 2, merged: true },
+        ]);
+    }
+
+    #[test]
+    fn stats_group_by_category() {
+        let toml = r#"
+            [[adapter]]
+            threads = ["58(M)", "52(F)"]
+            category = "step_down"
+            quantity = 2
+            price = 4.5
+            length = 3.0
+
+            [[adapter]]
+            threads = ["77(M)", "58(F)"]
+            category = "step_down"
+            loaned_to = "Sam"
+            length = 4.0
+
+            [[adapter]]
+            threads = ["LTM(M)", "40.5(F)"]
+            category = "lens"
+            price = 80.0
+
+            [[adapter]]
+            threads = ["EF(M)", "M42(F)"]
+        "#;
+        let stats = load(toml, &LoadOptions::default()).stats_by_category();
+        let step_downs = CategoryStats {
+            entries: 2,
+            count: 3,
+            loaned: 1,
+            total_price: 9.,
+            total_length: 10.,
+        };
+        assert_eq!(stats["step-down ring"], step_downs);
+        let lens = CategoryStats { entries: 1, count: 1, total_price: 80., ..Default::default() };
+        assert_eq!(stats["lens"], lens);
+        assert_eq!(stats.len(), 3);
+    }
+
+    #[test]
+    fn merge_policies_on_overlapping_inventories() {
+        let mine = r#"
+            [[adapter]]
+            threads = ["58(M)", "52(F)"]
+
+            [[terminal]]
+            name = "Canon 5D"
+            thread = "EF(F)"
+            kind = "body"
+        "#;
+        let theirs = r#"
+            [[adapter]]
+            threads = ["52(M)", "77(F)"]
+
+            [[adapter]]
+            threads = ["58(M)", "52(F)"]
+            quantity = 2
+
+            [[terminal]]
+            name = "Canon 5D"
+            thread = "EF(F)"
+            kind = "body"
+        "#;
+        let options = LoadOptions::default();
+        let merged = |policy| {
+            let mut inventory = load(mine, &options);
+            let result = inventory.merge(load(theirs, &options), policy);
+            (inventory, result)
+        };
+        let quantities = |inventory: &Inventory| {
+            inventory.entries().iter().map(|e| e.quantity).collect::<Vec<_>>()
+        };
+
+        let (both, result) = merged(MergePolicy::KeepBoth);
+        assert_eq!(result, Ok(()));
+        assert_eq!(quantities(&both), [3, 1]);
+        assert_eq!(both.terminals().len(), 2);
+
+        let (first, result) = merged(MergePolicy::KeepFirst);
+        assert_eq!(result, Ok(()));
+        assert_eq!(quantities(&first), [1, 1]);
+        assert_eq!(first.terminals().len(), 1);
+
+        let (unchanged, result) = merged(MergePolicy::Error);
+        assert_eq!(result, Err(MergeError::Entry(1)));
+        assert_eq!(quantities(&unchanged), [1]);
+        assert_eq!(unchanged.terminals().len(), 1);
+    }
+
+    #[test]
+    fn chains_still_work_through_a_renamed_thread() {
+        // What was typed as a 42 mm filter thread is really an M42 mount.
+        let toml = r#"
+            [[adapter]]
+            threads = ["EF(M)", "42(F)"]
+
+            [[adapter]]
+            threads = ["42(M)", "52(F)"]
+
+            [[adapter]]
+            threads = ["42(M)", "58(F)"]
+            category = "step_up"
+        "#;
+        let mut inventory = load(toml, &LoadOptions::default());
+        let category = |inventory: &Inventory, i: usize| inventory.entries()[i].adapter.category();
+        assert_eq!(category(&inventory, 1), Some(Category::StepUp));
+
+        assert_eq!(inventory.rename_thread("42", "M42"), 3);
+        let equipment = inventory.equipment();
+        let chains = |end| crate::make_chain(Thread::female("EF"), end, &equipment);
+        let to_52 = chains(Thread::male("52"));
+        assert_eq!(to_52.len(), 1);
+        assert_eq!(to_52[0].junctions().collect::<Vec<_>>(), [Thread::female("M42")]);
+        assert_eq!(chains(Thread::male("58")).len(), 1);
+
+        // The category worked out from the old threads is worked out again, but the one given in
+        // the file stays.
+        assert_eq!(category(&inventory, 1), Some(Category::MountAdapter));
+        assert!(inventory.entries()[1].inferred_category);
+        assert_eq!(category(&inventory, 2), Some(Category::StepUp));
+        assert!(!inventory.entries()[2].inferred_category);
+
+        assert_eq!(inventory.rename_thread("M42", "42"), 3);
+        assert_eq!(category(&inventory, 1), Some(Category::StepUp));
+    }
+
+    #[test]
+    fn hermaphroditic_threads_only_reach_searches_through_search_options() {
+        let toml = r#"
+            hermaphroditic = ["PF25"]
+
+            [[adapter]]
+            threads = ["EF(M)", "PF25(M)"]
+
+            [[adapter]]
+            threads = ["PF25(M)", "52(F)"]
+        "#;
+        let mut inventory = load(toml, &LoadOptions::default());
+        let equipment = inventory.equipment();
+        let chains = |options: &SearchOptions| {
+            crate::make_chain_with_options(Thread::female("EF"), Thread::male("52"), &equipment,
+                options).0
+        };
+        assert_eq!(chains(&inventory.search_options()).len(), 1);
+        // Loading the file doesn't change what mates for anything else.
+        assert!(chains(&SearchOptions::default()).is_empty());
+        assert!(!Thread::male("PF25").mates(Thread::male("PF25")));
+
+        assert_eq!(inventory.rename_thread("PF25", "PF26"), 2);
+        assert_eq!(inventory.hermaphroditic().iter().collect::<Vec<_>>(), ["PF26"]);
+        assert_eq!(inventory.search_options().hermaphroditic, ["PF26"]);
+    }
+
+    #[test]
+    fn extended_inventory_is_queried_as_one() {
+        let mut inventory = load(
+            "[[adapter]]\nthreads = [\"EF(M)\", \"58(F)\"]\n",
+            &LoadOptions::default(),
+        );
+        let (male, female) = (Thread::male, Thread::female);
+        inventory.extend([
+            Adapter::new(male("58mm"), female("52")),
+            Adapter::new(male("52"), female("77")),
+            Adapter::new(male("EF"), female("58")),
+        ]);
+        // Names are normalized, and another of something already there is one more of it.
+        let quantities = inventory.entries().iter().map(|e| e.quantity).collect::<Vec<_>>();
+        assert_eq!(quantities, [2, 1, 1]);
+        assert_eq!(inventory.entries()[1].adapter.threads(), (male("58"), female("52")));
+
+        let equipment = inventory.equipment();
+        assert_eq!(equipment.len(), 4);
+        let chains = crate::make_chain(female("EF"), male("77"), &equipment);
+        assert_eq!(chains.len(), 1);
+        assert_eq!(chains[0].adapters().len(), 3);
+
+        let collected: Inventory = equipment.into_iter().collect();
+        assert_eq!(collected.entries().len(), 3);
+        assert!(collected.terminals().is_empty());
+    }
+}
@@ -0,0 +1,175 @@
+//! A history of changes made to an inventory file, kept next to it as JSON lines.
+//!
+//! Each entry records what was done, when, and the whole file as it was beforehand, plus a hash
+//! of the file as it was left afterwards, so a change can be undone later as long as nobody has
+//! edited the file since.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// UTC, like `2024-01-31T18:05:00Z`, so entries sort and filter as plain strings.
+    pub timestamp: String,
+    /// The command that made the change, like `loan`.
+    pub command: String,
+    /// What it did, for people.
+    pub summary: String,
+    /// The inventory file's contents before the change.
+    pub before: String,
+    /// [`content_hash`] of the inventory file's contents after the change.
+    pub after_hash: String,
+    /// Set once the change has been undone.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub undone: bool,
+}
+
+/// Where the journal for an inventory file lives: `gear.toml` gets `gear.journal.jsonl`.
+pub fn journal_path(inventory: &Path) -> PathBuf {
+    inventory.with_extension("journal.jsonl")
+}
+
+/// Read a journal, oldest entry first. A missing journal is an empty one, and lines that can't
+/// be read are skipped, with a warning for each.
+pub fn read(path: &Path) -> io::Result<(Vec<JournalEntry>, Vec<String>)> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Default::default()),
+        Err(e) => return Err(e),
+    };
+    let mut entries = vec![];
+    let mut warnings = vec![];
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => {
+                warnings.push(format!("line {}: unreadable journal entry skipped: {}", i + 1, e));
+            }
+        }
+    }
+    Ok((entries, warnings))
+}
+
+pub fn append(path: &Path, entry: &JournalEntry) -> io::Result<()> {
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    OpenOptions::new().create(true).append(true).open(path)?.write_all(line.as_bytes())
+}
+
+/// Replace a whole journal.
+pub fn write(path: &Path, entries: &[JournalEntry]) -> io::Result<()> {
+    let mut text = String::new();
+    for entry in entries {
+        text += &serde_json::to_string(entry)?;
+        text.push('\n');
+    }
+    write_atomically(path, &text)
+}
+
+/// The entries that can be undone, given the inventory file's current contents: indexes, most
+/// recent first, of changes not already undone.
+///
+/// This stops at a change whose result doesn't match what came after it (or the current file),
+/// since that means the file was edited by something else in between, and undoing the change
+/// would throw that edit away. The flag says whether that's why it stopped.
+pub fn undo_stack(entries: &[JournalEntry], current: &str) -> (Vec<usize>, bool) {
+    let mut stack = vec![];
+    let mut expected = content_hash(current);
+    for (i, entry) in entries.iter().enumerate().rev().filter(|(_, e)| !e.undone) {
+        if entry.after_hash != expected {
+            return (stack, true);
+        }
+        stack.push(i);
+        expected = content_hash(&entry.before);
+    }
+    (stack, false)
+}
+
+/// Replace a file's contents in one step, so it's never seen half-written.
+pub fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)
+}
+
+/// A hash of a file's contents for noticing when it's been changed. 64-bit FNV-1a, which (unlike
+/// std's hashers) is guaranteed to stay the same between builds.
+pub fn content_hash(contents: &str) -> String {
+    let hash = contents.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// A time as UTC `YYYY-MM-DDTHH:MM:SSZ`.
+pub fn timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // Days since the epoch to a civil date; see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, secs / 3600, secs / 60 % 60,
+        secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(before: &str, after: &str) -> JournalEntry {
+        JournalEntry {
+            timestamp: "2024-01-31T18:05:00Z".to_owned(),
+            command: "loan".to_owned(),
+            summary: String::new(),
+            before: before.to_owned(),
+            after_hash: content_hash(after),
+            undone: false,
+        }
+    }
+
+    #[test]
+    fn undo_stack_is_most_recent_first() {
+        let entries = [entry("a", "b"), entry("b", "c")];
+        assert_eq!(undo_stack(&entries, "c"), (vec![1, 0], false));
+    }
+
+    #[test]
+    fn undo_stack_skips_undone_changes() {
+        let mut entries = [entry("a", "b"), entry("b", "c")];
+        entries[1].undone = true;
+        assert_eq!(undo_stack(&entries, "b"), (vec![0], false));
+    }
+
+    #[test]
+    fn undo_stack_stops_at_an_outside_edit() {
+        // Edited between the two changes:
+        let entries = [entry("a", "b"), entry("b2", "c")];
+        assert_eq!(undo_stack(&entries, "c"), (vec![1], true));
+        // Edited since the last change:
+        assert_eq!(undo_stack(&entries, "c2"), (vec![], true));
+    }
+
+    #[test]
+    fn timestamps_are_utc() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(1_706_724_300);
+        assert_eq!(timestamp(time), "2024-01-31T18:05:00Z");
+    }
+}
@@ -0,0 +1,5055 @@
+use std::{
+    borrow::Cow,
+    cmp::{Ordering, PartialEq, Reverse},
+    collections::{BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque},
+    error::Error,
+    fmt::{self, Display, Formatter},
+    hash::{Hash, Hasher},
+    io::{self, Write},
+    str::FromStr,
+    sync::{Arc, OnceLock, RwLock},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod inventory;
+pub mod journal;
+pub mod registry;
+pub mod scad;
+#[cfg(feature = "proptest")]
+pub mod strategy;
+
+/// One side of a joint: a named thread or mount, male or female. Left-hand threads have "LH"
+/// after their size, like `58LH(M)`; since that's part of the name, they only mate with other
+/// left-hand threads.
+///
+/// A clamp that grips a range of sizes has a range for its name, like `49-58(F)`. It mates with
+/// any size in the range, but not with another range (see `Thread::mates`).
+///
+/// Some connectors, like push-fit couplings and lugged bayonets, are hermaphroditic: male goes
+/// into male as well as female. Searches are told which those are with
+/// `SearchOptions::hermaphroditic`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash)]
+pub enum Thread {
+    M(ThreadSym),
+    F(ThreadSym),
+}
+
+pub const NIL_THREAD: Thread = Thread::M(ThreadSym::NIL);
+
+/// A thread name, as a small number standing in for the string. Comparing and hashing these is
+/// cheap, which matters in the middle of a search; the names are kept in a table on the side.
+///
+/// They order by name, not number, so sorted output doesn't depend on what got interned first.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ThreadSym(u32);
+
+impl ThreadSym {
+    /// `NIL_THREAD`'s name.
+    const NIL: Self = Self(0);
+
+    pub fn new(name: &str) -> Self {
+        Self(interner().write().unwrap().intern(name).0)
+    }
+
+    pub fn name(self) -> &'static str {
+        interner().read().unwrap().names[self.0 as usize]
+    }
+}
+
+impl fmt::Debug for ThreadSym {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.name(), f)
+    }
+}
+
+impl PartialOrd for ThreadSym {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ThreadSym {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self == other {
+            return Ordering::Equal;
+        }
+        self.name().cmp(other.name())
+    }
+}
+
+/// Every thread name seen, each stored once for the life of the program.
+#[derive(Default)]
+struct Interner {
+    names: Vec<&'static str>,
+    ids: HashMap<&'static str, u32>,
+}
+
+impl Interner {
+    fn intern(&mut self, name: &str) -> (u32, &'static str) {
+        if let Some((&interned, &id)) = self.ids.get_key_value(name) {
+            return (id, interned);
+        }
+        let interned: &'static str = Box::leak(name.to_owned().into_boxed_str());
+        let id = u32::try_from(self.names.len()).expect("too many thread names");
+        self.names.push(interned);
+        self.ids.insert(interned, id);
+        (id, interned)
+    }
+}
+
+fn interner() -> &'static RwLock<Interner> {
+    static INTERNER: OnceLock<RwLock<Interner>> = OnceLock::new();
+    INTERNER.get_or_init(|| {
+        let mut interner = Interner::default();
+        interner.intern("nil");
+        RwLock::new(interner)
+    })
+}
+
+impl Thread {
+    /// A male thread with the given name, like `Thread::male("58")` for `58(M)`.
+    pub fn male(name: &str) -> Self {
+        Self::M(ThreadSym::new(name))
+    }
+
+    pub fn female(name: &str) -> Self {
+        Self::F(ThreadSym::new(name))
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Self::M(x) => Self::F(x),
+            Self::F(x) => Self::M(x),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        self.sym().name()
+    }
+
+    pub fn sym(self) -> ThreadSym {
+        match self {
+            Self::M(x) | Self::F(x) => x,
+        }
+    }
+
+    pub fn is_male(self) -> bool {
+        matches!(self, Self::M(_))
+    }
+
+    /// The nominal diameter in mm, for threads named by their size (filter threads, mostly).
+    /// Left-hand threads have the same diameter as right-hand ones.
+    pub fn diameter(self) -> Option<f64> {
+        self.nominal_name().parse().ok()
+    }
+
+    /// The smallest and largest sizes a clamp grips, in mm, for threads named by a range like
+    /// `49-58`.
+    pub fn range(self) -> Option<(f64, f64)> {
+        let (low, high) = self.name().split_once('-')?;
+        let (low, high) = (low.parse::<f64>().ok()?, high.parse::<f64>().ok()?);
+        (low <= high).then_some((low, high))
+    }
+
+    /// Whether the two go together: opposite genders, and either the same thread or a range and
+    /// a size inside it. Two ranges don't go together, even the same one, since neither clamp
+    /// has a thread for the other to grip. Hermaphroditic threads going with themselves are up to
+    /// the search (see `SearchOptions::mates`).
+    pub fn mates(self, other: Thread) -> bool {
+        self.is_male() != other.is_male()
+            && ((self.sym() == other.sym() && self.range().is_none())
+                || self.grips(other)
+                || other.grips(self))
+    }
+
+    /// Whether this is a range that `other`'s size is inside.
+    fn grips(self, other: Thread) -> bool {
+        match (self.range(), other.diameter()) {
+            (Some((low, high)), Some(size)) => low <= size && size <= high,
+            _ => false,
+        }
+    }
+
+    pub fn is_left_handed(self) -> bool {
+        self.nominal_name() != self.name()
+    }
+
+    /// The name without any left-hand mark, so `58LH` and `58` are both `58`.
+    pub fn nominal_name(self) -> &'static str {
+        let name = self.name();
+        name.strip_suffix("LH").filter(|base| !base.is_empty()).unwrap_or(name)
+    }
+
+    /// Same gender, different name.
+    pub fn renamed(self, name: &str) -> Self {
+        let name = ThreadSym::new(name);
+        match self {
+            Self::M(_) => Self::M(name),
+            Self::F(_) => Self::F(name),
+        }
+    }
+
+    /// Display the thread in some style other than the default `58(M)`.
+    pub fn display_with(self, style: ThreadStyle) -> StyledThread {
+        StyledThread { thread: self, style }
+    }
+}
+
+/// Ways of writing a thread's gender.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ThreadStyle {
+    /// `58(M)`, same as `Display`. This is the only one `FromStr` understands.
+    #[default]
+    Suffix,
+    /// `M:58`
+    Prefix,
+    /// `58♂`
+    Symbol,
+}
+
+/// A thread plus the style to display it in; see [`Thread::display_with`].
+#[derive(Debug, Clone, Copy)]
+pub struct StyledThread {
+    thread: Thread,
+    style: ThreadStyle,
+}
+
+impl Display for StyledThread {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let name = self.thread.name();
+        let male = self.thread.is_male();
+        match self.style {
+            ThreadStyle::Suffix => write!(f, "{}", self.thread),
+            ThreadStyle::Prefix => write!(f, "{}:{}", if male { 'M' } else { 'F' }, name),
+            ThreadStyle::Symbol => write!(f, "{}{}", name, if male { '♂' } else { '♀' }),
+        }
+    }
+}
+
+impl Display for Thread {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::M(x) => {
+                f.write_str(x.name())?;
+                f.write_str("(M)")
+            }
+            Self::F(x) => {
+                f.write_str(x.name())?;
+                f.write_str("(F)")
+            }
+        }
+    }
+}
+
+/// Error returned when a thread spec like `58(F)` can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseThreadError(pub String);
+
+impl Display for ParseThreadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid thread {:?}: expected a name followed by (M) or (F)", self.0)
+    }
+}
+
+impl Error for ParseThreadError {}
+
+impl FromStr for Thread {
+    type Err = ParseThreadError;
+
+    /// Parses the same form `Display` produces: a name followed by `(M)` or `(F)`. The gender
+    /// letter can be lowercase too.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let err = || ParseThreadError(s.to_owned());
+        let (name, gender) = s.strip_suffix(')')
+            .and_then(|s| s.rsplit_once('('))
+            .ok_or_else(err)?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(err());
+        }
+        match gender {
+            "M" | "m" => Ok(Self::male(name)),
+            "F" | "f" => Ok(Self::female(name)),
+            _ => Err(err()),
+        }
+    }
+}
+
+/// Threads are written the same way as `Display` shows them, like `58(F)`.
+impl Serialize for Thread {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Thread {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Thread names get leaked, once per distinct name, so they can be borrowed for the life of the
+/// program.
+pub(crate) fn intern(name: &str) -> &'static str {
+    interner().write().unwrap().intern(name).1
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    MountAdapter,
+    GenderChanger,
+    StepUp,
+    StepDown,
+    /// Has glass in it.
+    Lens,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TerminalKind {
+    Body,
+    /// Body caps and lens caps.
+    Cap,
+    Filter,
+}
+
+impl Display for TerminalKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Body => "body",
+            Self::Cap => "cap",
+            Self::Filter => "filter",
+        })
+    }
+}
+
+/// Where in a chain a thread may appear (see `SearchOptions::roles`). Chains run from the camera
+/// end to the far end.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThreadRole {
+    /// Only at the start, like a camera body's mount: nothing stacks behind it.
+    Body,
+    /// Only at the end, like the target a chain is being built to reach.
+    Terminal,
+    /// Only at junctions between two adapters, never at either end.
+    Intermediate,
+    #[default]
+    Any,
+}
+
+impl Display for ThreadRole {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Body => "body",
+            Self::Terminal => "terminal",
+            Self::Intermediate => "intermediate",
+            Self::Any => "any",
+        })
+    }
+}
+
+/// Equipment with only one thread, like a camera body, a cap, or a filter. Nothing can go on the
+/// other side of it, so it can only be at one end of a chain.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Terminal {
+    pub name: String,
+    /// The thread as it faces whatever it connects to: a body's mount is female.
+    pub thread: Thread,
+    pub kind: TerminalKind,
+}
+
+impl Terminal {
+    pub fn new(name: impl Into<String>, thread: Thread, kind: TerminalKind) -> Self {
+        Self { name: name.into(), thread, kind }
+    }
+}
+
+impl Display for Terminal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.thread)
+    }
+}
+
+/// Details about a piece of equipment that don't affect what it connects to. Shared between
+/// copies of an adapter, since they get cloned a lot during searches.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Info {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<Category>,
+    /// Free-form notes, like "grub screw stripped - hand tighten only". Empty if none.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+    /// Who it's lent out to, if anyone.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loaned_to: Option<String>,
+    /// When it was lent out, as YYYY-MM-DD.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loaned_on: Option<String>,
+    /// The kit it's kept in (like "travel pouch"), if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kit: Option<String>,
+    /// Whether it can be used the other way round from how it's listed, if that's been said
+    /// explicitly (see `Adapter::is_reversible`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reversible: Option<bool>,
+    /// Items it can't go directly against, because they'd foul each other even though the threads
+    /// fit, by name or by threads like "52(M) -> 58(F)" (see `Adapter::collides_with`).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub collides_with: Vec<String>,
+    /// How wide it is on the outside, in mm, if that's been measured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outer_diameter: Option<f64>,
+    /// If its female thread is down in a recess, the widest thing that can reach it, in mm.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recess_diameter: Option<f64>,
+    /// How far its male thread sticks out, in mm, if that's been measured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub male_length: Option<f64>,
+    /// How deep its female thread goes, in mm, if that's been measured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub female_depth: Option<f64>,
+    /// What it costs to use, in whatever unit the inventory uses throughout (see `optimal_chain`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+    /// How much it adds to the length of a stack, in mm, if that's been measured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub length: Option<f64>,
+    /// What one is worth, in whatever currency the inventory uses throughout.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price: Option<f64>,
+}
+
+/// How two sides of a thread join (see `registry::coupling`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Coupling {
+    Screw,
+    Bayonet,
+}
+
+impl Display for Coupling {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Screw => "screw",
+            Self::Bayonet => "bayonet",
+        })
+    }
+}
+
+impl Display for Category {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::MountAdapter => "mount adapter",
+            Self::GenderChanger => "gender changer",
+            Self::StepUp => "step-up ring",
+            Self::StepDown => "step-down ring",
+            Self::Lens => "lens",
+        })
+    }
+}
+
+/// The last field is whether the adapter has been turned around from how it was listed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "SavedAdapter", into = "SavedAdapter")]
+pub struct Adapter(Thread, Thread, Cow<'static, str>, Arc<Info>, bool);
+
+/// How an adapter is serialized: as it was listed, plus whether it's been turned around since.
+#[derive(Serialize, Deserialize)]
+struct SavedAdapter {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    name: String,
+    threads: [Thread; 2],
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    reversed: bool,
+    #[serde(flatten)]
+    info: Info,
+}
+
+impl From<Adapter> for SavedAdapter {
+    fn from(adapter: Adapter) -> Self {
+        let reversed = adapter.is_reversed();
+        let adapter = if reversed { adapter.reverse() } else { adapter };
+        Self {
+            name: adapter.2.into_owned(),
+            threads: [adapter.0, adapter.1],
+            reversed,
+            info: Arc::unwrap_or_clone(adapter.3),
+        }
+    }
+}
+
+impl From<SavedAdapter> for Adapter {
+    fn from(saved: SavedAdapter) -> Self {
+        let [a, b] = saved.threads;
+        let adapter = Self(a, b, Cow::Owned(saved.name), Arc::new(saved.info), false);
+        if saved.reversed { adapter.reverse() } else { adapter }
+    }
+}
+
+impl Adapter {
+    pub fn new(a: Thread, b: Thread) -> Self {
+        Self(a, b, Cow::Borrowed(""), Arc::default(), false)
+    }
+
+    /// A step ring from one filter size to another, in mm: male `from` on the camera side, female
+    /// `to` on the other. It's named like "46->52 step-up", and categorized to match.
+    pub fn step_ring(from: impl Into<f64>, to: impl Into<f64>) -> Result<Self, InvalidAdapter> {
+        let (from, to) = (filter_size(from.into())?, filter_size(to.into())?);
+        if from == to {
+            return Err(InvalidAdapter::SameSize(from));
+        }
+        let (a, b) = (Thread::male(&from.to_string()), Thread::female(&to.to_string()));
+        let category = registry::infer_category(a, b);
+        let kind = if category == Some(Category::StepUp) { "step-up" } else { "step-down" };
+        Ok(Self::new(a, b)
+            .with_name(format!("{}->{} {}", from, to, kind))
+            .with_category(category))
+    }
+
+    /// A mount adapter that goes onto a `body` mount and takes a `lens` mount, both from
+    /// `registry::KNOWN_MOUNTS`. It's named like "M42 to EF adapter".
+    pub fn mount(body: &str, lens: &str) -> Result<Self, InvalidAdapter> {
+        for mount in [body, lens] {
+            if !registry::KNOWN_MOUNTS.contains(&mount) {
+                return Err(InvalidAdapter::NotAMount(mount.to_owned()));
+            }
+        }
+        if body == lens {
+            return Err(InvalidAdapter::SameMount(body.to_owned()));
+        }
+        Ok(Self::new(Thread::male(body), Thread::female(lens))
+            .with_name(format!("{} to {} adapter", lens, body))
+            .with_category(Some(Category::MountAdapter)))
+    }
+
+    /// A gender changer for a filter size, in mm, with both ends `gender`. It's named like "58
+    /// double male".
+    pub fn gender_changer(size: impl Into<f64>, gender: Gender) -> Result<Self, InvalidAdapter> {
+        let size = filter_size(size.into())?;
+        let thread = gender.thread(&size.to_string());
+        Ok(Self::new(thread, thread)
+            .with_name(format!("{} double {}", size, gender))
+            .with_category(registry::infer_category(thread, thread)))
+    }
+
+    /// The camera-facing end, then the other end.
+    pub fn threads(&self) -> (Thread, Thread) {
+        (self.0, self.1)
+    }
+
+    /// What each end goes onto, in the same order as `threads`: `58(F)` for a `58(M)` end.
+    pub fn mating_ends(&self) -> (Thread, Thread) {
+        (self.0.opposite(), self.1.opposite())
+    }
+
+    pub fn name(&self) -> &str {
+        &self.2
+    }
+
+    pub fn with_name(self, name: impl Into<Cow<'static, str>>) -> Self {
+        Self(self.0, self.1, name.into(), self.3, self.4)
+    }
+
+    pub fn info(&self) -> &Info {
+        &self.3
+    }
+
+    pub fn category(&self) -> Option<Category> {
+        self.3.category
+    }
+
+    pub fn with_category(mut self, category: Option<Category>) -> Self {
+        Arc::make_mut(&mut self.3).category = category;
+        self
+    }
+
+    /// If this is a step ring, which way it steps going from the first end to the second: `Less`
+    /// for up to a bigger size, `Greater` for down.
+    pub fn step(&self) -> Option<Ordering> {
+        if self.0.is_male() == self.1.is_male() {
+            // Gender changer, not a step ring.
+            return None;
+        }
+        match self.0.diameter()?.partial_cmp(&self.1.diameter()?)? {
+            Ordering::Equal => None,
+            direction => Some(direction),
+        }
+    }
+
+    pub fn notes(&self) -> &str {
+        &self.3.notes
+    }
+
+    pub fn with_notes(mut self, notes: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.3).notes = notes.into();
+        self
+    }
+
+    /// Who has it, if it's lent out.
+    pub fn loaned_to(&self) -> Option<&str> {
+        self.3.loaned_to.as_deref()
+    }
+
+    pub fn with_loan(mut self, to: Option<String>, on: Option<String>) -> Self {
+        let info = Arc::make_mut(&mut self.3);
+        info.loaned_to = to;
+        info.loaned_on = on;
+        self
+    }
+
+    /// The kit it's kept in, if any.
+    pub fn kit(&self) -> Option<&str> {
+        self.3.kit.as_deref()
+    }
+
+    pub fn with_kit(mut self, kit: Option<String>) -> Self {
+        Arc::make_mut(&mut self.3).kit = kit;
+        self
+    }
+
+    /// Whether it goes between a left-hand and a right-hand thread of the same size. Those are
+    /// rarely made, so suggestions leave them out unless asked.
+    pub fn mixes_handedness(&self) -> bool {
+        self.0.nominal_name() == self.1.nominal_name()
+            && self.0.is_left_handed() != self.1.is_left_handed()
+    }
+
+    /// Whether a search may use it the other way round from how it was listed. Unless it's been
+    /// said otherwise, that's anything without a bayonet on it: a mount adapter is made to go
+    /// one way.
+    pub fn is_reversible(&self) -> bool {
+        self.3.reversible.unwrap_or_else(|| {
+            ![self.0, self.1].into_iter().any(|t| registry::coupling(t) == Coupling::Bayonet)
+        })
+    }
+
+    pub fn with_reversible(mut self, reversible: Option<bool>) -> Self {
+        Arc::make_mut(&mut self.3).reversible = reversible;
+        self
+    }
+
+    pub fn with_collisions(mut self, items: Vec<String>) -> Self {
+        Arc::make_mut(&mut self.3).collides_with = items;
+        self
+    }
+
+    pub fn with_outer_diameter(mut self, diameter: Option<f64>) -> Self {
+        Arc::make_mut(&mut self.3).outer_diameter = diameter;
+        self
+    }
+
+    pub fn with_recess_diameter(mut self, diameter: Option<f64>) -> Self {
+        Arc::make_mut(&mut self.3).recess_diameter = diameter;
+        self
+    }
+
+    pub fn with_male_length(mut self, length: Option<f64>) -> Self {
+        Arc::make_mut(&mut self.3).male_length = length;
+        self
+    }
+
+    pub fn with_female_depth(mut self, depth: Option<f64>) -> Self {
+        Arc::make_mut(&mut self.3).female_depth = depth;
+        self
+    }
+
+    pub fn with_cost(mut self, cost: Option<f64>) -> Self {
+        Arc::make_mut(&mut self.3).cost = cost;
+        self
+    }
+
+    pub fn with_length(mut self, length: Option<f64>) -> Self {
+        Arc::make_mut(&mut self.3).length = length;
+        self
+    }
+
+    pub fn with_price(mut self, price: Option<f64>) -> Self {
+        Arc::make_mut(&mut self.3).price = price;
+        self
+    }
+
+    /// Whether the two mustn't go directly against each other, according to either one's
+    /// `Info::collides_with`. Which way round either of them is doesn't matter.
+    pub fn collides_with(&self, other: &Adapter) -> bool {
+        self.3.collides_with.iter().any(|item| other.answers_to(item))
+            || other.3.collides_with.iter().any(|item| self.answers_to(item))
+    }
+
+    /// Whether an item description, a name or threads like "52(M) -> 58(F)", means this adapter.
+    pub(crate) fn answers_to(&self, item: &str) -> bool {
+        let item = item.trim();
+        let name = self.listed_name();
+        (!name.is_empty() && name.eq_ignore_ascii_case(item))
+            || item.split_once("->").is_some_and(|(a, b)| match (a.parse(), b.parse()) {
+                (Ok(a), Ok(b)) => Adapter::new(a, b) == *self,
+                _ => false,
+            })
+    }
+
+    /// Whether it's been turned around from how it was listed.
+    pub fn is_reversed(&self) -> bool {
+        self.4
+    }
+
+    /// Whether there's glass in it.
+    pub fn is_optical(&self) -> bool {
+        self.3.category == Some(Category::Lens)
+    }
+
+    pub fn reverse(mut self) -> Self {
+        self.flip_in_place();
+        self
+    }
+
+    /// Like [`reverse`](Self::reverse), for an adapter that's sitting in a `Vec` or the like.
+    pub fn flip_in_place(&mut self) {
+        std::mem::swap(&mut self.0, &mut self.1);
+        if let Some(s) = self.2.strip_suffix(" (reversed)") {
+            self.2 = Cow::Owned(s.to_owned());
+        } else if !self.2.is_empty() {
+            self.2 = Cow::Owned(format!("{} (reversed)", self.2));
+        }
+        self.4 = !self.4;
+    }
+}
+
+impl Hash for Adapter {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // To allow it to be reversed freely without changing its hash value. Any fixed order of the
+        // ends will do, and comparing symbol numbers is cheaper than comparing names:
+        let key = |t: Thread| (t.sym().0, t.is_male());
+        if key(self.0) < key(self.1) {
+            self.0.hash(state);
+            self.1.hash(state);
+        } else {
+            self.1.hash(state);
+            self.0.hash(state);
+        }
+    }
+}
+
+impl PartialEq for Adapter {
+    fn eq(&self, other: &Self) -> bool {
+        // Matches the same adapter reversed:
+        (self.0 == other.0 && self.1 == other.1)
+            || (self.0 == other.1 && self.1 == other.0)
+    }
+}
+
+impl Eq for Adapter {}
+
+impl Adapter {
+    /// This adapter turned whichever way around lets it go onto an exposed thread, if either does.
+    /// It's only turned around if it's reversible.
+    fn oriented_after(&self, exposed: Thread, options: &SearchOptions) -> Option<Self> {
+        if options.mates(exposed, self.0) {
+            Some(self.clone())
+        } else if options.mates(exposed, self.1) && self.is_reversible() {
+            Some(self.clone().reverse())
+        } else {
+            None
+        }
+    }
+
+    /// The same piece of equipment as another, or a copy of it, whichever way round either is.
+    fn is_same_item(&self, other: &Self) -> bool {
+        self == other && self.listed_name() == other.listed_name()
+    }
+
+    /// The name it was listed with, without the mark for being reversed.
+    fn listed_name(&self) -> &str {
+        match self.2.strip_suffix(" (reversed)") {
+            Some(name) if self.4 => name,
+            _ => &self.2,
+        }
+    }
+
+    /// The threads in a fixed order regardless of which way around the adapter is.
+    fn sorted_threads(&self) -> (Thread, Thread) {
+        if self.0 < self.1 {
+            (self.0, self.1)
+        } else {
+            (self.1, self.0)
+        }
+    }
+}
+
+impl PartialOrd for Adapter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Adapter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Consistent with equality: the same adapter reversed compares equal.
+        self.sorted_threads().cmp(&other.sorted_threads())
+    }
+}
+
+impl Display for Adapter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if !self.2.is_empty() {
+            f.write_str(&self.2)?;
+            if self.0 != NIL_THREAD && self.1 != NIL_THREAD {
+                return Ok(());
+            } else {
+                f.write_str(": ")?;
+            }
+        }
+        if self.0 != NIL_THREAD {
+            self.0.fmt(f)?;
+            if self.1 != NIL_THREAD {
+                f.write_str(" -> ")?;
+            }
+        }
+        if self.1 != NIL_THREAD {
+            self.1.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Which half of a joint a thread is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Gender {
+    Male,
+    Female,
+}
+
+impl Gender {
+    /// A thread with this gender.
+    pub fn thread(self, name: &str) -> Thread {
+        match self {
+            Self::Male => Thread::male(name),
+            Self::Female => Thread::female(name),
+        }
+    }
+}
+
+impl Display for Gender {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Male => "male",
+            Self::Female => "female",
+        })
+    }
+}
+
+/// Why a constructor like `Adapter::step_ring` wouldn't make the adapter asked for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InvalidAdapter {
+    /// Not a size filter threads come in (see `registry::is_filter_size`).
+    NotAFilterSize(f64),
+    /// A step ring from a size to itself.
+    SameSize(f64),
+    /// Not in `registry::KNOWN_MOUNTS`.
+    NotAMount(String),
+    /// A mount adapter from a mount to itself.
+    SameMount(String),
+}
+
+impl Display for InvalidAdapter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAFilterSize(size) => write!(f, "{} mm isn't a filter thread size", size),
+            Self::SameSize(size) => {
+                write!(f, "a step ring from {} to {} doesn't step; that's a gender changer, if \
+                    anything", size, size)
+            }
+            Self::NotAMount(name) => write!(f, "{:?} isn't a known lens mount", name),
+            Self::SameMount(name) => {
+                write!(f, "a mount adapter from {} to {} doesn't adapt", name, name)
+            }
+        }
+    }
+}
+
+impl Error for InvalidAdapter {}
+
+fn filter_size(size: f64) -> Result<f64, InvalidAdapter> {
+    if registry::is_filter_size(size) {
+        Ok(size)
+    } else {
+        Err(InvalidAdapter::NotAFilterSize(size))
+    }
+}
+
+/// Error returned when an adapter spec like `EF(M) -> 58(F)` can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseAdapterError(pub String);
+
+impl Display for ParseAdapterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid adapter {:?}: expected two threads like \"EF(M) -> 58(F)\"", self.0)
+    }
+}
+
+impl Error for ParseAdapterError {}
+
+impl FromStr for Adapter {
+    type Err = ParseAdapterError;
+
+    /// Parses the form `Display` produces for an adapter with no name: its two threads, camera end
+    /// first, with `->` between them.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseAdapterError(s.trim().to_owned());
+        let (a, b) = s.split_once("->").ok_or_else(err)?;
+        Ok(Self::new(a.parse().map_err(|_| err())?, b.parse().map_err(|_| err())?))
+    }
+}
+
+impl Adapter {
+    /// For `equipment!`: parse a spec, or panic saying what's wrong with it, from where the macro
+    /// was used.
+    #[doc(hidden)]
+    #[track_caller]
+    pub fn from_spec(spec: &str) -> Self {
+        match spec.parse() {
+            Ok(adapter) => adapter,
+            Err(e) => panic!("equipment!: {}", e),
+        }
+    }
+}
+
+/// A list of adapters, written the way they're displayed, each with an optional name and
+/// category:
+///
+/// ```
+/// let equipment = adapter_party::equipment![
+///     "EF(M) -> 58(F)",
+///     "58(M) -> 52(F)" @ StepDown,
+///     "LTM(M) -> 40.5(F)" as "Rodagon 50/2.8" @ Lens,
+/// ];
+/// ```
+///
+/// This makes a `Vec<Adapter>`. The specs are parsed when it runs, and one that doesn't parse is a
+/// panic that points at the macro.
+#[macro_export]
+macro_rules! equipment {
+    ($($spec:literal $(as $name:literal)? $(@ $category:ident)?),* $(,)?) => {
+        vec![$(
+            $crate::Adapter::from_spec($spec)
+                $(.with_name($name))?
+                $(.with_category(Some($crate::Category::$category)))?
+        ),*]
+    };
+}
+
+/// Chains serialize as their real adapters in order, each saying whether it's been turned around,
+/// plus the start and end they were found between.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "SavedChain", into = "SavedChain")]
+pub struct Chain(Vec<Adapter>);
+
+#[derive(Serialize, Deserialize)]
+struct SavedChain {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    start: Option<SavedEnd>,
+    adapters: Vec<Adapter>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    end: Option<SavedEnd>,
+}
+
+/// The start or end placeholder of a chain.
+#[derive(Serialize, Deserialize)]
+struct SavedEnd {
+    name: String,
+    thread: Thread,
+}
+
+impl From<Chain> for SavedChain {
+    fn from(chain: Chain) -> Self {
+        let mut pieces = chain.0;
+        let end = pieces.pop_if(|a| a.1 == NIL_THREAD)
+            .map(|a| SavedEnd { name: a.2.into_owned(), thread: a.0 });
+        let start = (pieces.first().is_some_and(|a| a.0 == NIL_THREAD))
+            .then(|| pieces.remove(0))
+            .map(|a| SavedEnd { name: a.2.into_owned(), thread: a.1 });
+        Self { start, adapters: pieces, end }
+    }
+}
+
+impl From<SavedChain> for Chain {
+    fn from(saved: SavedChain) -> Self {
+        let start = saved.start.map(|s| Adapter::new(NIL_THREAD, s.thread).with_name(s.name));
+        let end = saved.end.map(|e| Adapter::new(e.thread, NIL_THREAD).with_name(e.name));
+        Self(start.into_iter().chain(saved.adapters).chain(end).collect())
+    }
+}
+
+impl Chain {
+    pub fn new(adapter: Adapter) -> Self {
+        Self(vec![adapter])
+    }
+
+    /// The chain with another piece on the end, turned around if need be, or `None` if it doesn't
+    /// fit or would foul the piece it goes onto (see `Adapter::collides_with`).
+    pub fn add(&self, next: Adapter) -> Option<Self> {
+        self.add_with_options(next, &SearchOptions::default())
+    }
+
+    /// `add`, going by what `options` says mates (see `SearchOptions::mates`).
+    pub fn add_with_options(&self, next: Adapter, options: &SearchOptions) -> Option<Self> {
+        let next = next.oriented_after(self.0.last().unwrap().1, options)?;
+        if self.fouls(&next) {
+            return None;
+        }
+        let mut new = self.clone();
+        new.0.push(next);
+        Some(new)
+    }
+
+    /// The same chain seen from the other end: the last adapter first, each turned around, with
+    /// the start and end swapped.
+    pub fn reverse(mut self) -> Self {
+        self.0.reverse();
+        for adapter in &mut self.0 {
+            if adapter.0 == NIL_THREAD || adapter.1 == NIL_THREAD {
+                // A placeholder only changes which end it's at; it isn't a reversed item.
+                std::mem::swap(&mut adapter.0, &mut adapter.1);
+            } else {
+                adapter.flip_in_place();
+            }
+        }
+        self
+    }
+}
+
+impl Chain {
+    /// Whether `next` would foul the last piece. The start placeholder doesn't collide with
+    /// anything.
+    fn fouls(&self, next: &Adapter) -> bool {
+        let last = self.0.last().unwrap();
+        last.0 != NIL_THREAD && last.collides_with(next)
+    }
+
+    /// Whether each piece goes onto the one before it, with the start and end placeholders (if
+    /// any) only at the ends.
+    pub fn is_valid(&self) -> bool {
+        let placeholders_at_ends = self.0.iter().enumerate().all(|(i, a)| {
+            (a.0 != NIL_THREAD || i == 0) && (a.1 != NIL_THREAD || i == self.0.len() - 1)
+        });
+        placeholders_at_ends && self.0.windows(2).all(|pair| pair[0].1.mates(pair[1].0))
+    }
+
+    /// The real adapters in the chain, without the start and end placeholders.
+    pub fn adapters(&self) -> &[Adapter] {
+        let mut adapters = &self.0[..];
+        if let Some((first, rest)) = adapters.split_first() {
+            if first.0 == NIL_THREAD {
+                adapters = rest;
+            }
+        }
+        if let Some((last, rest)) = adapters.split_last() {
+            if last.1 == NIL_THREAD {
+                adapters = rest;
+            }
+        }
+        adapters
+    }
+
+    pub fn metrics(&self) -> ChainMetrics {
+        let adapters = self.adapters();
+        ChainMetrics {
+            adapters: adapters.len(),
+            reversed: adapters.iter().filter(|a| a.is_reversed()).count(),
+            optical: adapters.iter().filter(|a| a.is_optical()).count(),
+            step_ups: adapters.iter().filter(|a| a.step() == Some(Ordering::Less)).count(),
+            step_downs: adapters.iter().filter(|a| a.step() == Some(Ordering::Greater)).count(),
+            interferences: self.interferences().len(),
+            seating_problems: self.seating_problems().len(),
+            min_diameter: self.junctions()
+                .enumerate()
+                .filter_map(|(i, t)| Some((i + 1, registry::clear_diameter(t)?)))
+                // The first of equally narrow junctions.
+                .reduce(|min, next| if next.1 < min.1 { next } else { min }),
+        }
+    }
+
+    /// Junctions where the male piece is wider on the outside than the recess around the female
+    /// thread it goes into, so it likely can't screw all the way in. Only pieces with both
+    /// measurements given are checked.
+    pub fn interferences(&self) -> Vec<Interference> {
+        self.0.windows(2)
+            .filter(|pair| pair[0].0 != NIL_THREAD && pair[1].1 != NIL_THREAD)
+            .enumerate()
+            .filter_map(|(i, pair)| interference(&pair[0], &pair[1], i + 1))
+            .collect()
+    }
+
+    /// Junctions where the male thread is longer than the female one is deep, so it'll bottom out
+    /// before the two seat. Only junctions with both measurements given are checked.
+    pub fn seating_problems(&self) -> Vec<SeatingProblem> {
+        self.0.windows(2)
+            .filter(|pair| pair[0].0 != NIL_THREAD && pair[1].1 != NIL_THREAD)
+            .enumerate()
+            .filter_map(|(i, pair)| seating_problem(&pair[0], &pair[1], i + 1))
+            .collect()
+    }
+
+    /// A single number for how good a chain is, lower being better, used by `best_chain`: each
+    /// adapter costs 1, plus another 0.5 for each step-down (as in `default_cmp`).
+    pub fn score(&self) -> f64 {
+        let metrics = self.metrics();
+        metrics.adapters as f64 + 0.5 * metrics.step_downs as f64
+    }
+
+    /// The default order for results: fewest adapters first, then, since stepping down a filter
+    /// thread risks vignetting, whichever has more step-ups than step-downs.
+    pub fn default_cmp(&self, other: &Self) -> Ordering {
+        let key = |m: ChainMetrics| (m.adapters, m.step_downs as isize - m.step_ups as isize);
+        key(self.metrics()).cmp(&key(other.metrics()))
+    }
+
+    /// Widest narrowest point first, for when vignetting is the main worry. Chains with no known
+    /// diameters at their junctions go last.
+    pub fn min_diameter_cmp(&self, other: &Self) -> Ordering {
+        let key = |chain: &Self| chain.metrics().min_diameter.map(|(_, d)| d);
+        match (key(self), key(other)) {
+            (Some(a), Some(b)) => b.total_cmp(&a),
+            (a, b) => b.is_some().cmp(&a.is_some()),
+        }
+    }
+
+    /// Sort by the given keys, falling back to `default_cmp` for ties.
+    pub fn cmp_by(&self, other: &Self, keys: &[SortSpec]) -> Ordering {
+        self.metrics().cmp_by(&other.metrics(), keys).then_with(|| self.default_cmp(other))
+    }
+
+    /// The same chain, shown as starting at a terminal item instead of a bare thread. The item's
+    /// thread should be the one the chain was searched from.
+    pub fn starting_at(mut self, terminal: &Terminal) -> Self {
+        if let Some(start) = self.0.first_mut().filter(|a| a.0 == NIL_THREAD) {
+            *start = Adapter::new(NIL_THREAD, terminal.thread).with_name(terminal.name.clone());
+        }
+        self
+    }
+
+    /// The same chain, shown as ending at a terminal item instead of a bare thread.
+    pub fn ending_at(mut self, terminal: &Terminal) -> Self {
+        if let Some(end) = self.0.last_mut().filter(|a| a.1 == NIL_THREAD) {
+            *end = Adapter::new(terminal.thread, NIL_THREAD).with_name(terminal.name.clone());
+        }
+        self
+    }
+
+    /// The chain split in two at the first junction between real adapters (see `junctions`) where
+    /// either side is `thread`: everything up to it, ending on what the rest goes onto, and the
+    /// rest, starting from what it goes onto. Each half is a chain in its own right, so a
+    /// sub-assembly can be shown on its own. `None` if `thread` isn't at any junction.
+    pub fn split_at_thread(&self, thread: Thread) -> Option<(Chain, Chain)> {
+        let i = self.0.windows(2).position(|pair| {
+            pair[0].0 != NIL_THREAD && pair[1].1 != NIL_THREAD
+                && (pair[0].1 == thread || pair[1].0 == thread)
+        })?;
+        let (exposed, onto) = (self.0[i].1, self.0[i + 1].0);
+        let mut first = Chain(self.0[..=i].to_vec());
+        first.0.push(Adapter::new(onto, NIL_THREAD).with_name("end"));
+        let mut rest = Chain::new(Adapter::new(NIL_THREAD, exposed).with_name("start"));
+        rest.0.extend_from_slice(&self.0[i + 1..]);
+        Some((first, rest))
+    }
+
+    /// The adapters in the order to put them on, building out from the start of the chain, which
+    /// is normally the camera body.
+    pub fn assembly_order(&self) -> Vec<&Adapter> {
+        self.adapters().iter().collect()
+    }
+
+    /// The adapters in the order to put them on when building from the end of the chain instead,
+    /// which can be easier when the fittings at that end are tight.
+    pub fn assembly_order_reversed(&self) -> Vec<&Adapter> {
+        self.adapters().iter().rev().collect()
+    }
+
+    /// Numbered steps for putting the chain together, one per junction from the start, each
+    /// saying which side goes into which. Adapters that go on the other way round from how they
+    /// were listed get a warning, since that's easy to get wrong.
+    pub fn instructions(&self) -> String {
+        // How to refer to one side of a piece: adapters by their listed threads (or name), the
+        // start and end by their terminal's name if they have one.
+        let describe = |a: &Adapter| {
+            let name = a.2.strip_suffix(" (reversed)").unwrap_or(&a.2);
+            if !name.is_empty() {
+                return name.to_owned();
+            }
+            let (x, y) = if a.is_reversed() { (a.1, a.0) } else { (a.0, a.1) };
+            match a.category() {
+                Some(category) => format!("{} -> {} {}", x, y, category),
+                None => format!("{} -> {} adapter", x, y),
+            }
+        };
+        let side = |a: &Adapter, thread: Thread| match (a.0 == NIL_THREAD, a.1 == NIL_THREAD) {
+            (true, _) if a.name() == "start" => format!("the {} you're starting from", thread),
+            (_, true) if a.name() == "end" => format!("the {} you're ending at", thread),
+            _ => format!("the {} side of the {}", thread, describe(a)),
+        };
+        let start = match self.0.first() {
+            Some(a) if a.0 == NIL_THREAD && a.name() != "start" => format!("the {}", a.name()),
+            _ => "the start".to_owned(),
+        };
+
+        let mut out = String::new();
+        for (i, pair) in self.0.windows(2).enumerate() {
+            let (prev, next) = (&pair[0], &pair[1]);
+            let (exposed, attached) = (prev.1, next.0);
+            let (male, female) = if exposed.is_male() {
+                (side(prev, exposed), side(next, attached))
+            } else {
+                (side(next, attached), side(prev, exposed))
+            };
+            let verb = if exposed.diameter().is_some() { "Screw" } else { "Fit" };
+            out += &format!("{}. {} {} into {}.", i + 1, verb, male, female);
+            if next.is_reversed() && next.1 != NIL_THREAD {
+                out += &format!(" The {} goes on backwards, with its {} side toward {}.",
+                    describe(next), attached, start);
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// A multi-line description of the chain, one adapter per line with which way around it goes
+    /// and which thread is left exposed after it, followed by the metrics.
+    pub fn detailed(&self) -> String {
+        let mut out = String::new();
+        if let Some(start) = self.0.first().filter(|a| a.0 == NIL_THREAD) {
+            match start.name() {
+                "start" => out += &format!("start: {}\n", start.1),
+                name => out += &format!("start: {}, {}\n", name, start.1),
+            }
+        }
+        for (i, a) in self.adapters().iter().enumerate() {
+            out += &format!("  {}. ", i + 1);
+            if !a.2.is_empty() {
+                out += &format!("{}: ", a.2);
+            }
+            out += &format!("{} -> {}", a.0, a.1);
+            if a.is_reversed() {
+                out += ", reversed";
+            }
+            if let Some(category) = a.category() {
+                out += &format!(", {}", category);
+            }
+            out += &format!("; exposes {}\n", a.1);
+            if !a.notes().is_empty() {
+                out += &format!("     note: {}\n", a.notes());
+            }
+            if let Some(who) = a.loaned_to() {
+                out += &format!("     currently with {}\n", who);
+            }
+            if let Some(kit) = a.kit() {
+                out += &format!("     in the {} kit\n", kit);
+            }
+        }
+        if let Some(end) = self.0.last().filter(|a| a.1 == NIL_THREAD) {
+            match end.name() {
+                "end" => out += &format!("end: {}\n", end.0),
+                name => out += &format!("end: {}, {}\n", name, end.0),
+            }
+        }
+        for grip in self.grips() {
+            out += &format!("{}\n", grip);
+        }
+        if self.uses_both_orientations() {
+            out += "note: uses the same item both ways round\n";
+        }
+        for interference in self.interferences() {
+            out += &format!("warning: {}\n", interference);
+        }
+        for problem in self.seating_problems() {
+            out += &format!("warning: {}\n", problem);
+        }
+        out += &self.metrics().to_string();
+        out
+    }
+
+    /// Whether the chain is just mechanical couplings, with no lenses in it.
+    pub fn is_purely_mechanical(&self) -> bool {
+        !self.adapters().iter().any(Adapter::is_optical)
+    }
+
+    /// Every thread along the chain, from the start: both sides of each junction, plus the start
+    /// and end threads themselves.
+    pub fn thread_path(&self) -> Vec<Thread> {
+        self.0.iter()
+            .flat_map(|a| [a.0, a.1])
+            .filter(|&t| t != NIL_THREAD)
+            .collect()
+    }
+
+    /// The threads where one real adapter meets the next, in order from the start. Each is given
+    /// as exposed by the earlier adapter, except that where a clamp grips a thread, it's the size
+    /// gripped rather than the clamp's range. The start and end threads aren't included.
+    pub fn junctions(&self) -> impl Iterator<Item = Thread> + '_ {
+        self.0.windows(2)
+            .filter(|pair| pair[0].0 != NIL_THREAD && pair[1].1 != NIL_THREAD)
+            .map(|pair| match pair[0].1.range() {
+                Some(_) => pair[1].0.opposite(),
+                None => pair[0].1,
+            })
+    }
+
+    /// Whether some item is used forwards in one place and reversed in another, which takes two
+    /// copies of it. That's either a mistake in the inventory or a clever trick.
+    pub fn uses_both_orientations(&self) -> bool {
+        let adapters = self.adapters();
+        adapters.iter().any(|a| {
+            a.is_reversed() && adapters.iter().any(|b| !b.is_reversed() && a.is_same_item(b))
+        })
+    }
+
+    /// What each clamp in the chain is gripping, in order from the start (see `Thread::range`).
+    pub fn grips(&self) -> Vec<Grip> {
+        self.0.windows(2)
+            .filter_map(|pair| {
+                let (before, after) = (&pair[0], &pair[1]);
+                let (clamp, gripped) = match (before.1.range(), after.0.range()) {
+                    (Some(_), _) => (before, after.0),
+                    (_, Some(_)) => (after, before.1),
+                    _ => return None,
+                };
+                Some(Grip { clamp: clamp.clone(), size: gripped.diameter()? })
+            })
+            .collect()
+    }
+
+    /// The size of each junction in mm, in order, for threads named by their size (filter threads
+    /// and the like). Mounts and other threads without a numeric name give `None`.
+    pub fn diameter_timeline(&self) -> Vec<Option<f64>> {
+        self.junctions().map(Thread::diameter).collect()
+    }
+}
+
+impl Chain {
+    /// Like the normal display, but with the two sides of each joint shown between the pieces
+    /// they belong to, so the genders can be checked: `[A] 58(F)|58(M) [B]`.
+    pub fn joints_display(&self) -> String {
+        let mut out = String::new();
+        for (i, a) in self.0.iter().enumerate() {
+            if i > 0 {
+                out += &format!("{}|{} ", self.0[i - 1].1, a.0);
+            }
+            out += &format!("[{}] ", a);
+        }
+        out
+    }
+
+    /// Like the normal display, but with each run of consecutive step rings going the same way
+    /// (all up or all down) summarized as one jump: `[46(M) -> 77(F) (via 3 rings)]`. Only pieces
+    /// categorized as step rings, all as the same kind, make a run; a lens or anything else with
+    /// threads like a step ring's breaks it.
+    pub fn collapsed_display(&self) -> String {
+        let ring = |a: &Adapter| match a.category() {
+            Some(kind @ (Category::StepUp | Category::StepDown)) => Some((kind, a.step()?)),
+            _ => None,
+        };
+        let mut out = String::new();
+        let mut i = 0;
+        while i < self.0.len() {
+            let kind = ring(&self.0[i]);
+            let mut run = 1;
+            while kind.is_some() && i + run < self.0.len() && ring(&self.0[i + run]) == kind {
+                run += 1;
+            }
+            if run > 1 {
+                let collapsed = Adapter::new(self.0[i].0, self.0[i + run - 1].1);
+                out += &format!("[{} (via {} rings)] ", collapsed, run);
+            } else {
+                out += &format!("[{}] ", self.0[i]);
+            }
+            i += run;
+        }
+        out
+    }
+}
+
+impl Display for Chain {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for a in &self.0 {
+            write!(f, "[{}] ", a)?;
+        }
+        Ok(())
+    }
+}
+
+/// Numbers describing a chain, for summaries and comparing chains.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChainMetrics {
+    /// Junctions where one piece looks too wide to seat in the other (see `Chain::interferences`).
+    pub interferences: usize,
+    /// Junctions where the male thread will bottom out (see `Chain::seating_problems`).
+    pub seating_problems: usize,
+    /// Real adapters, not counting the start and end.
+    pub adapters: usize,
+    /// Adapters used the other way around from how they were listed.
+    pub reversed: usize,
+    /// Adapters with glass in them.
+    pub optical: usize,
+    /// Step rings going to a bigger size, in the direction of the chain.
+    pub step_ups: usize,
+    /// Step rings going to a smaller size, in the direction of the chain.
+    pub step_downs: usize,
+    /// The narrowest junction with a known diameter (see `registry::clear_diameter`): its number
+    /// counting from 1 at the start of the chain, and the diameter in mm.
+    pub min_diameter: Option<(usize, f64)>,
+}
+
+impl Display for ChainMetrics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} adapters, {} reversed, {} optical, {} step-ups, {} step-downs",
+            self.adapters, self.reversed, self.optical, self.step_ups, self.step_downs)?;
+        if let Some((junction, diameter)) = self.min_diameter {
+            write!(f, ", min clear diameter {} mm at junction {}", diameter, junction)?;
+        }
+        if self.interferences != 0 {
+            write!(f, ", {} fit warning{}", self.interferences,
+                if self.interferences == 1 { "" } else { "s" })?;
+        }
+        if self.seating_problems != 0 {
+            write!(f, ", {} seating warning{}", self.seating_problems,
+                if self.seating_problems == 1 { "" } else { "s" })?;
+        }
+        Ok(())
+    }
+}
+
+/// A clamp and the size it grips in a particular chain (see `Chain::grips`).
+#[derive(Debug, Clone)]
+pub struct Grip {
+    pub clamp: Adapter,
+    /// In mm.
+    pub size: f64,
+}
+
+impl Display for Grip {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] grips {} mm", self.clamp, self.size)
+    }
+}
+
+/// A junction where one piece looks too wide to seat in the other (see `Chain::interferences`).
+#[derive(Debug, Clone)]
+pub struct Interference {
+    /// The junction's number, counting from 1 at the start of the chain.
+    pub junction: usize,
+    /// The piece whose male thread goes in.
+    pub male: Adapter,
+    /// The piece with the recessed female thread.
+    pub female: Adapter,
+    /// The male piece's outer diameter, in mm.
+    pub outer_diameter: f64,
+    /// The female piece's recess diameter, in mm.
+    pub recess_diameter: f64,
+}
+
+impl Display for Interference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] is {} mm across, too wide for the {} mm recess in [{}] (junction {})",
+            self.male, self.outer_diameter, self.recess_diameter, self.female, self.junction)
+    }
+}
+
+/// The interference between two pieces in a row, if there is one. `junction` is the number of the
+/// junction between them.
+fn interference(before: &Adapter, after: &Adapter, junction: usize) -> Option<Interference> {
+    let (male, female) = male_and_female(before, after);
+    let outer_diameter = male.3.outer_diameter?;
+    let recess_diameter = female.3.recess_diameter?;
+    (outer_diameter > recess_diameter).then(|| Interference {
+        junction,
+        male: male.clone(),
+        female: female.clone(),
+        outer_diameter,
+        recess_diameter,
+    })
+}
+
+/// A junction where the male thread is too long for the female one (see
+/// `Chain::seating_problems`).
+#[derive(Debug, Clone)]
+pub struct SeatingProblem {
+    /// The junction's number, counting from 1 at the start of the chain.
+    pub junction: usize,
+    /// The piece whose male thread goes in.
+    pub male: Adapter,
+    /// The piece with the female thread.
+    pub female: Adapter,
+    /// How long the male thread is, in mm.
+    pub male_length: f64,
+    /// How deep the female thread is, in mm.
+    pub female_depth: f64,
+}
+
+impl Display for SeatingProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "junction {} may not seat fully: [{}] has {} mm of thread, but [{}] is only {} \
+            mm deep", self.junction, self.male, self.male_length, self.female, self.female_depth)
+    }
+}
+
+/// The seating problem between two pieces in a row, if there is one. `junction` is the number of
+/// the junction between them.
+fn seating_problem(before: &Adapter, after: &Adapter, junction: usize) -> Option<SeatingProblem> {
+    let (male, female) = male_and_female(before, after);
+    let male_length = male.3.male_length?;
+    let female_depth = female.3.female_depth?;
+    (male_length > female_depth).then(|| SeatingProblem {
+        junction,
+        male: male.clone(),
+        female: female.clone(),
+        male_length,
+        female_depth,
+    })
+}
+
+/// Which of two pieces in a row has the male side of the junction between them, and which the
+/// female.
+fn male_and_female<'a>(before: &'a Adapter, after: &'a Adapter) -> (&'a Adapter, &'a Adapter) {
+    if before.1.is_male() { (before, after) } else { (after, before) }
+}
+
+/// A `ChainMetrics` number to sort chains by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Adapters,
+    Reversed,
+    Optical,
+    StepUps,
+    StepDowns,
+    /// The narrowest junction's clear diameter. Chains without one sort last either way.
+    MinDiameter,
+}
+
+impl SortKey {
+    /// The names `FromStr` accepts, for help text.
+    pub const NAMES: &'static [&'static str] = &[
+        "adapters", "reversed", "optical", "step-ups", "step-downs", "diameter",
+    ];
+
+    fn get(self, metrics: &ChainMetrics) -> Option<f64> {
+        Some(match self {
+            Self::Adapters => metrics.adapters as f64,
+            Self::Reversed => metrics.reversed as f64,
+            Self::Optical => metrics.optical as f64,
+            Self::StepUps => metrics.step_ups as f64,
+            Self::StepDowns => metrics.step_downs as f64,
+            Self::MinDiameter => return metrics.min_diameter.map(|(_, d)| d),
+        })
+    }
+}
+
+/// One key of a sort order, smallest first unless `descending`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortSpec {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseSortKeyError(pub String);
+
+impl Display for ParseSortKeyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown sort key {:?}: expected one of {}", self.0, SortKey::NAMES.join(", "))
+    }
+}
+
+impl Error for ParseSortKeyError {}
+
+impl FromStr for SortSpec {
+    type Err = ParseSortKeyError;
+
+    /// A key name, with a `-` in front for descending order.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, descending) = match s.strip_prefix('-') {
+            Some(name) => (name, true),
+            None => (s, false),
+        };
+        let key = match name {
+            "adapters" => SortKey::Adapters,
+            "reversed" => SortKey::Reversed,
+            "optical" => SortKey::Optical,
+            "step-ups" => SortKey::StepUps,
+            "step-downs" => SortKey::StepDowns,
+            "diameter" => SortKey::MinDiameter,
+            _ => return Err(ParseSortKeyError(s.to_owned())),
+        };
+        Ok(Self { key, descending })
+    }
+}
+
+impl ChainMetrics {
+    /// Compare by each key in turn. A chain missing a metric goes after one that has it,
+    /// whichever way that key sorts.
+    pub fn cmp_by(&self, other: &Self, keys: &[SortSpec]) -> Ordering {
+        for spec in keys {
+            let ord = match (spec.key.get(self), spec.key.get(other)) {
+                (Some(a), Some(b)) if spec.descending => b.total_cmp(&a),
+                (Some(a), Some(b)) => a.total_cmp(&b),
+                (a, b) => b.is_some().cmp(&a.is_some()),
+            };
+            if ord != Ordering::Equal {
+                return ord;
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+/// Counters describing how much work a search did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SearchStats {
+    /// Partial chains taken off the stack and expanded.
+    pub states_explored: usize,
+    /// Complete chains found.
+    pub chains_found: usize,
+    /// The most partial chains waiting on the stack at once.
+    pub max_frontier: usize,
+    /// Extensions rejected because they'd make a junction on an avoided thread, or on one whose
+    /// role (see `ThreadRole`) keeps it to one end.
+    pub avoid_pruned: usize,
+    /// Extensions rejected only because the two pieces would foul each other (see
+    /// `Adapter::collides_with`).
+    pub collision_pruned: usize,
+    /// Extensions rejected because they'd come back to a thread the chain had already been
+    /// through (see `SearchOptions::allow_repeats`).
+    #[serde(default)]
+    pub repeat_pruned: usize,
+    /// Extensions rejected because they'd make a junction on a thread not allowed there (see
+    /// `SearchOptions::only_junctions`).
+    #[serde(default)]
+    pub junction_pruned: usize,
+    /// The search stopped at its deadline before it was done.
+    pub timed_out: bool,
+    /// For searches after the single best chain: the score of the best one found.
+    pub best_score: Option<f64>,
+    /// For searches after the single best chain: no chain can score lower than this. Equal to
+    /// `best_score` if the search finished.
+    pub lower_bound: Option<f64>,
+    /// The search stopped at `SearchOptions::max_states` before it was done, so it may have missed
+    /// chains.
+    pub truncated: bool,
+}
+
+impl SearchStats {
+    /// Fold in the stats from another search: counts add up, the frontier is the max of the two.
+    pub fn merge(&mut self, other: &Self) {
+        self.states_explored += other.states_explored;
+        self.chains_found += other.chains_found;
+        self.max_frontier = self.max_frontier.max(other.max_frontier);
+        self.avoid_pruned += other.avoid_pruned;
+        self.collision_pruned += other.collision_pruned;
+        self.repeat_pruned += other.repeat_pruned;
+        self.junction_pruned += other.junction_pruned;
+        self.timed_out |= other.timed_out;
+        self.truncated |= other.truncated;
+    }
+}
+
+impl Display for SearchStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} states explored, {} chains found, max frontier {}",
+            self.states_explored, self.chains_found, self.max_frontier)?;
+        if self.avoid_pruned != 0 {
+            write!(f, ", {} extensions pruned by avoided threads", self.avoid_pruned)?;
+        }
+        if self.collision_pruned != 0 {
+            write!(f, ", {} extensions pruned by colliding items", self.collision_pruned)?;
+        }
+        if self.repeat_pruned != 0 {
+            write!(f, ", {} extensions pruned by repeated threads", self.repeat_pruned)?;
+        }
+        if self.junction_pruned != 0 {
+            write!(f, ", {} extensions pruned by the junction whitelist", self.junction_pruned)?;
+        }
+        if let Some(best) = self.best_score {
+            write!(f, ", best found {}", best)?;
+        }
+        if let Some(bound) = self.lower_bound {
+            write!(f, ", lower bound {}", bound)?;
+        }
+        if self.timed_out {
+            f.write_str(" (stopped at deadline)")?;
+        }
+        if self.truncated {
+            f.write_str(" (stopped at state limit)")?;
+        }
+        Ok(())
+    }
+}
+
+/// How many partial chains a search expands by default before giving up. Ordinary inventories
+/// need a tiny fraction of this; it's there so a query that would run forever doesn't eat all
+/// the memory first.
+pub const DEFAULT_MAX_STATES: usize = 10_000_000;
+
+/// Restrictions on which chains a search returns. The default is no restrictions, apart from
+/// stopping after `DEFAULT_MAX_STATES`.
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    /// Thread names that must each appear at some junction between two adapters, in any order.
+    pub via: Vec<&'static str>,
+    /// Thread names that must not appear at any junction between two adapters. Adapters with these
+    /// threads can still be used, as long as that end is the start or end of the chain.
+    pub avoid: Vec<&'static str>,
+    /// When the start and end already mate, also return chains of adapters that get from one to
+    /// the other the long way round. Otherwise the only result is the empty chain (unless `first`
+    /// or `last` rule that out).
+    pub loops: bool,
+    /// Give up after expanding this many partial chains, returning what's been found so far and
+    /// setting `SearchStats::truncated`. `None` means no limit.
+    pub max_states: Option<usize>,
+    /// An adapter that has to be the first one, right on the start. Copies of it count.
+    pub first: Option<Adapter>,
+    /// An adapter that has to be the last one, right on the end.
+    pub last: Option<Adapter>,
+    /// Adapters that, wherever they're used, have to be right next to something in particular:
+    /// a filter holder next to the lens, say. An adapter can be given more than one of these, and
+    /// has to satisfy them all.
+    pub adjacent: Vec<(Adapter, Neighbor)>,
+    /// Rule out chains with interferences (see `Chain::interferences`), rather than just reporting
+    /// them.
+    pub strict_fit: bool,
+    /// Rule out chains with seating problems (see `Chain::seating_problems`), rather than just
+    /// reporting them.
+    pub strict_seating: bool,
+    /// Thread names that can only be in some places in a chain. Threads not listed can be anywhere.
+    pub roles: Vec<(&'static str, ThreadRole)>,
+    /// Let chains end at either gender of the end thread, for when there's something of each to
+    /// go there. Each chain's end says which one it reached.
+    pub any_gender_end: bool,
+    /// Allow chains that come back to a thread they've already been through, like 52 -> 77 -> 52
+    /// -> 58, which could always skip the loop. Gender changers, which have the same thread on
+    /// both ends, don't count as coming back, and neither does ending on the start's thread.
+    pub allow_repeats: bool,
+    /// The only threads allowed at junctions between two adapters, to keep chains to filter
+    /// threads, say. The start and end threads can be anything. `None` allows everything.
+    pub only_junctions: Option<JunctionWhitelist>,
+    /// Thread names that mate with their own gender as well as the other, like push-fit couplings
+    /// that go male into male (see `SearchOptions::mates`).
+    pub hermaphroditic: Vec<&'static str>,
+}
+
+/// Which threads may be at junctions (see `SearchOptions::only_junctions`).
+#[derive(Debug, Clone)]
+pub enum JunctionWhitelist {
+    /// Threads with these names.
+    Threads(Vec<&'static str>),
+    /// Threads of this kind (see `registry::thread_kind`).
+    Kind(registry::ThreadKind),
+}
+
+impl JunctionWhitelist {
+    pub fn allows(&self, thread: Thread) -> bool {
+        match self {
+            Self::Threads(names) => names.contains(&thread.name()),
+            Self::Kind(kind) => registry::thread_kind(thread) == Some(*kind),
+        }
+    }
+}
+
+/// What an adapter has to be next to (see `SearchOptions::adjacent`).
+#[derive(Debug, Clone)]
+pub enum Neighbor {
+    Start,
+    End,
+    Adapter(Adapter),
+}
+
+impl Display for Neighbor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Start => f.write_str("the start"),
+            Self::End => f.write_str("the end"),
+            Self::Adapter(a) => write!(f, "[{}]", a),
+        }
+    }
+}
+
+impl Neighbor {
+    fn same_as(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Start, Self::Start) | (Self::End, Self::End) => true,
+            (Self::Adapter(a), Self::Adapter(b)) => a.is_same_item(b),
+            _ => false,
+        }
+    }
+
+    /// Whether this is the piece of a chain (start and end included) that's given.
+    fn is(&self, piece: &Adapter) -> bool {
+        match self {
+            Self::Start => piece.0 == NIL_THREAD,
+            Self::End => piece.1 == NIL_THREAD,
+            Self::Adapter(a) => {
+                piece.0 != NIL_THREAD && piece.1 != NIL_THREAD && piece.is_same_item(a)
+            }
+        }
+    }
+}
+
+/// Position constraints in `SearchOptions` that can't all be met, or that name something that
+/// isn't in the equipment.
+#[derive(Debug, Clone)]
+pub enum ConstraintError {
+    NotInEquipment(Adapter),
+    /// An adapter has to be next to more things than it has sides.
+    TooManyNeighbors(Adapter),
+    /// The first adapter has to be next to the end (or the last next to the start), but they
+    /// aren't the same adapter.
+    FirstAndLast(Adapter),
+}
+
+impl Display for ConstraintError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotInEquipment(a) => write!(f, "[{}] isn't in the equipment being searched", a),
+            Self::TooManyNeighbors(a) => {
+                write!(f, "[{}] can't be next to everything it's supposed to be: it only has two \
+                    sides", a)
+            }
+            Self::FirstAndLast(a) => {
+                write!(f, "[{}] would have to be both first and last, but something else is \
+                    pinned to the other end", a)
+            }
+        }
+    }
+}
+
+impl Error for ConstraintError {}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            via: vec![],
+            avoid: vec![],
+            loops: false,
+            max_states: Some(DEFAULT_MAX_STATES),
+            first: None,
+            last: None,
+            adjacent: vec![],
+            strict_fit: false,
+            strict_seating: false,
+            roles: vec![],
+            any_gender_end: false,
+            allow_repeats: false,
+            only_junctions: None,
+            hermaphroditic: vec![],
+        }
+    }
+}
+
+impl SearchOptions {
+    /// Check the position constraints against each other and the equipment, so a search that
+    /// can't find anything because of them can be headed off with an explanation.
+    pub fn check(&self, equipment: &[Adapter]) -> Result<(), ConstraintError> {
+        let named = self.first.iter()
+            .chain(&self.last)
+            .chain(self.adjacent.iter().flat_map(|(a, n)| match n {
+                Neighbor::Adapter(b) => vec![a, b],
+                _ => vec![a],
+            }));
+        for a in named {
+            if !equipment.iter().any(|e| e.is_same_item(a)) {
+                return Err(ConstraintError::NotInEquipment(a.clone()));
+            }
+        }
+
+        let pinned = |end: &Option<Adapter>, a: &Adapter| {
+            end.as_ref().is_some_and(|e| e.is_same_item(a))
+        };
+        for (item, _) in &self.adjacent {
+            let mut sides = vec![];
+            if pinned(&self.first, item) {
+                sides.push(Neighbor::Start);
+            }
+            if pinned(&self.last, item) {
+                sides.push(Neighbor::End);
+            }
+            for (_, n) in self.adjacent.iter().filter(|(a, _)| a.is_same_item(item)) {
+                if !sides.iter().any(|side| side.same_as(n)) {
+                    sides.push(n.clone());
+                }
+            }
+            if sides.len() > 2 {
+                return Err(ConstraintError::TooManyNeighbors(item.clone()));
+            }
+            let needs = |end: Neighbor| sides.iter().any(|side| side.same_as(&end));
+            let other_pinned = |end: &Option<Adapter>| {
+                end.as_ref().is_some_and(|e| !e.is_same_item(item))
+            };
+            if (pinned(&self.first, item) && needs(Neighbor::End) && other_pinned(&self.last))
+                || (pinned(&self.last, item) && needs(Neighbor::Start) && other_pinned(&self.first))
+            {
+                return Err(ConstraintError::FirstAndLast(item.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether to keep looking when the start and end mate directly. Pinning an adapter to
+    /// either end means the direct connection isn't wanted.
+    fn wants_loops(&self) -> bool {
+        self.loops || self.first.is_some() || self.last.is_some()
+    }
+
+    /// The end a chain exposing `exposed` has reached, if it's reached one: `end`, or, with
+    /// `any_gender_end`, the other gender of it.
+    fn reached(&self, exposed: Thread, end: Thread) -> Option<Thread> {
+        if self.mates(exposed, end) {
+            Some(end)
+        } else if self.any_gender_end && self.mates(exposed, end.opposite()) {
+            Some(end.opposite())
+        } else {
+            None
+        }
+    }
+
+    /// The chain with no adapters in it, if `start` already reaches the end.
+    fn direct_chain(&self, start: Thread, end: Thread) -> Option<Chain> {
+        Some(empty_chain(start, self.reached(start, end)?))
+    }
+
+    /// Whether the two go together: as `Thread::mates` has it, or the same thread twice when it's
+    /// one of the `hermaphroditic` ones.
+    pub fn mates(&self, a: Thread, b: Thread) -> bool {
+        a.mates(b) || a == b && self.hermaphroditic.contains(&a.name())
+    }
+
+    /// Where a thread may go in a chain, by `roles`.
+    fn role(&self, thread: Thread) -> ThreadRole {
+        self.roles.iter()
+            .find(|(name, _)| *name == thread.name())
+            .map_or(ThreadRole::Any, |(_, role)| *role)
+    }
+
+    fn accepts(&self, chain: &Chain) -> bool {
+        let adapters = chain.adapters();
+        let is = |a: Option<&Adapter>, pinned: &Adapter| a.is_some_and(|a| a.is_same_item(pinned));
+        let start = self.role(chain.0[0].1);
+        let end = self.role(chain.0.last().unwrap().0);
+        !matches!(start, ThreadRole::Terminal | ThreadRole::Intermediate)
+            && !matches!(end, ThreadRole::Body | ThreadRole::Intermediate)
+            && self.via.iter().all(|via| chain.junctions().any(|t| t.name() == *via))
+            && self.first.as_ref().is_none_or(|first| is(adapters.first(), first))
+            && self.last.as_ref().is_none_or(|last| is(adapters.last(), last))
+            && chain.0.windows(3).all(|w| self.neighbors_ok(&w[0], &w[1], &w[2]))
+    }
+
+    /// Whether a piece's neighbors are ones it's allowed.
+    fn neighbors_ok(&self, left: &Adapter, piece: &Adapter, right: &Adapter) -> bool {
+        self.adjacent.iter()
+            .filter(|(a, _)| piece.is_same_item(a))
+            .all(|(_, n)| n.is(left) || n.is(right))
+    }
+
+    /// Whether `next` can go on the end of a partial chain, as far as position constraints,
+    /// `strict_fit`, and `strict_seating` go. Putting it there settles both neighbors of the piece
+    /// before it, so that gets checked too.
+    fn can_place(&self, chain: &Chain, next: &Adapter) -> bool {
+        self.placement_problem(chain, next).is_none()
+    }
+
+    /// Which of the checks in `can_place` `next` fails, if any.
+    fn placement_problem(&self, chain: &Chain, next: &Adapter) -> Option<Rejection> {
+        let last = chain.0.last().unwrap();
+        if last.0 != NIL_THREAD {
+            if self.strict_fit && interference(last, next, 0).is_some() {
+                return Some(Rejection::Interference);
+            }
+            if self.strict_seating && seating_problem(last, next, 0).is_some() {
+                return Some(Rejection::Seating);
+            }
+        }
+        if let ([_start], Some(first)) = (&chain.0[..], &self.first) {
+            return (!next.is_same_item(first)).then_some(Rejection::NotFirst);
+        }
+        match &chain.0[..] {
+            [.., left, piece] if piece.0 != NIL_THREAD && !self.neighbors_ok(left, piece, next) => {
+                Some(Rejection::Neighbors)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether putting `next` on the chain would come back to a thread it's already been through
+    /// (see `allow_repeats`).
+    fn revisits(&self, chain: &Chain, next: &Adapter, end: Thread) -> bool {
+        let name = next.1.name();
+        if self.allow_repeats || name == next.0.name() {
+            return false;
+        }
+        // Ending up where it started is what a gender flip or a way round has to do.
+        let completes = self.reached(next.1, end).is_some();
+        chain.0.iter()
+            .enumerate()
+            .any(|(i, a)| a.1.name() == name && !(i == 0 && completes))
+    }
+
+    /// Whether a chain's current end may be a junction, by `only_junctions` (it's only a junction
+    /// if the last piece is a real adapter, not the start).
+    fn allows_junction(&self, chain: &Chain) -> bool {
+        let last = chain.0.last().unwrap();
+        last.0 == NIL_THREAD || self.only_junctions.as_ref().is_none_or(|only| only.allows(last.1))
+    }
+
+    /// Whether a chain may be extended from its current end. It can't be if that would make a
+    /// junction on an avoided thread, or on one whose role keeps it to one end (it's only a
+    /// junction if the last piece is a real adapter, not the start).
+    fn can_extend(&self, chain: &Chain) -> bool {
+        let last = chain.0.last().unwrap();
+        last.0 == NIL_THREAD
+            || !self.avoid.contains(&last.1.name())
+                && !matches!(self.role(last.1), ThreadRole::Body | ThreadRole::Terminal)
+    }
+}
+
+/// The chain with no adapters in it, if `start` and `end` mate directly.
+fn direct_chain(start: Thread, end: Thread) -> Option<Chain> {
+    start.mates(end).then(|| empty_chain(start, end))
+}
+
+/// Just the start and end placeholders, with nothing between them.
+fn empty_chain(start: Thread, end: Thread) -> Chain {
+    let mut chain = Chain::new(Adapter::new(NIL_THREAD, start).with_name("start"));
+    chain.0.push(Adapter::new(end, NIL_THREAD).with_name("end"));
+    chain
+}
+
+/// Every chain that connects `start` to `end`, best first (see `Chain::default_cmp`).
+///
+/// If the two already mate (`58(F)` to `58(M)`, say), the answer is the empty chain, and that's
+/// all unless `options.loops` asks for the longer ways round too. Asking for the same thread at
+/// both ends (`58(F)` to `58(F)`) is an ordinary search: it needs a gender changer.
+pub fn make_chain(start: Thread, end: Thread, equipment: &[Adapter]) -> Vec<Chain> {
+    make_chain_with_stats(start, end, equipment).0
+}
+
+pub fn make_chain_with_stats(
+    start: Thread,
+    end: Thread,
+    equipment: &[Adapter],
+) -> (Vec<Chain>, SearchStats) {
+    make_chain_with_options(start, end, equipment, &SearchOptions::default())
+}
+
+/// `make_chain` with restrictions on what it returns and how long it keeps looking.
+///
+/// The search keeps its own stack on the heap rather than recursing, so however long the chains
+/// get, it can't overflow the call stack; memory is what grows, and `max_states` bounds that.
+pub fn make_chain_with_options(
+    start: Thread,
+    end: Thread,
+    equipment: &[Adapter],
+    options: &SearchOptions,
+) -> (Vec<Chain>, SearchStats) {
+    search(SearchCheckpoint::new(start, end, equipment), equipment, options, None, None)
+}
+
+/// `make_chain_with_options`, also recording what the search did (see `SearchTree`), to find out
+/// why it didn't find a chain it was expected to. The tree is as big as the search was, so
+/// `options.max_states` bounds it too.
+pub fn make_chain_with_tree(
+    start: Thread,
+    end: Thread,
+    equipment: &[Adapter],
+    options: &SearchOptions,
+) -> (Vec<Chain>, SearchStats, SearchTree) {
+    let mut tree = SearchTree::default();
+    let state = SearchCheckpoint::new(start, end, equipment);
+    let (chains, stats) = search(state, equipment, options, None, Some(&mut tree));
+    (chains, stats, tree)
+}
+
+/// Everything a search expanded, and every extension it turned down and why (see
+/// `make_chain_with_tree`).
+#[derive(Debug, Clone, Default)]
+pub struct SearchTree {
+    /// Partial chains the search expanded, and complete ones it found. The first is the start.
+    pub nodes: Vec<SearchNode>,
+    /// Adapters that weren't put on the end of an expanded chain.
+    pub rejections: Vec<SearchRejection>,
+}
+
+/// A partial chain the search expanded, or a complete one it found.
+#[derive(Debug, Clone)]
+pub struct SearchNode {
+    /// The node for the chain this one extends, by its place in `SearchTree::nodes`. Only the
+    /// start has none.
+    pub parent: Option<usize>,
+    /// The piece on the end of the chain: the adapter placed last, or the start.
+    pub adapter: Adapter,
+    /// The thread left exposed on the end of the chain.
+    pub exposed: Thread,
+    /// How many adapters are in the chain.
+    pub depth: usize,
+    /// The chain is complete and meets the search's constraints. For the start, it already mates
+    /// the end.
+    pub goal: bool,
+}
+
+/// An adapter the search didn't put on the end of an expanded chain.
+#[derive(Debug, Clone)]
+pub struct SearchRejection {
+    /// The chain it wasn't put on, by its place in `SearchTree::nodes`.
+    pub node: usize,
+    /// The adapter, turned around to fit if it would.
+    pub adapter: Adapter,
+    pub reason: Rejection,
+}
+
+/// Why the search didn't put an adapter on the end of a chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rejection {
+    /// It's already in the chain.
+    InUse,
+    /// It's a copy of something, and an earlier copy isn't in the chain. That copy is tried
+    /// instead, so every chain is only found once.
+    LaterCopy,
+    /// Neither of its ends mates the exposed thread.
+    DoesntMate,
+    /// The exposed thread is in `SearchOptions::avoid`, or has a role keeping it to one end, so
+    /// nothing can go on it.
+    Avoided,
+    /// The exposed thread isn't allowed at a junction (`SearchOptions::only_junctions`).
+    NotWhitelisted,
+    /// Something else is pinned first (`SearchOptions::first`).
+    NotFirst,
+    /// The piece before it has to be next to something else (`SearchOptions::adjacent`).
+    Neighbors,
+    /// It would interfere with the piece before it (`SearchOptions::strict_fit`).
+    Interference,
+    /// It wouldn't seat on the piece before it (`SearchOptions::strict_seating`).
+    Seating,
+    /// It would come back to a thread the chain has been through (`SearchOptions::allow_repeats`).
+    Repeat,
+    /// It would foul something in the chain (see `Adapter::collides_with`).
+    Collision,
+    /// It would complete the chain, but the chain doesn't meet the search's other constraints:
+    /// `via`, `first`, `last`, `adjacent`, or `roles`.
+    Unaccepted,
+}
+
+impl Display for Rejection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::InUse => "already used",
+            Self::LaterCopy => "an earlier copy is unused",
+            Self::DoesntMate => "doesn't mate",
+            Self::Avoided => "avoided thread",
+            Self::NotWhitelisted => "junction not whitelisted",
+            Self::NotFirst => "not the first adapter",
+            Self::Neighbors => "wrong neighbor",
+            Self::Interference => "interferes",
+            Self::Seating => "doesn't seat",
+            Self::Repeat => "repeats a thread",
+            Self::Collision => "collides",
+            Self::Unaccepted => "chain breaks a constraint",
+        })
+    }
+}
+
+impl SearchTree {
+    fn reject(tree: &mut Option<&mut Self>, node: usize, adapter: &Adapter, reason: Rejection) {
+        if let Some(tree) = tree {
+            tree.rejections.push(SearchRejection { node, adapter: adapter.clone(), reason });
+        }
+    }
+
+    /// A Graphviz DOT graph of the tree, with the chains that reached the end filled in green.
+    /// Rejected adapters hang off the chains they weren't put on, in grey, except that adapters
+    /// that don't mate are only counted, since that's most of them.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph search {\n    node [shape=box];\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            let piece = match node.parent {
+                None => format!("start: {}", node.exposed),
+                Some(_) => format!("[{}]", node.adapter),
+            };
+            let label = format!("{}\nexposes {}, depth {}", piece, node.exposed, node.depth);
+            let goal = if node.goal { ", style=filled, fillcolor=palegreen" } else { "" };
+            out += &format!("    n{} [label={}{}];\n", i, dot_quote(&label), goal);
+            if let Some(parent) = node.parent {
+                out += &format!("    n{} -> n{};\n", parent, i);
+            }
+        }
+        let mut unmated = vec![0; self.nodes.len()];
+        for (i, r) in self.rejections.iter().enumerate() {
+            if r.reason == Rejection::DoesntMate {
+                unmated[r.node] += 1;
+                continue;
+            }
+            let label = format!("[{}]\n{}", r.adapter, r.reason);
+            out += &format!("    r{} [label={}, color=gray, fontcolor=gray];\n", i,
+                dot_quote(&label));
+            out += &format!("    n{} -> r{} [style=dashed, color=gray];\n", r.node, i);
+        }
+        for (i, count) in unmated.into_iter().enumerate().filter(|(_, count)| *count > 0) {
+            out += &format!("    m{} [label=\"{} don't mate\", shape=plaintext, \
+                fontcolor=gray];\n", i, count);
+            out += &format!("    n{} -> m{} [style=dashed, color=gray];\n", i, i);
+        }
+        out += "}\n";
+        out
+    }
+}
+
+/// Where an exhaustive search had got to, so it can be picked up again later, maybe by another run
+/// of the program (see `make_chain_with_checkpoints`). Adapters are recorded by their place in the
+/// equipment and whether they're turned around, so it's only any use with the same equipment.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchCheckpoint {
+    /// Identifies the equipment, to check it's the same when resuming (see `equipment_hash`).
+    equipment_hash: u64,
+    start: Thread,
+    end: Thread,
+    /// The chain being extended, after the start.
+    path: Vec<(usize, bool)>,
+    /// Adapters waiting to be tried, each with the length of the chain (counting the start) it
+    /// goes after.
+    stack: Vec<(usize, usize, bool)>,
+    /// The chains found so far, without their ends.
+    found: Vec<Vec<(usize, bool)>>,
+    stats: SearchStats,
+}
+
+impl SearchCheckpoint {
+    fn new(start: Thread, end: Thread, equipment: &[Adapter]) -> Self {
+        Self {
+            equipment_hash: equipment_hash(equipment),
+            start,
+            end,
+            path: vec![],
+            stack: vec![],
+            found: vec![],
+            stats: SearchStats { max_frontier: 1, ..SearchStats::default() },
+        }
+    }
+
+    /// The search's stats up to the checkpoint.
+    pub fn stats(&self) -> &SearchStats {
+        &self.stats
+    }
+
+    /// The adapter at a place in the equipment, turned around if need be.
+    fn place(equipment: &[Adapter], (i, reversed): (usize, bool)) -> Adapter {
+        let a = equipment[i].clone();
+        if reversed { a.reverse() } else { a }
+    }
+
+    /// A chain from the start through the given adapters, with no end on it.
+    fn chain(&self, equipment: &[Adapter], path: &[(usize, bool)]) -> Chain {
+        let mut chain = Chain::new(Adapter::new(NIL_THREAD, self.start).with_name("start"));
+        chain.0.extend(path.iter().map(|&placed| Self::place(equipment, placed)));
+        chain
+    }
+}
+
+/// Why a search can't be resumed from a checkpoint.
+#[derive(Debug, Clone)]
+pub enum CheckpointError {
+    /// The equipment isn't what it was when the checkpoint was made, so the adapters it refers to
+    /// by position might not be the same ones.
+    EquipmentChanged,
+    /// The checkpoint is of a search between two different threads.
+    DifferentEnds { start: Thread, end: Thread },
+}
+
+impl Display for CheckpointError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EquipmentChanged => {
+                f.write_str("the inventory has changed since the checkpoint was made, so it can't \
+                    be resumed; start the search again")
+            }
+            Self::DifferentEnds { start, end } => {
+                write!(f, "the checkpoint is of a search from {} to {}", start, end)
+            }
+        }
+    }
+}
+
+impl Error for CheckpointError {}
+
+/// A hash of the equipment, its order, and everything known about each piece, which stays the same
+/// from one run of the program to the next (FNV-1a over the adapters as JSON).
+fn equipment_hash(equipment: &[Adapter]) -> u64 {
+    let mut hash = 0xcbf29ce484222325_u64;
+    for a in equipment {
+        let json = serde_json::to_string(a).expect("adapters always serialize");
+        for byte in json.bytes().chain([b'\n']) {
+            hash = (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+/// `make_chain_with_options`, passing `save` a checkpoint of the search every so often, and once
+/// more if it stops at `options.max_states`. Given an earlier checkpoint to `resume` from, it
+/// carries on from there instead of starting over; that has to be made with the same equipment
+/// and ends, and should be made with the same options. Counts in the stats, including the one
+/// compared against `options.max_states`, carry on from the checkpoint's.
+pub fn make_chain_with_checkpoints(
+    start: Thread,
+    end: Thread,
+    equipment: &[Adapter],
+    options: &SearchOptions,
+    resume: Option<SearchCheckpoint>,
+    every: Duration,
+    save: &mut dyn FnMut(&SearchCheckpoint),
+) -> Result<(Vec<Chain>, SearchStats), CheckpointError> {
+    let state = match resume {
+        Some(checkpoint) if checkpoint.equipment_hash != equipment_hash(equipment) => {
+            return Err(CheckpointError::EquipmentChanged);
+        }
+        Some(checkpoint) if (checkpoint.start, checkpoint.end) != (start, end) => {
+            return Err(CheckpointError::DifferentEnds {
+                start: checkpoint.start,
+                end: checkpoint.end,
+            });
+        }
+        Some(mut checkpoint) => {
+            checkpoint.stats.truncated = false;
+            checkpoint
+        }
+        None => SearchCheckpoint::new(start, end, equipment),
+    };
+    Ok(search(state, equipment, options, Some((every, save)), None))
+}
+
+/// What to do with each checkpoint of a search.
+type SaveCheckpoint<'a> = &'a mut dyn FnMut(&SearchCheckpoint);
+
+/// The exhaustive search behind `make_chain_with_options`, from a new or resumed state, recording
+/// what it does in `tree` if there is one.
+fn search<'a>(
+    state: SearchCheckpoint,
+    equipment: &'a [Adapter],
+    options: &'a SearchOptions,
+    checkpoints: Option<(Duration, SaveCheckpoint<'a>)>,
+    tree: Option<&'a mut SearchTree>,
+) -> (Vec<Chain>, SearchStats) {
+    let mut search = ChainIter::new(state, equipment, options, checkpoints, tree);
+    let mut found = search.by_ref().collect::<Vec<_>>();
+    found.sort_by(Chain::default_cmp);
+    (found, search.state.stats)
+}
+
+/// Every chain from `start` to `end`, as `make_chain_with_options` finds them, but handed out one
+/// at a time as the search comes to them rather than all at once, best first. Only the chain
+/// being extended is held on to, so the memory used doesn't grow with the number of chains.
+pub fn make_chain_iter<'a>(
+    start: Thread,
+    end: Thread,
+    equipment: &'a [Adapter],
+    options: &'a SearchOptions,
+) -> ChainIter<'a> {
+    let state = SearchCheckpoint::new(start, end, equipment);
+    ChainIter::new(state, equipment, options, None, None)
+}
+
+/// The chains of an exhaustive search, in the order it finds them (see `make_chain_iter`).
+pub struct ChainIter<'a> {
+    state: SearchCheckpoint,
+    equipment: &'a [Adapter],
+    options: &'a SearchOptions,
+    checkpoints: Option<(Duration, SaveCheckpoint<'a>)>,
+    tree: Option<&'a mut SearchTree>,
+    /// The chain being extended, and which adapters are in it.
+    chain: Chain,
+    used: Vec<bool>,
+    /// For each adapter, where in `equipment` the copy of it before it is, if there is one.
+    previous_copy: Vec<Option<usize>>,
+    /// The tree's nodes for the chain being expanded, and for the one each adapter on the stack
+    /// would go on. These stay empty without a tree.
+    node: usize,
+    parent: Option<usize>,
+    parents: Vec<usize>,
+    last_saved: Instant,
+    /// Chains found and not handed out yet.
+    ready: VecDeque<Chain>,
+    done: bool,
+}
+
+impl<'a> ChainIter<'a> {
+    fn new(
+        mut state: SearchCheckpoint,
+        equipment: &'a [Adapter],
+        options: &'a SearchOptions,
+        checkpoints: Option<(Duration, SaveCheckpoint<'a>)>,
+        mut tree: Option<&'a mut SearchTree>,
+    ) -> Self {
+        // Depth-first, with one chain and one set of used adapters shared by every branch. The
+        // stack holds adapters waiting to be tried, each with how long the chain was when it was
+        // found to fit; trying one means cutting the chain back to that length first.
+        //
+        // Adapters are tracked by their place in `equipment`, so two copies of something can both
+        // be used. Copies are interchangeable, though, so a copy is only tried once the one before
+        // it is in use; otherwise every chain using one would be found once per copy.
+        let end = state.end;
+        let chain = state.chain(equipment, &state.path);
+        let mut used = vec![false; equipment.len()];
+        for &(i, _) in &state.path {
+            used[i] = true;
+        }
+        let mut last_copy = HashMap::new();
+        let previous_copy = equipment.iter().enumerate()
+            .map(|(i, a)| last_copy.insert((a.sorted_threads(), a.listed_name()), i))
+            .collect::<Vec<_>>();
+        let mut ready = state.found.iter()
+            .map(|path| {
+                let mut chain = state.chain(equipment, path);
+                let exposed = chain.0.last().unwrap().1;
+                chain.0.push(end_piece(options.reached(exposed, end).unwrap_or(end)));
+                chain
+            })
+            .collect::<VecDeque<_>>();
+
+        if let Some(tree) = &mut tree {
+            let last = chain.0.last().unwrap();
+            let (adapter, exposed, depth) = (last.clone(), last.1, chain.0.len() - 1);
+            tree.nodes.push(SearchNode { parent: None, adapter, exposed, depth, goal: false });
+        }
+
+        // A resumed search might already have it.
+        let have_direct = state.found.iter().any(Vec::is_empty);
+        let mut done = false;
+        if let Some(direct) = options.direct_chain(state.start, end).filter(|_| !have_direct) {
+            if options.accepts(&direct) {
+                ready.push_back(direct);
+                state.found.push(vec![]);
+                if let Some(tree) = &mut tree {
+                    tree.nodes[0].goal = true;
+                }
+            }
+            done = !options.wants_loops();
+        }
+        state.stats.chains_found = ready.len();
+        Self {
+            state,
+            equipment,
+            options,
+            checkpoints,
+            tree,
+            chain,
+            used,
+            previous_copy,
+            node: 0,
+            parent: None,
+            parents: vec![],
+            last_saved: Instant::now(),
+            ready,
+            done,
+        }
+    }
+
+    /// The search's stats so far. Once the chains have run out, they're the stats of the whole
+    /// search.
+    pub fn stats(&self) -> &SearchStats {
+        &self.state.stats
+    }
+
+    /// Expand the chain being extended, and move on to the next one to try. False once there's
+    /// nothing left to try, or the search has stopped at `max_states`.
+    fn step(&mut self) -> bool {
+        let (equipment, options, end) = (self.equipment, self.options, self.state.end);
+        let state = &mut self.state;
+        let (chain, used, tree) = (&mut self.chain, &mut self.used, &mut self.tree);
+        if let Some((every, save)) = &mut self.checkpoints {
+            if self.last_saved.elapsed() >= *every {
+                save(state);
+                self.last_saved = Instant::now();
+            }
+        }
+        if options.max_states.is_some_and(|max| state.stats.states_explored >= max) {
+            if let Some((_, save)) = &mut self.checkpoints {
+                save(state);
+            }
+            state.stats.truncated = true;
+            return false;
+        }
+        state.stats.states_explored += 1;
+        let mut node = self.node;
+        if let (Some(tree), Some(parent)) = (&mut *tree, self.parent) {
+            let last = chain.0.last().unwrap();
+            let (adapter, exposed, depth) = (last.clone(), last.1, chain.0.len() - 1);
+            let parent = Some(parent);
+            node = tree.nodes.len();
+            tree.nodes.push(SearchNode { parent, adapter, exposed, depth, goal: false });
+        }
+        self.node = node;
+        let mut tree = tree.as_deref_mut();
+        let exposed = chain.0.last().unwrap().1;
+        for (i, a) in equipment.iter().enumerate() {
+            if used[i] {
+                SearchTree::reject(&mut tree, node, a, Rejection::InUse);
+                continue;
+            }
+            if self.previous_copy[i].is_some_and(|j| !used[j]) {
+                SearchTree::reject(&mut tree, node, a, Rejection::LaterCopy);
+                continue;
+            }
+            let Some(next) = a.oriented_after(exposed, options) else {
+                SearchTree::reject(&mut tree, node, a, Rejection::DoesntMate);
+                continue;
+            };
+            if !options.can_extend(chain) {
+                state.stats.avoid_pruned += 1;
+                SearchTree::reject(&mut tree, node, &next, Rejection::Avoided);
+                continue;
+            }
+            if !options.allows_junction(chain) {
+                state.stats.junction_pruned += 1;
+                SearchTree::reject(&mut tree, node, &next, Rejection::NotWhitelisted);
+                continue;
+            }
+            if let Some(problem) = options.placement_problem(chain, &next) {
+                SearchTree::reject(&mut tree, node, &next, problem);
+                continue;
+            }
+            if options.revisits(chain, &next, end) {
+                state.stats.repeat_pruned += 1;
+                SearchTree::reject(&mut tree, node, &next, Rejection::Repeat);
+                continue;
+            }
+            if chain.fouls(&next) {
+                state.stats.collision_pruned += 1;
+                SearchTree::reject(&mut tree, node, &next, Rejection::Collision);
+                continue;
+            }
+            let reversed = next.4 != a.4;
+            if let Some(reached) = options.reached(next.1, end) {
+                let mut complete = chain.clone();
+                complete.0.push(next.clone());
+                complete.0.push(end_piece(reached));
+                if options.accepts(&complete) {
+                    self.ready.push_back(complete);
+                    state.stats.chains_found += 1;
+                    let mut path = state.path.clone();
+                    path.push((i, reversed));
+                    state.found.push(path);
+                    if let Some(tree) = &mut tree {
+                        let (exposed, depth) = (next.1, chain.0.len());
+                        tree.nodes.push(SearchNode {
+                            parent: Some(node),
+                            adapter: next,
+                            exposed,
+                            depth,
+                            goal: true,
+                        });
+                    }
+                } else {
+                    SearchTree::reject(&mut tree, node, &next, Rejection::Unaccepted);
+                }
+            } else {
+                state.stack.push((chain.0.len(), i, reversed));
+                if tree.is_some() {
+                    self.parents.push(node);
+                }
+            }
+        }
+        state.stats.max_frontier = state.stats.max_frontier.max(state.stack.len());
+
+        let Some((len, i, reversed)) = state.stack.pop() else { return false };
+        self.parent = self.parents.pop();
+        chain.0.truncate(len);
+        // The chain's first piece is the start, which isn't in `path`.
+        for (removed, _) in state.path.drain(len - 1..) {
+            used[removed] = false;
+        }
+        used[i] = true;
+        state.path.push((i, reversed));
+        chain.0.push(SearchCheckpoint::place(equipment, (i, reversed)));
+        true
+    }
+}
+
+impl Iterator for ChainIter<'_> {
+    type Item = Chain;
+
+    fn next(&mut self) -> Option<Chain> {
+        loop {
+            if let Some(chain) = self.ready.pop_front() {
+                return Some(chain);
+            }
+            if self.done {
+                return None;
+            }
+            self.done = !self.step();
+        }
+    }
+}
+
+/// The piece at the end of a finished chain, on the thread it reached.
+fn end_piece(reached: Thread) -> Adapter {
+    Adapter::new(reached, NIL_THREAD).with_name("end")
+}
+
+/// Ways to join two lenses front to front, as for reverse-lens macro, given their filter threads.
+/// The lenses themselves aren't used in the chains, and every chain has a coupler with the same
+/// gender at both ends, which is what lets two filter threads (normally both female) face each
+/// other.
+pub fn couple_lenses(lens_a: Thread, lens_b: Thread, equipment: &[Adapter]) -> Vec<Chain> {
+    let couplers = equipment.iter().filter(|a| !a.is_optical()).cloned().collect::<Vec<_>>();
+    make_chain(lens_a, lens_b, &couplers)
+        .into_iter()
+        .filter(|chain| chain.adapters().iter().any(|a| a.0.is_male() == a.1.is_male()))
+        .collect()
+}
+
+/// `make_chain_with_options` from one terminal item to another, with the chains showing them at
+/// their ends.
+pub fn make_chain_between(
+    from: &Terminal,
+    to: &Terminal,
+    equipment: &[Adapter],
+    options: &SearchOptions,
+) -> (Vec<Chain>, SearchStats) {
+    let (chains, stats) = make_chain_with_options(from.thread, to.thread, equipment, options);
+    let chains = chains.into_iter().map(|chain| chain.starting_at(from).ending_at(to)).collect();
+    (chains, stats)
+}
+
+/// Like `make_chain`, but the first and/or last real adapter in every chain is fixed: a particular
+/// body-mount adapter, say, or the lens at the far end. If a pinned adapter doesn't fit on its end
+/// there are no chains. If both are pinned and they're the same adapter, the only possible chain
+/// is that one adapter on its own.
+pub fn make_chain_with_ends(
+    start: Thread,
+    end: Thread,
+    equipment: &[Adapter],
+    first: Option<Adapter>,
+    last: Option<Adapter>,
+) -> Vec<Chain> {
+    let options = SearchOptions { first, last, ..SearchOptions::default() };
+    make_chain_with_options(start, end, equipment, &options).0
+}
+
+/// Like `make_chain`, but no adapter in any chain has `avoid` on either end, in either gender. This
+/// is stricter than `SearchOptions::avoid`, which only keeps it out of the junctions. If `start` or
+/// `end` is `avoid`, the only chain there can be is the empty one, when the two already mate.
+pub fn make_chain_avoiding_thread(
+    start: Thread,
+    end: Thread,
+    equipment: &[Adapter],
+    avoid: Thread,
+) -> Vec<Chain> {
+    let avoid = avoid.sym();
+    let equipment = equipment.iter()
+        .filter(|a| a.0.sym() != avoid && a.1.sym() != avoid)
+        .cloned()
+        .collect::<Vec<_>>();
+    make_chain(start, end, &equipment)
+}
+
+/// The chains that aren't dominated by a shorter one, in the order given. Chain `x` is dominated
+/// by chain `y` when they start and end on the same threads, `y` has fewer adapters, and the names
+/// of `y`'s junction threads (see `Chain::junctions`) all appear among `x`'s, in the same order,
+/// with others in between allowed. So `x` goes everywhere `y` does, and takes extra hops to get
+/// there. A chain with no adapters dominates every other one between the same ends. Only the
+/// given chains are compared with each other, so this is meant for everything a search found.
+pub fn minimal_chains(chains: Vec<Chain>) -> Vec<Chain> {
+    let keys = chains.iter()
+        .map(|chain| {
+            let ends = (chain.0.first().unwrap().1, chain.0.last().unwrap().0);
+            let junctions = chain.junctions().map(Thread::name).collect::<Vec<_>>();
+            (ends, chain.adapters().len(), junctions)
+        })
+        .collect::<Vec<_>>();
+    let dominated = |(ends, len, junctions): &(_, usize, Vec<_>)| {
+        keys.iter().any(|(other_ends, other_len, others)| {
+            other_ends == ends && other_len < len && is_subsequence(others, junctions)
+        })
+    };
+    chains.into_iter()
+        .zip(&keys)
+        .filter(|(_, key)| !dominated(key))
+        .map(|(chain, _)| chain)
+        .collect()
+}
+
+/// Whether everything in `short` is in `long`, in the same order.
+fn is_subsequence<T: PartialEq>(short: &[T], long: &[T]) -> bool {
+    let mut long = long.iter();
+    short.iter().all(|s| long.any(|l| l == s))
+}
+
+/// Find the chain with the lowest `Chain::score`, giving up at the deadline if there is one.
+/// It also gives up at `options.max_states`, which works the same way but sets `truncated`.
+///
+/// This is a depth-first branch and bound: it finds some chain quickly, then keeps looking for
+/// better ones, skipping any partial chain that already scores no better than the best so far.
+/// If the deadline hits, the best chain found so far is returned, with `timed_out` set in the
+/// stats and `lower_bound` saying how much better an undiscovered chain could possibly be.
+pub fn best_chain(
+    start: Thread,
+    end: Thread,
+    equipment: &[Adapter],
+    options: &SearchOptions,
+    deadline: Option<Instant>,
+) -> (Option<Chain>, SearchStats) {
+    let first = Chain::new(Adapter::new(NIL_THREAD, start).with_name("start"));
+    // Each chain waits with the places in `equipment` of the adapters in it.
+    let mut states = vec![(Vec::<usize>::new(), first)];
+    let mut best: Option<(f64, Chain)> = None;
+    let mut stats = SearchStats { max_frontier: 1, ..SearchStats::default() };
+
+    // Nothing scores better than no adapters at all.
+    if let Some(direct) = options.direct_chain(start, end).filter(|chain| options.accepts(chain)) {
+        stats.chains_found = 1;
+        stats.best_score = Some(0.);
+        stats.lower_bound = Some(0.);
+        return (Some(direct), stats);
+    }
+
+    while let Some((used, chain)) = states.pop() {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            // Put it back so it counts toward the lower bound.
+            states.push((used, chain));
+            stats.timed_out = true;
+            break;
+        }
+        if options.max_states.is_some_and(|max| stats.states_explored >= max) {
+            states.push((used, chain));
+            stats.truncated = true;
+            break;
+        }
+        stats.states_explored += 1;
+        // Scores only go up as a chain gets longer, so this can't lead anywhere better.
+        if best.as_ref().is_some_and(|(best, _)| chain.score() >= *best) {
+            continue;
+        }
+        for (i, a) in equipment.iter().enumerate() {
+            if used.contains(&i) {
+                continue;
+            }
+            let exposed = chain.0.last().unwrap().1;
+            let Some(oriented) = a.oriented_after(exposed, options) else { continue };
+            if chain.fouls(&oriented) {
+                stats.collision_pruned += 1;
+                continue;
+            }
+            let Some(mut next) = chain.add_with_options(a.clone(), options) else { continue };
+            if !options.can_extend(&chain) {
+                stats.avoid_pruned += 1;
+                continue;
+            }
+            if !options.allows_junction(&chain) {
+                stats.junction_pruned += 1;
+                continue;
+            }
+            if !options.can_place(&chain, next.0.last().unwrap()) {
+                continue;
+            }
+            if options.revisits(&chain, next.0.last().unwrap(), end) {
+                stats.repeat_pruned += 1;
+                continue;
+            }
+            if let Some(reached) = options.reached(next.0.last().unwrap().1, end) {
+                next.0.push(Adapter::new(reached, NIL_THREAD).with_name("end"));
+                let score = next.score();
+                if options.accepts(&next) && best.as_ref().is_none_or(|(best, _)| score < *best) {
+                    stats.chains_found += 1;
+                    best = Some((score, next));
+                }
+            } else {
+                let mut used = used.clone();
+                used.push(i);
+                states.push((used, next));
+            }
+        }
+        stats.max_frontier = stats.max_frontier.max(states.len());
+    }
+
+    stats.best_score = best.as_ref().map(|(score, _)| *score);
+    // Anything still waiting could only be extended into something scoring more than it does now.
+    stats.lower_bound = states.iter()
+        .map(|(_, chain)| chain.score())
+        .chain(stats.best_score)
+        .min_by(f64::total_cmp);
+    (best.map(|(_, chain)| chain), stats)
+}
+
+/// One of the chains with the fewest adapters, found breadth-first rather than by enumerating
+/// every chain. Which one is picked depends only on the order of the equipment. As in
+/// `make_chain`, each copy of an adapter can be used once.
+pub fn shortest_chain(start: Thread, end: Thread, equipment: &[Adapter]) -> Option<Chain> {
+    if let Some(direct) = direct_chain(start, end) {
+        return Some(direct);
+    }
+    // Each chain waits with the places in `equipment` of the adapters in it.
+    let first = Chain::new(Adapter::new(NIL_THREAD, start).with_name("start"));
+    let mut queue = VecDeque::from([(first, vec![])]);
+    let mut visited = HashSet::from([start]);
+    while let Some((chain, used)) = queue.pop_front() {
+        for (i, a) in equipment.iter().enumerate() {
+            if used.contains(&i) {
+                continue;
+            }
+            if let Some(mut next) = chain.add(a.clone()) {
+                let exposed = next.0.last().unwrap().1;
+                if exposed.mates(end) {
+                    next.0.push(Adapter::new(end, NIL_THREAD).with_name("end"));
+                    return Some(next);
+                }
+                if visited.insert(exposed) {
+                    let mut used = used.clone();
+                    used.push(i);
+                    queue.push_back((next, used));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// The total weight of a route in `optimal_chain`, then how many adapters it took, to break ties.
+#[derive(Debug, Clone, Copy)]
+struct RouteWeight(f64, usize);
+
+impl PartialEq for RouteWeight {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RouteWeight {}
+
+impl PartialOrd for RouteWeight {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RouteWeight {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+/// The chain with the lowest total of `w_cost * cost + w_len * length` over its adapters (see
+/// `Info::cost` and `Info::length`), fewest adapters breaking ties. Missing measurements count as
+/// 0, and so do negative weights and values, so it's worth filling them in before relying on this.
+///
+/// This is Dijkstra's algorithm over the threads: each thread is reached once, by the cheapest
+/// way there. That means it's fast, but in the rare case where the cheapest way to some thread
+/// uses up an item that's needed again further on, a dearer chain avoiding that can be missed.
+pub fn optimal_chain(
+    start: Thread,
+    end: Thread,
+    equipment: &[Adapter],
+    w_cost: f64,
+    w_len: f64,
+) -> Option<Chain> {
+    if let Some(direct) = direct_chain(start, end) {
+        return Some(direct);
+    }
+    let weight = |a: &Adapter| {
+        w_cost.max(0.) * a.3.cost.unwrap_or(0.).max(0.)
+            + w_len.max(0.) * a.3.length.unwrap_or(0.).max(0.)
+    };
+    // Each chain is kept with the places in `equipment` of the adapters in it.
+    let first = Chain::new(Adapter::new(NIL_THREAD, start).with_name("start"));
+    let mut chains = vec![(first, vec![])];
+    let mut queue = BinaryHeap::from([Reverse((RouteWeight(0., 0), 0))]);
+    let mut settled = HashSet::new();
+    while let Some(Reverse((RouteWeight(total, len), idx))) = queue.pop() {
+        let exposed = chains[idx].0.0.last().unwrap().1;
+        if !settled.insert(exposed) {
+            continue;
+        }
+        if idx != 0 && exposed.mates(end) {
+            let (mut chain, _) = chains.swap_remove(idx);
+            chain.0.push(Adapter::new(end, NIL_THREAD).with_name("end"));
+            return Some(chain);
+        }
+        for (i, a) in equipment.iter().enumerate() {
+            if chains[idx].1.contains(&i) {
+                continue;
+            }
+            let Some(next) = chains[idx].0.add(a.clone()) else { continue };
+            if settled.contains(&next.0.last().unwrap().1) {
+                continue;
+            }
+            let mut used = chains[idx].1.clone();
+            used.push(i);
+            queue.push(Reverse((RouteWeight(total + weight(a), len + 1), chains.len())));
+            chains.push((next, used));
+        }
+    }
+    None
+}
+
+/// The chain that uses the fewest differently-named threads, for keeping to one family of sizes.
+/// Ties go to the better chain by `Chain::default_cmp`. This looks at every chain, so it's as slow
+/// as `make_chain`.
+pub fn fewest_threads_chain(start: Thread, end: Thread, equipment: &[Adapter]) -> Option<Chain> {
+    make_chain(start, end, equipment).into_iter().min_by_key(|chain| {
+        chain.thread_path().iter().map(|t| t.name()).collect::<HashSet<_>>().len()
+    })
+}
+
+/// Every pair of the given threads that doesn't already mate, each pair once, in order.
+pub fn thread_pairs(threads: &[Thread]) -> Vec<(Thread, Thread)> {
+    threads.iter()
+        .enumerate()
+        .flat_map(|(i, &a)| threads[i + 1..].iter().map(move |&b| (a, b)))
+        .filter(|(a, b)| !a.mates(*b))
+        .collect()
+}
+
+/// The shortest chain (see `shortest_chain`) for each pair that can be connected, leaving out the
+/// ones that can't. Adapters that only go one way round (see `Adapter::is_reversible`) can make a
+/// pair connectable in one direction only, which still counts; the chain then goes the other way.
+pub fn shortest_chains_for_pairs(
+    pairs: &[(Thread, Thread)],
+    equipment: &[Adapter],
+) -> Vec<(Thread, Thread, Chain)> {
+    pairs.iter()
+        .filter_map(|&(a, b)| {
+            let chain = shortest_chain(a, b, equipment)
+                .or_else(|| shortest_chain(b, a, equipment))?;
+            Some((a, b, chain))
+        })
+        .collect()
+}
+
+/// How many of the chains each item of equipment is used in, most used first (ties in equipment
+/// order). Copies of an item count as the same item, and an item counts once per chain however
+/// many copies are in it. Items that aren't in any of the chains are included, with 0.
+pub fn adapter_utilization(chains: &[Chain], equipment: &[Adapter]) -> Vec<(Adapter, usize)> {
+    let mut counts: Vec<(Adapter, usize)> = vec![];
+    for a in equipment {
+        if !counts.iter().any(|(b, _)| a.is_same_item(b)) {
+            counts.push((a.clone(), 0));
+        }
+    }
+    for chain in chains {
+        for (item, count) in &mut counts {
+            if chain.adapters().iter().any(|a| a.is_same_item(item)) {
+                *count += 1;
+            }
+        }
+    }
+    counts.sort_by_key(|(_, count)| Reverse(*count));
+    counts
+}
+
+/// For each adapter, how many of the shortest chains from `start` to `end` (all the ones with the
+/// fewest adapters) go through it. The ones most chains depend on are worth having a spare of.
+/// Adapters that are equal (the same threads, either way round) count together, since either will
+/// do, and every one in the equipment is included, with 0 if no shortest chain uses it. This looks
+/// at every chain, so it's as slow as `make_chain`.
+pub fn adapter_betweenness(
+    start: Thread,
+    end: Thread,
+    equipment: &[Adapter],
+) -> HashMap<Adapter, usize> {
+    let mut counts: HashMap<Adapter, usize> = equipment.iter().map(|a| (a.clone(), 0)).collect();
+    let chains = make_chain(start, end, equipment);
+    let fewest = chains.iter().map(|chain| chain.adapters().len()).min();
+    for chain in chains.iter().filter(|chain| Some(chain.adapters().len()) == fewest) {
+        let through = chain.adapters().iter().collect::<HashSet<_>>();
+        for a in through {
+            *counts.entry(a.clone()).or_default() += 1;
+        }
+    }
+    counts
+}
+
+/// Two items of equipment that only ever turn up in chains together (see `always_together`), and
+/// so might as well be kept as one compound item.
+#[derive(Debug, Clone)]
+pub struct Together {
+    /// Never in a chain without `with`.
+    pub adapter: Adapter,
+    pub with: Adapter,
+    /// Whether `with` is never in a chain without `adapter` either.
+    pub mutual: bool,
+    /// How many of the chains `adapter` is in.
+    pub chains: usize,
+}
+
+/// Items of equipment that are never used without some other item: for each of the pairs of
+/// threads, the chains with the fewest adapters between them (and no more than `max_len` adapters,
+/// if given) are looked at, and an item that's in some of those but never without another item is
+/// reported with it. A pair of items that are each never without the other is reported once, as
+/// `mutual`. Copies of an item count as the same item. Mutual pairs come first, then by how many
+/// chains, most first.
+///
+/// Pairs that one adapter connects on its own are left out, since they say nothing about what's
+/// used with what. Only the shortest chains for each pair are looked at, so this doesn't blow up
+/// with the number of ways round; `max_states` limits each search, as in `SearchOptions`.
+pub fn always_together(
+    pairs: &[(Thread, Thread)],
+    equipment: &[Adapter],
+    max_len: Option<usize>,
+    max_states: Option<usize>,
+) -> (Vec<Together>, SearchStats) {
+    let mut items: Vec<&Adapter> = vec![];
+    for a in equipment {
+        if !items.iter().any(|b| a.is_same_item(b)) {
+            items.push(a);
+        }
+    }
+    let options = SearchOptions { max_states, ..SearchOptions::default() };
+    let mut stats = SearchStats::default();
+    // How many chains each item is in, and each pair of items.
+    let mut alone = vec![0; items.len()];
+    let mut both = vec![vec![0; items.len()]; items.len()];
+    for &(a, b) in pairs {
+        let (mut chains, search_stats) = make_chain_with_options(a, b, equipment, &options);
+        stats.merge(&search_stats);
+        if chains.is_empty() {
+            // It may only go the other way (see `shortest_chains_for_pairs`).
+            let (reversed, search_stats) = make_chain_with_options(b, a, equipment, &options);
+            stats.merge(&search_stats);
+            chains = reversed;
+        }
+        let Some(fewest) = chains.iter().map(|chain| chain.adapters().len()).min() else {
+            continue;
+        };
+        if fewest < 2 || max_len.is_some_and(|max| fewest > max) {
+            continue;
+        }
+        for chain in chains.iter().filter(|chain| chain.adapters().len() == fewest) {
+            let used = (0..items.len())
+                .filter(|&i| chain.adapters().iter().any(|a| a.is_same_item(items[i])))
+                .collect::<Vec<_>>();
+            for &i in &used {
+                alone[i] += 1;
+                for &j in &used {
+                    both[i][j] += 1;
+                }
+            }
+        }
+    }
+
+    let mut results = vec![];
+    for i in 0..items.len() {
+        for j in 0..items.len() {
+            if i == j || alone[i] == 0 || both[i][j] != alone[i] {
+                continue;
+            }
+            let mutual = both[j][i] == alone[j];
+            // Mutual pairs once, the first way round.
+            if mutual && j < i {
+                continue;
+            }
+            results.push(Together {
+                adapter: items[i].clone(),
+                with: items[j].clone(),
+                mutual,
+                chains: alone[i],
+            });
+        }
+    }
+    results.sort_by_key(|t| (!t.mutual, Reverse(t.chains)));
+    (results, stats)
+}
+
+/// Names of threads on the equipment that start with `prefix`, in sorted order. A name that
+/// matches exactly wins: if there is one, it's the only result.
+pub fn threads_with_prefix(prefix: &str, equipment: &[Adapter]) -> Vec<&'static str> {
+    let names = equipment.iter()
+        .flat_map(|a| [a.0.name(), a.1.name()])
+        .filter(|name| name.starts_with(prefix))
+        .collect::<BTreeSet<_>>();
+    match names.get(prefix) {
+        Some(exact) => vec![*exact],
+        None => names.into_iter().collect(),
+    }
+}
+
+/// Like `make_chain_with_options`, but `end`'s name is a prefix (see `threads_with_prefix`), and
+/// chains are found to every matching thread, keeping `end`'s gender. Results are grouped by the
+/// thread they reach.
+pub fn make_chain_to_prefix(
+    start: Thread,
+    end: Thread,
+    equipment: &[Adapter],
+    options: &SearchOptions,
+) -> (Vec<(Thread, Vec<Chain>)>, SearchStats) {
+    let mut stats = SearchStats::default();
+    let groups = threads_with_prefix(end.name(), equipment)
+        .into_iter()
+        .map(|name| {
+            let end = end.renamed(name);
+            let (chains, search_stats) = make_chain_with_options(start, end, equipment, options);
+            stats.merge(&search_stats);
+            (end, chains)
+        })
+        .collect();
+    (groups, stats)
+}
+
+/// Colors for `chains_to_dot`, used in turn.
+const DOT_COLORS: &[&str] = &[
+    "#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b", "#e377c2", "#17becf",
+];
+
+/// A Graphviz DOT graph of several chains at once, to compare them. Each thread size is one node,
+/// shared by every chain that passes through it, and each adapter is an edge between the threads
+/// on its two ends, colored by which chain it's in. Colors repeat after eight chains.
+pub fn chains_to_dot(chains: &[Chain]) -> String {
+    let quote = dot_quote;
+    let mut out = String::from("digraph chains {\n    rankdir=LR;\n    node [shape=box];\n");
+    for (i, chain) in chains.iter().enumerate() {
+        let color = DOT_COLORS[i % DOT_COLORS.len()];
+        for a in chain.adapters() {
+            let label = match &*a.2 {
+                "" => format!("{} -> {}", a.0, a.1),
+                name => name.to_owned(),
+            };
+            out += &format!("    {} -> {} [label={}, color=\"{}\", fontcolor=\"{}\"];\n",
+                quote(a.0.name()), quote(a.1.name()), quote(&label), color, color);
+        }
+    }
+    out += "}\n";
+    out
+}
+
+/// A string as a quoted DOT ID, with any line breaks in it kept.
+fn dot_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+/// Write chains to `w` as a JSON array, the same as serializing a `Vec<Chain>` would but one chain
+/// at a time, so however many there are, only one is held at once. Each chain goes on a line of
+/// its own.
+pub fn write_chains_json(
+    chains: impl Iterator<Item = Chain>,
+    mut w: impl Write,
+) -> io::Result<()> {
+    w.write_all(b"[")?;
+    for (i, chain) in chains.enumerate() {
+        w.write_all(if i == 0 { b"\n  " } else { b",\n  " })?;
+        serde_json::to_writer(&mut w, &chain)?;
+    }
+    w.write_all(b"\n]\n")?;
+    w.flush()
+}
+
+/// For all possible adapters (using threads present on existing equipment), how many new chains do
+/// they make possible if they are added? This includes another copy of each adapter already in
+/// `equipment`, which shows up in the results as an adapter equal to the owned one.
+pub fn find_useful_additions(equipment: &[Adapter]) -> Vec<(Adapter, usize)> {
+    find_useful_additions_with_stats(equipment).0
+}
+
+/// `find_useful_additions`, plus the combined stats of every search it ran.
+pub fn find_useful_additions_with_stats(
+    equipment: &[Adapter],
+) -> (Vec<(Adapter, usize)>, SearchStats) {
+    find_useful_additions_with_limits(equipment, None, None)
+}
+
+/// `find_useful_additions` for when it would take too long: each search gives up after
+/// `max_states` partial chains, and the whole pass stops at the deadline, leaving out any adapters
+/// it hadn't got to. Either makes the results partial, which the stats say (`truncated` and
+/// `timed_out` respectively).
+pub fn find_useful_additions_with_limits(
+    equipment: &[Adapter],
+    max_states: Option<usize>,
+    deadline: Option<Instant>,
+) -> (Vec<(Adapter, usize)>, SearchStats) {
+    find_useful_additions_with_mode(equipment, AdditionMode::Any, max_states, deadline)
+}
+
+/// Every pair of the threads the equipment has (see `mating_threads`) that some chain connects,
+/// each pair once, with the lower thread first. This is what equipment is measured by:
+/// `find_useful_additions` scores an adapter by how many pairs it adds to these.
+pub fn reachable_pairs(equipment: &[Adapter]) -> BTreeSet<(Thread, Thread)> {
+    reachable_pairs_with_limit(equipment, Some(DEFAULT_MAX_STATES)).0
+}
+
+/// `reachable_pairs`, with `max_states` limiting each search, as it does for
+/// `find_useful_additions_with_limits`.
+pub fn reachable_pairs_with_limit(
+    equipment: &[Adapter],
+    max_states: Option<usize>,
+) -> (BTreeSet<(Thread, Thread)>, SearchStats) {
+    let pairs = all_pairs(&mating_threads(equipment), false);
+    let options = SearchOptions { max_states, ..SearchOptions::default() };
+    let mut stats = SearchStats::default();
+    let pairs = pairs.iter().map(|a| (a.0, a.1));
+    let reachable = connected_pairs(pairs, equipment, &options, &mut stats)
+        .into_iter()
+        .map(|(a, b)| if a <= b { (a, b) } else { (b, a) })
+        .collect();
+    (reachable, stats)
+}
+
+/// A pair of the equipment's threads that no chain connects (see `find_gaps`).
+#[derive(Debug, Clone)]
+pub struct Gap {
+    pub start: Thread,
+    pub end: Thread,
+    /// The simplest thing to buy to connect them (the first `suggest_for` gives), with the chain
+    /// it makes, or `None` if it'd take more adapters than the search allowed.
+    pub fix: Option<(Vec<Adapter>, Chain)>,
+}
+
+impl Gap {
+    /// How many adapters it takes to close the gap, if it's known.
+    pub fn distance(&self) -> Option<usize> {
+        self.fix.as_ref().map(|(buy, _)| buy.len())
+    }
+}
+
+/// The complement of `reachable_pairs`: every pair of the equipment's threads that no chain
+/// connects, closest first, each with the simplest fix from buying up to `max_buy` adapters (see
+/// `suggest_for`). Gaps that need more than that come last, with no fix.
+pub fn find_gaps(equipment: &[Adapter], max_buy: usize) -> Vec<Gap> {
+    let reachable = reachable_pairs(equipment);
+    let mut gaps = all_pairs(&mating_threads(equipment), false).into_iter()
+        .map(|a| (a.0, a.1))
+        .filter(|&(a, b)| !reachable.contains(&(a.min(b), a.max(b))))
+        .map(|(start, end)| {
+            let candidates = candidate_adapters(start, end, equipment, false);
+            let fix = suggest_for(start, end, equipment, &candidates, max_buy).into_iter().next();
+            Gap { start, end, fix }
+        })
+        .collect::<Vec<_>>();
+    gaps.sort_by_key(|gap| gap.distance().unwrap_or(usize::MAX));
+    gaps
+}
+
+/// How many pairs of the threads the equipment has are connected by some chain (see
+/// `reachable_pairs`): the baseline that `find_useful_additions` counts new chains against.
+/// `max_states` limits each search, as it does there.
+pub fn count_connected_pairs(
+    equipment: &[Adapter],
+    max_states: Option<usize>,
+) -> (usize, SearchStats) {
+    let (pairs, stats) = reachable_pairs_with_limit(equipment, max_states);
+    (pairs.len(), stats)
+}
+
+/// `gain` as a percentage of `baseline`, or `None` if there's no baseline to compare with.
+pub fn percent_gain(baseline: usize, gain: usize) -> Option<f64> {
+    (baseline != 0).then(|| gain as f64 * 100.0 / baseline as f64)
+}
+
+/// Which adapters `find_useful_additions_with_mode` considers adding.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AdditionMode {
+    /// Any pairing of threads the equipment has.
+    #[default]
+    Any,
+    /// Only step rings between neighboring filter sizes: sizes the equipment has, taken in order,
+    /// each with the next one up (both step-up and step-down). These are the rings that are easy to
+    /// buy, so they make for realistic suggestions. Left-hand threads are left out.
+    FilterSteps,
+}
+
+/// Step rings between each filter size the threads have and the next size up (see
+/// `AdditionMode::FilterSteps`).
+fn filter_step_rings(threads: &BTreeSet<Thread>) -> BTreeSet<Adapter> {
+    let mut sizes = threads.iter()
+        .filter(|t| !t.is_left_handed() && !registry::KNOWN_MOUNTS.contains(&t.name()))
+        .filter_map(|&t| Some((t.diameter()?, t)))
+        .collect::<Vec<_>>();
+    sizes.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+    sizes.dedup_by(|(a, _), (b, _)| a == b);
+    let male = |t: Thread| if t.is_male() { t } else { t.opposite() };
+    sizes.windows(2)
+        .flat_map(|w| {
+            let (small, large) = (male(w[0].1), male(w[1].1));
+            [Adapter::new(small, large.opposite()), Adapter::new(large, small.opposite())]
+        })
+        .collect()
+}
+
+/// The adapters `mode` says to consider, for equipment with these mating threads.
+fn addition_candidates(threads: &BTreeSet<Thread>, mode: AdditionMode) -> BTreeSet<Adapter> {
+    match mode {
+        AdditionMode::Any => all_pairs(threads, false),
+        AdditionMode::FilterSteps => filter_step_rings(threads),
+    }
+}
+
+/// `find_useful_additions_with_limits`, with a choice of which adapters to consider. Either way,
+/// adapters are scored on chains between every pair of threads the equipment has.
+pub fn find_useful_additions_with_mode(
+    equipment: &[Adapter],
+    mode: AdditionMode,
+    max_states: Option<usize>,
+    deadline: Option<Instant>,
+) -> (Vec<(Adapter, usize)>, SearchStats) {
+    let all_threads = mating_threads(equipment);
+    let scored = all_pairs(&all_threads, false);
+    let mut all_adapters = addition_candidates(&all_threads, mode);
+    // One more of something already owned is worth considering too. It's added as a copy of the
+    // owned one, name and all, so it's recognizably the same thing.
+    for owned in equipment.iter().rev() {
+        if mode == AdditionMode::Any || all_adapters.contains(owned) {
+            all_adapters.replace(owned.clone());
+        }
+    }
+    let options = SearchOptions { max_states, ..SearchOptions::default() };
+    let pairs = || scored.iter().map(|a| (a.0, a.1));
+
+    let mut stats = SearchStats::default();
+    let start = count_chains(pairs(), equipment, &options, &mut stats);
+
+    let mut results = vec![];
+    let mut new_equip = equipment.to_vec();
+    for new in &all_adapters {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            stats.timed_out = true;
+            break;
+        }
+        new_equip.push(new.clone());
+        let count = count_chains(pairs(), &new_equip, &options, &mut stats);
+        // A truncated search can find fewer chains with more equipment.
+        results.push((new.clone(), count.saturating_sub(start)));
+        new_equip.pop();
+    }
+
+    results.sort_by_key(|(_a, n)| *n);
+    (results, stats)
+}
+
+/// Which of `mine` would do the most for someone else's equipment, `theirs`, if lent to them: each
+/// is scored as an addition to their equipment, the way `find_useful_additions_with_mode` scores
+/// a purchase, by how many more pairs of their threads it connects. Best first. Anything they
+/// have an equivalent of already (see `Adapter`'s `==`) is left out, and so are second copies of
+/// mine. `max_states` limits each search, as it does there.
+pub fn rank_loans(
+    mine: &[Adapter],
+    theirs: &[Adapter],
+    max_states: Option<usize>,
+) -> (Vec<(Adapter, usize)>, SearchStats) {
+    let scored = all_pairs(&mating_threads(theirs), false);
+    let pairs = || scored.iter().map(|a| (a.0, a.1));
+    let options = SearchOptions { max_states, ..SearchOptions::default() };
+    let mut stats = SearchStats::default();
+    let start = count_chains(pairs(), theirs, &options, &mut stats);
+
+    let mut results = vec![];
+    let mut new_equip = theirs.to_vec();
+    for (i, lent) in mine.iter().enumerate() {
+        if theirs.contains(lent) || mine[..i].contains(lent) {
+            continue;
+        }
+        new_equip.push(lent.clone());
+        let count = count_chains(pairs(), &new_equip, &options, &mut stats);
+        // A truncated search can find fewer chains with more equipment.
+        results.push((lent.clone(), count.saturating_sub(start)));
+        new_equip.pop();
+    }
+    results.sort_by_key(|(_, n)| Reverse(*n));
+    (results, stats)
+}
+
+/// Replacing an owned adapter with one that connects everything it did, and more (see
+/// `find_swaps`).
+#[derive(Debug, Clone)]
+pub struct Swap {
+    /// What to sell or retire.
+    pub retire: Adapter,
+    pub buy: Adapter,
+    /// Pairs of threads that needed `retire`, and that `buy` still connects.
+    pub preserved: Vec<(Thread, Thread)>,
+    /// Pairs of threads that only get connected after the swap.
+    pub enabled: Vec<(Thread, Thread)>,
+}
+
+/// Owned adapters worth trading in: for each one that some pair of the equipment's threads needs,
+/// the candidates (as `find_useful_additions_with_mode` picks them, leaving out what's owned) that
+/// keep every pair connected with it gone and connect some new ones. Best first, by how many new
+/// pairs. An adapter that nothing needs is better off just retired, so it isn't offered a swap.
+/// The limits work as in `find_useful_additions_with_limits`.
+pub fn find_swaps(
+    equipment: &[Adapter],
+    mode: AdditionMode,
+    max_states: Option<usize>,
+    deadline: Option<Instant>,
+) -> (Vec<Swap>, SearchStats) {
+    let all_threads = mating_threads(equipment);
+    let pairs = all_pairs(&all_threads, false).into_iter()
+        .map(|a| (a.0, a.1))
+        .collect::<Vec<_>>();
+    let candidates = addition_candidates(&all_threads, mode).into_iter()
+        .filter(|a| !equipment.contains(a))
+        .collect::<Vec<_>>();
+    let options = SearchOptions { max_states, ..SearchOptions::default() };
+    let mut stats = SearchStats::default();
+
+    let before = connected_pairs(pairs.iter().copied(), equipment, &options, &mut stats);
+    let unconnected = pairs.iter()
+        .filter(|pair| !before.contains(pair))
+        .copied()
+        .collect::<Vec<_>>();
+
+    let mut swaps = vec![];
+    'retire: for (i, retire) in equipment.iter().enumerate() {
+        // Another of the same is no different.
+        if equipment[..i].iter().any(|a| a.is_same_item(retire)) {
+            continue;
+        }
+        let mut rest = equipment.to_vec();
+        rest.remove(i);
+        let still = connected_pairs(before.iter().copied(), &rest, &options, &mut stats);
+        let needed = before.iter()
+            .filter(|pair| !still.contains(pair))
+            .copied()
+            .collect::<Vec<_>>();
+        if needed.is_empty() {
+            continue;
+        }
+        for buy in &candidates {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                stats.timed_out = true;
+                break 'retire;
+            }
+            rest.push(buy.clone());
+            let kept = connected_pairs(needed.iter().copied(), &rest, &options, &mut stats);
+            if kept.len() == needed.len() {
+                let enabled =
+                    connected_pairs(unconnected.iter().copied(), &rest, &options, &mut stats);
+                if !enabled.is_empty() {
+                    swaps.push(Swap {
+                        retire: retire.clone(),
+                        buy: buy.clone(),
+                        preserved: needed.clone(),
+                        enabled,
+                    });
+                }
+            }
+            rest.pop();
+        }
+    }
+
+    swaps.sort_by_key(|swap| Reverse(swap.enabled.len()));
+    (swaps, stats)
+}
+
+/// For each candidate thread not yet in the equipment (say, the mount of a lens you're thinking of
+/// buying), how many new chains open up if you get it along with the single most useful adapter
+/// between it and existing gear?
+pub fn find_useful_threads(
+    equipment: &[Adapter],
+    candidate_threads: &[Thread],
+) -> Vec<(Thread, usize)> {
+    find_useful_threads_with_stats(equipment, candidate_threads).0
+}
+
+/// `find_useful_threads`, plus the combined stats of every search it ran.
+pub fn find_useful_threads_with_stats(
+    equipment: &[Adapter],
+    candidate_threads: &[Thread],
+) -> (Vec<(Thread, usize)>, SearchStats) {
+    let existing = mating_threads(equipment);
+    let options = SearchOptions::default();
+    let mut stats = SearchStats::default();
+
+    let mut results = vec![];
+    let mut new_equip = equipment.to_vec();
+    for &new in candidate_threads {
+        let mut all_threads = existing.clone();
+        all_threads.insert(new);
+        let all_adapters = all_pairs(&all_threads, false);
+        let pairs = || all_adapters.iter().map(|a| (a.0, a.1));
+        let start = count_chains(pairs(), equipment, &options, &mut stats);
+
+        let mut best = 0;
+        for &other in &existing {
+            new_equip.push(Adapter::new(new.opposite(), other));
+            let count = count_chains(pairs(), &new_equip, &options, &mut stats);
+            best = best.max(count.saturating_sub(start));
+            new_equip.pop();
+        }
+        results.push((new, best));
+    }
+
+    results.sort_by_key(|(_t, n)| *n);
+    (results, stats)
+}
+
+/// Every thread that something could be attached to the equipment by.
+pub fn mating_threads(equipment: &[Adapter]) -> BTreeSet<Thread> {
+    equipment.iter()
+        .flat_map(|adapter| <[Thread; 2]>::from(adapter.mating_ends()))
+        .collect()
+}
+
+/// Every pair of the given threads, as adapters (so reversed pairs count once).
+fn all_pairs(threads: &BTreeSet<Thread>, allow_mixed_handedness: bool) -> BTreeSet<Adapter> {
+    threads.iter()
+        .flat_map(|a| {
+            threads.iter()
+                .map(|b| Adapter::new(*a, *b))
+        })
+        .filter(|a| allow_mixed_handedness || !a.mixes_handedness())
+        // Clamps are only ever used, not suggested.
+        .filter(|a| a.0.range().is_none() && a.1.range().is_none())
+        .collect()
+}
+
+/// Which of the candidate adapters would do the most for a whole setup: each one is scored by how
+/// many (body, target) pairs it would newly connect, where `bodies` are the threads chains start
+/// from (camera mounts, say) and `targets` the threads they end at (lenses, filters). Best first;
+/// candidates that connect nothing new score zero and come last.
+pub fn best_next_purchase(
+    bodies: &[Thread],
+    targets: &[Thread],
+    equipment: &[Adapter],
+    candidates: &[Adapter],
+) -> Vec<(Adapter, usize)> {
+    let pairs = || {
+        bodies.iter().flat_map(|&body| targets.iter().map(move |&target| (body, target)))
+    };
+    let options = SearchOptions::default();
+    let mut stats = SearchStats::default();
+    let start = count_chains(pairs(), equipment, &options, &mut stats);
+
+    let mut results = vec![];
+    let mut new_equip = equipment.to_vec();
+    for candidate in candidates {
+        new_equip.push(candidate.clone());
+        let count = count_chains(pairs(), &new_equip, &options, &mut stats);
+        results.push((candidate.clone(), count.saturating_sub(start)));
+        new_equip.pop();
+    }
+
+    results.sort_by_key(|(_a, n)| Reverse(*n));
+    results
+}
+
+/// Pairs of candidate adapters that connect nothing new on their own, but do together: two halves
+/// of a bridge to nowhere, say. Each pair comes with how many more pairs of threads (among those
+/// the equipment can attach to) it connects. Best first.
+pub fn find_synergistic_pairs(
+    equipment: &[Adapter],
+    candidates: &[Adapter],
+) -> Vec<(Adapter, Adapter, usize)> {
+    let all_adapters = all_pairs(&mating_threads(equipment), false);
+    let pairs = || all_adapters.iter().map(|a| (a.0, a.1));
+    let options = SearchOptions::default();
+    let mut stats = SearchStats::default();
+    let start = count_chains(pairs(), equipment, &options, &mut stats);
+
+    let mut new_equip = equipment.to_vec();
+    let useless = candidates.iter()
+        .filter(|candidate| {
+            new_equip.push((*candidate).clone());
+            let count = count_chains(pairs(), &new_equip, &options, &mut stats);
+            new_equip.pop();
+            count == start
+        })
+        .collect::<Vec<_>>();
+
+    let mut results = vec![];
+    for (i, a) in useless.iter().enumerate() {
+        for b in &useless[i + 1..] {
+            new_equip.push((*a).clone());
+            new_equip.push((*b).clone());
+            let count = count_chains(pairs(), &new_equip, &options, &mut stats);
+            if count > start {
+                results.push(((*a).clone(), (*b).clone(), count - start));
+            }
+            new_equip.truncate(equipment.len());
+        }
+    }
+
+    results.sort_by_key(|(_a, _b, n)| Reverse(*n));
+    results
+}
+
+/// Adapters worth considering buying to connect `start` to `end`: every pairing of threads that
+/// could attach to the equipment or to either end, leaving out what's already there. Adapters
+/// between left- and right-hand threads of the same size are left out too, unless
+/// `allow_mixed_handedness` says otherwise.
+pub fn candidate_adapters(
+    start: Thread,
+    end: Thread,
+    equipment: &[Adapter],
+    allow_mixed_handedness: bool,
+) -> Vec<Adapter> {
+    let mut threads = mating_threads(equipment);
+    threads.insert(start.opposite());
+    threads.insert(end.opposite());
+    all_pairs(&threads, allow_mixed_handedness).into_iter()
+        .filter(|a| !equipment.contains(a))
+        .collect()
+}
+
+/// The smallest sets of candidate adapters that, bought together, would connect `start` to `end`,
+/// each with the best chain (by `Chain::score`) it makes possible. Sets of up to `max_purchases`
+/// are tried, smallest first, stopping at the first size that works; results are ordered by the
+/// score of their chain. Empty if nothing needs buying, or nothing small enough would do.
+///
+/// To keep the number of combinations down, each set has to include something that fits onto
+/// what's already reachable from `start`, and something that leads onto what can already reach
+/// `end`.
+pub fn suggest_for(
+    start: Thread,
+    end: Thread,
+    equipment: &[Adapter],
+    candidates: &[Adapter],
+    max_purchases: usize,
+) -> Vec<(Vec<Adapter>, Chain)> {
+    if shortest_chain(start, end, equipment).is_some() {
+        return vec![];
+    }
+
+    // `reachable(end)` is in terms of the reversed chain: a thread exposed going backwards is
+    // mated by the thread exposed at the same point going forwards.
+    let options = SearchOptions::default();
+    let fits_onto = |a: &Adapter, threads: &HashSet<Thread>| {
+        threads.iter().any(|&t| a.oriented_after(t, &options).is_some())
+    };
+    let forward = reachable(start, equipment);
+    let backward = reachable(end, equipment);
+
+    // Nothing can be useful unless it's reachable from both ends with every candidate available.
+    let mut everything = equipment.to_vec();
+    everything.extend_from_slice(candidates);
+    let (forward_all, backward_all) = (reachable(start, &everything), reachable(end, &everything));
+    let candidates = candidates.iter()
+        .filter(|a| fits_onto(a, &forward_all) && fits_onto(a, &backward_all))
+        .collect::<Vec<_>>();
+
+    for size in 1..=max_purchases {
+        let mut results = vec![];
+        for_each_combination(candidates.len(), size, &mut |picked| {
+            let picked = picked.iter().map(|&i| candidates[i].clone()).collect::<Vec<_>>();
+            if !picked.iter().any(|a| fits_onto(a, &forward))
+                || !picked.iter().any(|a| fits_onto(a, &backward))
+            {
+                return;
+            }
+            let mut new_equip = equipment.to_vec();
+            new_equip.extend_from_slice(&picked);
+            // Cheap check first: most sets won't connect anything.
+            if shortest_chain(start, end, &new_equip).is_none() {
+                return;
+            }
+            let options = SearchOptions::default();
+            if let (Some(chain), _stats) = best_chain(start, end, &new_equip, &options, None) {
+                results.push((picked, chain));
+            }
+        });
+        if !results.is_empty() {
+            results.sort_by(|(_, a), (_, b)| a.score().total_cmp(&b.score()));
+            return results;
+        }
+    }
+    vec![]
+}
+
+/// Catalogs with up to this many useful items are planned exactly by `plan_purchases`; bigger ones
+/// are planned greedily.
+pub const EXACT_PLAN_LIMIT: usize = 16;
+
+/// The most items `plan_purchases` will consider buying at once, for a want that no single item
+/// would connect, when it's planning greedily.
+const GREEDY_PLAN_SET: usize = 3;
+
+/// What to buy so that every wanted connection can be made (see `plan_purchases`).
+#[derive(Debug, Clone)]
+pub struct PurchasePlan {
+    /// Each one is bought once, however many wants it serves.
+    pub buy: Vec<Adapter>,
+    pub cost: f64,
+    /// The shortest chain for each want that can be connected, using what's bought.
+    pub chains: Vec<(Thread, Thread, Chain)>,
+    /// Wants that nothing in the catalog connects.
+    pub impossible: Vec<(Thread, Thread)>,
+    /// Whether nothing cheaper would do. Greedy plans can't promise that.
+    pub optimal: bool,
+}
+
+/// The cheapest set of adapters from a catalog that, added to the equipment, lets every one of the
+/// wanted connections be made. Something bought for one want counts once toward the cost, however
+/// many others it helps with. Catalog items are priced by `Info::price`; ones without a price, or
+/// that are already in the equipment, aren't considered.
+///
+/// This is a set cover, solved exactly when at most `EXACT_PLAN_LIMIT` catalog items could help
+/// with any of the wants. Otherwise it's greedy: it keeps buying whichever set of items (found by
+/// `suggest_for`) connects the most wants for what it costs. Greedy plans can cost more than they
+/// need to, and give up on wants that need more than a few items bought together, which are then
+/// listed as impossible.
+pub fn plan_purchases(
+    wants: &[(Thread, Thread)],
+    equipment: &[Adapter],
+    catalog: &[Adapter],
+) -> PurchasePlan {
+    let price = |a: &Adapter| a.3.price.unwrap_or(0.);
+    let connects = |(a, b): (Thread, Thread), equipment: &[Adapter]| {
+        shortest_chain(a, b, equipment).is_some()
+    };
+    let with = |bought: &[Adapter]| [equipment, bought].concat();
+    let catalog = catalog.iter()
+        .filter(|a| a.3.price.is_some() && !equipment.contains(a))
+        .cloned()
+        .collect::<Vec<_>>();
+    let everything = with(&catalog);
+    let (needed, impossible): (Vec<_>, Vec<_>) = wants.iter()
+        .copied()
+        .filter(|&want| !connects(want, equipment))
+        .partition(|&want| connects(want, &everything));
+    // As in `suggest_for`, only items that fit onto what could be reached from both ends matter.
+    let options = SearchOptions::default();
+    let useful = catalog.iter()
+        .filter(|a| needed.iter().any(|&(start, end)| {
+            let fits_onto = |threads: HashSet<Thread>| {
+                threads.iter().any(|&t| a.oriented_after(t, &options).is_some())
+            };
+            fits_onto(reachable(start, &everything)) && fits_onto(reachable(end, &everything))
+        }))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let mut plan = PurchasePlan {
+        buy: vec![],
+        cost: 0.,
+        chains: vec![],
+        impossible,
+        optimal: useful.len() <= EXACT_PLAN_LIMIT,
+    };
+    if plan.optimal {
+        let mut best: Option<(f64, usize, Vec<Adapter>)> = None;
+        for mask in 0..1_u32 << useful.len() {
+            let picked = (0..useful.len())
+                .filter(|i| mask & 1 << i != 0)
+                .map(|i| useful[i].clone())
+                .collect::<Vec<_>>();
+            let cost = picked.iter().map(price).sum::<f64>();
+            // Ties go to buying fewer things.
+            if best.as_ref().is_some_and(|(c, n, _)| (*c, *n) <= (cost, picked.len())) {
+                continue;
+            }
+            let equipment = with(&picked);
+            if needed.iter().all(|&want| connects(want, &equipment)) {
+                best = Some((cost, picked.len(), picked));
+            }
+        }
+        plan.buy = best.map(|(_, _, picked)| picked).unwrap_or_default();
+    } else {
+        let mut unmet = needed.clone();
+        while !unmet.is_empty() {
+            let equipment = with(&plan.buy);
+            let candidates = useful.iter()
+                .filter(|a| !plan.buy.contains(a))
+                .cloned()
+                .collect::<Vec<_>>();
+            // The best value set of purchases that connects any of the wants still unmet.
+            let mut best: Option<(Vec<Adapter>, f64, usize)> = None;
+            for &(a, b) in &unmet {
+                for (set, _) in suggest_for(a, b, &equipment, &candidates, GREEDY_PLAN_SET) {
+                    let cost = set.iter().map(price).sum::<f64>();
+                    let equipment = [&equipment[..], &set].concat();
+                    let met = unmet.iter().filter(|&&want| connects(want, &equipment)).count();
+                    let better = best.as_ref().is_none_or(|(_, best_cost, best_met)| {
+                        let (value, best_value) = (met as f64 * best_cost, *best_met as f64 * cost);
+                        value > best_value || value == best_value && cost < *best_cost
+                    });
+                    if better {
+                        best = Some((set, cost, met));
+                    }
+                }
+            }
+            // Every set found connects at least the want it was found for, so this always gets
+            // somewhere.
+            let Some((set, _, _)) = best else {
+                plan.impossible.append(&mut unmet);
+                break;
+            };
+            plan.buy.extend(set);
+            let equipment = with(&plan.buy);
+            unmet.retain(|&want| !connects(want, &equipment));
+        }
+    }
+
+    plan.cost = plan.buy.iter().map(price).sum();
+    let equipment = with(&plan.buy);
+    plan.chains = wants.iter()
+        .filter(|want| !plan.impossible.contains(want))
+        .filter_map(|&(a, b)| Some((a, b, shortest_chain(a, b, &equipment)?)))
+        .collect();
+    plan
+}
+
+/// A cheap set of candidate adapters to buy so that every thread the equipment has (see
+/// `mating_threads`) is connected to every other, making one group out of however many there
+/// are. Threads count as connected when adapters join their names, whatever the genders, so a
+/// gender changer may still be needed to make a particular chain.
+///
+/// This is Kruskal's algorithm: candidates are taken cheapest first, and each is bought if it
+/// joins two groups that aren't joined yet. Using only candidates between threads the equipment
+/// has, that's the cheapest way to join them. Candidates that bring in new threads can make
+/// cheaper bridges, or dearer ones, so it's tried both ways and the cheaper answer wins; anything
+/// bought that turns out not to be needed is dropped again, most expensive first. Even so, it
+/// isn't always the cheapest answer. If the candidates can't join everything, it joins what they
+/// can.
+pub fn connect_all(
+    equipment: &[Adapter],
+    candidates: &[Adapter],
+    cost: impl Fn(&Adapter) -> f64,
+) -> Vec<Adapter> {
+    let syms = mating_threads(equipment).into_iter().map(Thread::sym).collect::<BTreeSet<_>>();
+    let mut candidates = candidates.iter().collect::<Vec<_>>();
+    candidates.sort_by(|a, b| cost(a).total_cmp(&cost(b)));
+    let direct = candidates.iter()
+        .copied()
+        .filter(|a| syms.contains(&a.0.sym()) && syms.contains(&a.1.sym()))
+        .collect::<Vec<_>>();
+    let total = |bought: &[Adapter]| bought.iter().map(&cost).sum::<f64>();
+    let direct = spanning_purchases(equipment, &direct, &syms);
+    let any = spanning_purchases(equipment, &candidates, &syms);
+    if total(&any) < total(&direct) { any } else { direct }
+}
+
+/// `connect_all` with a given set of candidates, cheapest first: Kruskal's algorithm, then
+/// dropping whatever isn't needed to join `syms`.
+fn spanning_purchases(
+    equipment: &[Adapter],
+    candidates: &[&Adapter],
+    syms: &BTreeSet<ThreadSym>,
+) -> Vec<Adapter> {
+    let mut groups = Groups::default();
+    for a in equipment {
+        groups.join(a.0.sym(), a.1.sym());
+    }
+    let mut bought = candidates.iter()
+        .filter(|a| groups.join(a.0.sym(), a.1.sym()))
+        .map(|&a| a.clone())
+        .collect::<Vec<_>>();
+
+    // How many groups the threads that matter are in, given what's bought.
+    let count = |bought: &[Adapter]| {
+        let mut groups = Groups::default();
+        for a in equipment.iter().chain(bought) {
+            groups.join(a.0.sym(), a.1.sym());
+        }
+        syms.iter().map(|&t| groups.find(t)).collect::<HashSet<_>>().len()
+    };
+    let needed = count(&bought);
+    for i in (0..bought.len()).rev() {
+        let without = bought[..i].iter().chain(&bought[i + 1..]).cloned().collect::<Vec<_>>();
+        if count(&without) == needed {
+            bought = without;
+        }
+    }
+    bought
+}
+
+/// Which threads are joined to which, by name (see `connect_all`).
+#[derive(Default)]
+struct Groups(HashMap<ThreadSym, ThreadSym>);
+
+impl Groups {
+    /// The thread standing for the group a thread is in.
+    fn find(&mut self, mut t: ThreadSym) -> ThreadSym {
+        while let Some(&parent) = self.0.get(&t).filter(|&&parent| parent != t) {
+            t = parent;
+        }
+        t
+    }
+
+    /// Put two threads' groups together. Returns whether they were apart before.
+    fn join(&mut self, a: ThreadSym, b: ThreadSym) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.0.insert(a, b);
+        }
+        a != b
+    }
+}
+
+/// The threads on the equipment (either end of anything) that a chain from `start` gets to: it
+/// either leaves them exposed, or leaves exposed something they go onto. In order. Like
+/// `reachable`, this doesn't account for each adapter only being usable once, so it can count a few
+/// threads as reachable that aren't really. `unreachable_from` has the rest.
+pub fn reachable_threads(start: Thread, equipment: &[Adapter]) -> Vec<Thread> {
+    partition_reachable(start, equipment).0
+}
+
+/// The threads on the equipment that no chain from `start` gets to (see `reachable_threads`): the
+/// gear with them is stranded, as far as `start` goes. In order.
+pub fn unreachable_from(start: Thread, equipment: &[Adapter]) -> Vec<Thread> {
+    partition_reachable(start, equipment).1
+}
+
+fn partition_reachable(start: Thread, equipment: &[Adapter]) -> (Vec<Thread>, Vec<Thread>) {
+    let exposed = reachable(start, equipment);
+    equipment.iter()
+        .flat_map(|a| [a.0, a.1])
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .partition(|&t| exposed.iter().any(|&e| e == t || e.mates(t)))
+}
+
+/// Every thread a chain from `start` could leave exposed, `start` included. This doesn't account
+/// for each adapter only being usable once, so it can include a few threads no real chain gets
+/// to; it's for ruling things out.
+fn reachable(start: Thread, equipment: &[Adapter]) -> HashSet<Thread> {
+    let mut seen = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    let options = SearchOptions::default();
+    while let Some(exposed) = queue.pop_front() {
+        for next in equipment.iter().filter_map(|a| a.oriented_after(exposed, &options)) {
+            if seen.insert(next.1) {
+                queue.push_back(next.1);
+            }
+        }
+    }
+    seen
+}
+
+/// Call `f` with each set of `k` distinct indexes below `n`, in increasing order.
+fn for_each_combination(n: usize, k: usize, f: &mut dyn FnMut(&[usize])) {
+    fn recurse(
+        from: usize,
+        n: usize,
+        k: usize,
+        picked: &mut Vec<usize>,
+        f: &mut dyn FnMut(&[usize]),
+    ) {
+        if picked.len() == k {
+            f(picked);
+            return;
+        }
+        for i in from..n {
+            picked.push(i);
+            recurse(i + 1, n, k, picked, f);
+            picked.pop();
+        }
+    }
+    recurse(0, n, k, &mut Vec::with_capacity(k), f);
+}
+
+/// How many of the pairs have at least one chain between them.
+fn count_chains(
+    pairs: impl Iterator<Item=(Thread, Thread)>,
+    equipment: &[Adapter],
+    options: &SearchOptions,
+    stats: &mut SearchStats,
+) -> usize {
+    connected_pairs(pairs, equipment, options, stats).len()
+}
+
+/// The pairs that have at least one chain between them, in the order given.
+fn connected_pairs(
+    pairs: impl Iterator<Item=(Thread, Thread)>,
+    equipment: &[Adapter],
+    options: &SearchOptions,
+    stats: &mut SearchStats,
+) -> Vec<(Thread, Thread)> {
+    pairs.filter(|&(a, b)| {
+            let (chains, search_stats) = make_chain_with_options(a, b, equipment, options);
+            stats.merge(&search_stats);
+            !chains.is_empty()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn m(name: &str) -> Thread {
+        Thread::male(name)
+    }
+
+    fn f(name: &str) -> Thread {
+        Thread::female(name)
+    }
+
+    /// The equipment from `main`'s demo.
+    fn sample() -> Vec<Adapter> {
+        equipment![
+            "EF(M) -> 58(F)",
+            "EF(M) -> LTM(F)",
+            "EF(M) -> M42(F)",
+            "EF(M) -> FD(F)",
+            "58(M) -> 58(M)",
+            "Bay1(M) -> 46mm(F)",
+            "40.5(M) -> 46(F)",
+            "46(M) -> 52(F)",
+            "46(M) -> 77(F)",
+            "52(M) -> 77(F)",
+            "55(M) -> 77(F)",
+            "58(M) -> 77(F)",
+            "62(M) -> 77(F)",
+            "72(M) -> 77(F)",
+            "72(M) -> 52(F)",
+            "58(M) -> 52(F)",
+            "LTM(M) -> 40.5(F)" as "Rodenstock Rodagon 50mm f/2.8" @ Lens,
+            "LTM(M) -> 43(F)" as "Schneider Componon-S 80mm f/4" @ Lens,
+        ]
+    }
+
+    fn strings(chains: &[Chain]) -> Vec<String> {
+        chains.iter().map(Chain::to_string).collect()
+    }
+
+    #[test]
+    fn stats_agree_with_results() {
+        let (chains, stats) = make_chain_with_stats(f("EF"), m("52"), &sample());
+        assert!(!chains.is_empty());
+        assert_eq!(stats.chains_found, chains.len());
+        assert!(stats.states_explored >= stats.chains_found);
+        assert!(stats.max_frontier > 0);
+        assert!(!stats.truncated && !stats.timed_out);
+
+        let small = equipment!["EF(M) -> 58(F)", "58(M) -> 52(F)", "46(M) -> 52(F)"];
+        let (_, mut total) = find_useful_additions_with_stats(&small);
+        assert!(total.states_explored >= total.chains_found);
+        let before = total;
+        total.merge(&stats);
+        assert_eq!(total.states_explored, before.states_explored + stats.states_explored);
+        assert_eq!(total.chains_found, before.chains_found + stats.chains_found);
+        assert_eq!(total.max_frontier, before.max_frontier.max(stats.max_frontier));
+    }
+
+    #[test]
+    fn new_thread_unlocks_several_chains() {
+        let equipment = equipment!["EF(M) -> 58(F)", "58(M) -> 52(F)", "52(M) -> 77(F)"];
+        let (results, stats) = find_useful_threads_with_stats(&equipment, &[m("LTM")]);
+        assert_eq!(results, vec![(m("LTM"), 3)]);
+        assert!(stats.states_explored > 0);
+        assert_eq!(find_useful_threads(&equipment, &[m("LTM")]), results);
+    }
+
+    #[test]
+    fn useful_additions_come_out_in_the_same_order() {
+        let equipment = &sample()[..10];
+        let run = |equipment: &[Adapter]| format!("{:?}", find_useful_additions(equipment));
+        let first = run(equipment);
+        assert_eq!(run(equipment), first);
+
+        let mut shuffled = equipment.to_vec();
+        shuffled.reverse();
+        shuffled.rotate_left(3);
+        shuffled.swap(0, 5);
+        assert_eq!(run(&shuffled), first);
+    }
+
+    #[test]
+    fn collapsed_display_summarizes_step_ring_runs() {
+        let equipment = equipment![
+            "EF(M) -> 40.5(F)" @ MountAdapter,
+            "40.5(M) -> 46(F)" @ StepUp,
+            "46(M) -> 52(F)" @ StepUp,
+            "52(M) -> 77(F)" @ StepUp,
+            "77(M) -> 58(F)" @ StepDown,
+        ];
+        let chains = make_chain(f("EF"), m("58"), &equipment);
+        assert_eq!(chains.len(), 1);
+        let chain = &chains[0];
+        // The step-down at the end isn't part of the run of step-ups.
+        assert_eq!(chain.collapsed_display(), "[start: EF(F)] [EF(M) -> 40.5(F)] \
+            [40.5(M) -> 77(F) (via 3 rings)] [77(M) -> 58(F)] [end: 58(M)] ");
+        // The full detail is still there.
+        assert_eq!(chain.adapters().len(), 5);
+        assert!(chain.to_string().contains("[46(M) -> 52(F)]"));
+
+        // A lens in the middle steps up too, but it isn't a ring, so there's no run either side
+        // of it.
+        let mut equipment = equipment;
+        equipment[2] = Adapter::from_spec("46(M) -> 52(F)").with_category(Some(Category::Lens));
+        let chain = &make_chain(f("EF"), m("58"), &equipment)[0];
+        assert_eq!(chain.collapsed_display(), chain.to_string());
+    }
+
+    #[test]
+    fn prefix_matches_several_threads() {
+        let equipment = sample();
+        assert_eq!(threads_with_prefix("5", &equipment), ["52", "55", "58"]);
+        // An exact match is all there is, even though "58" starts with "5" too.
+        assert_eq!(threads_with_prefix("46", &equipment), ["46"]);
+        assert!(threads_with_prefix("9", &equipment).is_empty());
+
+        let options = SearchOptions::default();
+        let (groups, _) = make_chain_to_prefix(f("EF"), m("5"), &equipment, &options);
+        let ends = groups.iter().map(|(end, _)| *end).collect::<Vec<_>>();
+        assert_eq!(ends, [m("52"), m("55"), m("58")]);
+        for (end, chains) in &groups {
+            assert_eq!(strings(chains), strings(&make_chain(f("EF"), *end, &equipment)));
+        }
+        assert!(!groups[0].1.is_empty());
+        assert!(!groups[2].1.is_empty());
+    }
+
+    #[test]
+    fn mechanical_chain_versus_one_with_a_lens() {
+        let chains = make_chain(f("EF"), m("52"), &sample());
+        let has_lens = |chain: &Chain| chain.to_string().contains("Rodagon");
+        let mechanical = chains.iter().find(|chain| !has_lens(chain)).unwrap();
+        let optical = chains.iter().find(|chain| has_lens(chain)).unwrap();
+        assert!(mechanical.is_purely_mechanical(), "{}", mechanical);
+        assert!(!optical.is_purely_mechanical(), "{}", optical);
+    }
+
+    #[test]
+    fn equal_length_chains_prefer_stepping_up() {
+        // Listed step-down first, so the order isn't just the order they were found in.
+        let equipment = equipment![
+            "EF(M) -> 58(F)",
+            "58(M) -> 52(F)",
+            "EF(M) -> 46(F)",
+            "46(M) -> 52(F)",
+        ];
+        let chains = make_chain(f("EF"), m("52"), &equipment);
+        assert_eq!(strings(&chains), [
+            "[start: EF(F)] [EF(M) -> 46(F)] [46(M) -> 52(F)] [end: 52(M)] ",
+            "[start: EF(F)] [EF(M) -> 58(F)] [58(M) -> 52(F)] [end: 52(M)] ",
+        ]);
+        assert_eq!(chains[0].default_cmp(&chains[1]), Ordering::Less);
+        assert_eq!(chains[1].default_cmp(&chains[0]), Ordering::Greater);
+    }
+
+    #[test]
+    fn display_styles() {
+        let styled = |thread: Thread, style| thread.display_with(style).to_string();
+        assert_eq!(styled(m("58"), ThreadStyle::Suffix), "58(M)");
+        assert_eq!(styled(f("58"), ThreadStyle::Suffix), "58(F)");
+        assert_eq!(styled(m("58"), ThreadStyle::Prefix), "M:58");
+        assert_eq!(styled(f("EF"), ThreadStyle::Prefix), "F:EF");
+        assert_eq!(styled(m("58"), ThreadStyle::Symbol), "58♂");
+        assert_eq!(styled(f("EF"), ThreadStyle::Symbol), "EF♀");
+        // The default is what Display does, and it round-trips.
+        assert_eq!(styled(m("40.5"), ThreadStyle::default()), m("40.5").to_string());
+        assert_eq!("40.5(M)".parse::<Thread>().unwrap(), m("40.5"));
+    }
+
+    #[test]
+    fn start_mating_end_is_the_empty_chain() {
+        let empty = "[start: 58(F)] [end: 58(M)] ";
+        assert_eq!(strings(&make_chain(f("58"), m("58"), &[])), [empty]);
+        // Up to 77mm and back down would do too, but only when asked for.
+        let equipment = equipment!["58(M) -> 77(F)", "77(M) -> 58(F)"];
+        assert_eq!(strings(&make_chain(f("58"), m("58"), &equipment)), [empty]);
+        let options = SearchOptions { loops: true, ..SearchOptions::default() };
+        let (chains, _) = make_chain_with_options(f("58"), m("58"), &equipment, &options);
+        assert_eq!(strings(&chains), [
+            empty,
+            "[start: 58(F)] [58(M) -> 77(F)] [77(M) -> 58(F)] [end: 58(M)] ",
+        ]);
+    }
+
+    #[test]
+    fn same_thread_at_both_ends_needs_a_gender_changer() {
+        assert!(make_chain(f("58"), f("58"), &[]).is_empty());
+        let step_ring = Adapter::new(m("58"), f("52"));
+        assert!(make_chain(f("58"), f("58"), &[step_ring]).is_empty());
+        assert_eq!(strings(&make_chain(f("58"), f("58"), &sample())),
+            ["[start: 58(F)] [58(M) -> 58(M)] [end: 58(F)] "]);
+    }
+
+    #[test]
+    fn best_next_purchase_connects_the_most_pairs() {
+        let equipment = equipment!["EF(M) -> 58(F)", "FD(M) -> 58(F)"];
+        let candidates = equipment![
+            "FD(M) -> 77(F)",
+            "40.5(M) -> 46(F)",
+            "58(M) -> 77(F)",
+            "EF(M) -> 58(F)",
+        ];
+        let bodies = [f("EF"), f("FD")];
+        let targets = [m("58"), m("77")];
+        let ranked = best_next_purchase(&bodies, &targets, &equipment, &candidates);
+        let ranked = ranked.iter().map(|(a, n)| (a.to_string(), *n)).collect::<Vec<_>>();
+        assert_eq!(ranked, [
+            // Both bodies to 77mm.
+            ("58(M) -> 77(F)".to_owned(), 2),
+            ("FD(M) -> 77(F)".to_owned(), 1),
+            ("40.5(M) -> 46(F)".to_owned(), 0),
+            // Another copy of what's there already changes nothing.
+            ("EF(M) -> 58(F)".to_owned(), 0),
+        ]);
+    }
+
+    /// The search as it was before it backtracked: every partial chain is a state of its own,
+    /// with its own copy of the chain and of the used set. It makes the same checks in the same
+    /// order as `search`, so the two should agree on everything, stats included.
+    fn cloning_search(
+        start: Thread,
+        end: Thread,
+        equipment: &[Adapter],
+        options: &SearchOptions,
+    ) -> (Vec<Chain>, SearchStats) {
+        struct State {
+            chain: Chain,
+            used: Vec<bool>,
+        }
+
+        let previous_copy = equipment.iter().enumerate()
+            .map(|(i, a)| equipment[..i].iter().rposition(|b| a.is_same_item(b)))
+            .collect::<Vec<_>>();
+        let mut states = vec![State {
+            chain: Chain::new(Adapter::new(NIL_THREAD, start).with_name("start")),
+            used: vec![false; equipment.len()],
+        }];
+        let mut found = vec![];
+        let mut stats = SearchStats { max_frontier: 1, ..SearchStats::default() };
+        if let Some(direct) = options.direct_chain(start, end) {
+            if options.accepts(&direct) {
+                found.push(direct);
+            }
+            if !options.wants_loops() {
+                states.clear();
+            }
+        }
+
+        while let Some(state) = states.pop() {
+            if options.max_states.is_some_and(|max| stats.states_explored >= max) {
+                stats.truncated = true;
+                break;
+            }
+            stats.states_explored += 1;
+            let exposed = state.chain.0.last().unwrap().1;
+            for (i, a) in equipment.iter().enumerate() {
+                if state.used[i] || previous_copy[i].is_some_and(|j| !state.used[j]) {
+                    continue;
+                }
+                let Some(next) = a.oriented_after(exposed, options) else { continue };
+                if !options.can_extend(&state.chain) {
+                    stats.avoid_pruned += 1;
+                    continue;
+                }
+                if !options.allows_junction(&state.chain) {
+                    stats.junction_pruned += 1;
+                    continue;
+                }
+                if !options.can_place(&state.chain, &next) {
+                    continue;
+                }
+                if options.revisits(&state.chain, &next, end) {
+                    stats.repeat_pruned += 1;
+                    continue;
+                }
+                if state.chain.fouls(&next) {
+                    stats.collision_pruned += 1;
+                    continue;
+                }
+                let mut chain = state.chain.clone();
+                chain.0.push(next.clone());
+                if let Some(reached) = options.reached(next.1, end) {
+                    chain.0.push(Adapter::new(reached, NIL_THREAD).with_name("end"));
+                    if options.accepts(&chain) {
+                        found.push(chain);
+                    }
+                } else {
+                    let mut used = state.used.clone();
+                    used[i] = true;
+                    states.push(State { chain, used });
+                }
+            }
+            stats.max_frontier = stats.max_frontier.max(states.len());
+        }
+
+        found.sort_by(Chain::default_cmp);
+        stats.chains_found = found.len();
+        (found, stats)
+    }
+
+    /// Equipment made up from a seed: `count` adapters between threads from a small pool, so
+    /// there are lots of ways to connect things. (A linear congruential generator; it only needs
+    /// to be repeatable, not good.)
+    fn generated(seed: u64, count: usize) -> Vec<Adapter> {
+        const NAMES: &[&str] = &["52", "58", "77", "46", "EF", "LTM"];
+        let mut state = seed;
+        let mut next = |n: usize| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) as usize % n
+        };
+        let mut thread = || {
+            let name = NAMES[next(NAMES.len())];
+            if next(2) == 0 { m(name) } else { f(name) }
+        };
+        (0..count).map(|_| Adapter::new(thread(), thread())).collect()
+    }
+
+    fn assert_same_search(
+        start: Thread,
+        end: Thread,
+        equipment: &[Adapter],
+        options: &SearchOptions,
+    ) {
+        let (chains, stats) = make_chain_with_options(start, end, equipment, options);
+        let (expected, expected_stats) = cloning_search(start, end, equipment, options);
+        let context = || format!("{} to {} with {:?}", start, end, equipment);
+        assert_eq!(strings(&chains), strings(&expected), "{}", context());
+        assert_eq!(stats, expected_stats, "{}", context());
+    }
+
+    #[test]
+    fn backtracking_search_matches_cloning_search_on_sample() {
+        let equipment = sample();
+        let options = SearchOptions::default();
+        let threads = mating_threads(&equipment);
+        for &start in &threads {
+            for &end in &threads {
+                assert_same_search(start, end.opposite(), &equipment, &options);
+            }
+        }
+        let loops = SearchOptions { loops: true, allow_repeats: true, ..SearchOptions::default() };
+        assert_same_search(f("EF"), m("52"), &equipment, &loops);
+    }
+
+    #[test]
+    fn backtracking_search_matches_cloning_search_on_generated_equipment() {
+        let options = SearchOptions { max_states: Some(20_000), ..SearchOptions::default() };
+        for seed in 0..40 {
+            let equipment = generated(seed, 4 + seed as usize % 6);
+            let threads = mating_threads(&equipment);
+            let start = *threads.first().unwrap();
+            for &end in &threads {
+                assert_same_search(start, end, &equipment, &options);
+            }
+        }
+    }
+
+    #[test]
+    fn pinning_ends() {
+        let equipment = sample();
+        let pin = |spec: &str| Some(Adapter::from_spec(spec));
+        let search = |first, last| {
+            strings(&make_chain_with_ends(f("EF"), m("52"), &equipment, first, last))
+        };
+
+        let all = strings(&make_chain(f("EF"), m("52"), &equipment));
+        let first = search(pin("EF(M) -> 58(F)"), None);
+        assert!(!first.is_empty() && first.len() < all.len());
+        assert!(first.iter().all(|chain| chain.starts_with("[start: EF(F)] [EF(M) -> 58(F)] ")));
+
+        let last = search(None, pin("46(M) -> 52(F)"));
+        assert!(!last.is_empty() && last.len() < all.len());
+        assert!(last.iter().all(|chain| chain.ends_with("[46(M) -> 52(F)] [end: 52(M)] ")));
+
+        let both = search(pin("EF(M) -> LTM(F)"), pin("46(M) -> 52(F)"));
+        assert_eq!(both, [
+            "[start: EF(F)] [EF(M) -> LTM(F)] [Rodenstock Rodagon 50mm f/2.8] [40.5(M) -> 46(F)] \
+                [46(M) -> 52(F)] [end: 52(M)] ",
+        ]);
+
+        // Pinned to the wrong end, it can't be first.
+        assert!(search(pin("46(M) -> 52(F)"), None).is_empty());
+    }
+
+    #[test]
+    fn chain_with_reversed_adapter_round_trips() {
+        // The ring only fits the other way round from how it's listed.
+        let equipment = equipment!["EF(M) -> 58(F)", "52(F) -> 58(M)" as "cheap ring"];
+        let chains = make_chain(f("EF"), m("52"), &equipment);
+        assert_eq!(chains.len(), 1);
+        let chain = &chains[0];
+        assert!(chain.adapters()[1].is_reversed());
+
+        let json = serde_json::to_string(chain).unwrap();
+        assert!(json.contains(r#""reversed":true"#), "{}", json);
+        let loaded: Chain = serde_json::from_str(&json).unwrap();
+        assert!(loaded.is_valid());
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", chain));
+        assert_eq!(loaded.to_string(), chain.to_string());
+        assert!(loaded.adapters()[1].is_reversed());
+        assert_eq!(loaded.adapters()[1].name(), "cheap ring (reversed)");
+    }
+
+    #[test]
+    fn synergistic_pair_is_found() {
+        let equipment = equipment!["EF(M) -> 58(F)", "52(M) -> 46(F)"];
+        // The first two only get as far as 77mm on their own, but meet there. The third is useful
+        // on its own, so it isn't part of any pair.
+        let candidates = equipment!["58(M) -> 77(F)", "77(M) -> 52(F)", "58(M) -> 52(F)"];
+        let pairs = find_synergistic_pairs(&equipment, &candidates);
+        assert_eq!(pairs.len(), 1);
+        let (a, b, gain) = &pairs[0];
+        assert_eq!((a.to_string().as_str(), b.to_string().as_str()),
+            ("58(M) -> 77(F)", "77(M) -> 52(F)"));
+        assert!(*gain > 0);
+    }
+
+    #[test]
+    fn tiny_bounds_flag_partial_results() {
+        let equipment = &sample()[..8];
+        let (all, stats) = find_useful_additions_with_limits(equipment, None, None);
+        assert!(!stats.truncated && !stats.timed_out);
+
+        let (capped, stats) = find_useful_additions_with_limits(equipment, Some(1), None);
+        assert!(stats.truncated && !stats.timed_out);
+        assert_eq!(capped.len(), all.len());
+
+        let deadline = Some(Instant::now());
+        let (timed, stats) = find_useful_additions_with_limits(equipment, None, deadline);
+        assert!(stats.timed_out && !stats.truncated);
+        assert!(timed.len() < all.len());
+    }
+
+    #[test]
+    fn flipping_twice_restores_the_original() {
+        let ring = Adapter::new(m("52"), f("58"));
+        for original in [ring.clone(), ring.with_name("ring")] {
+            let mut adapter = original.clone();
+            adapter.flip_in_place();
+            assert_eq!(adapter.threads(), (f("58"), m("52")));
+            assert!(adapter.is_reversed());
+            assert_eq!(format!("{:?}", adapter), format!("{:?}", original.clone().reverse()));
+            adapter.flip_in_place();
+            assert_eq!(format!("{:?}", adapter), format!("{:?}", original));
+        }
+    }
+
+    #[test]
+    fn fewest_threads_can_take_more_adapters() {
+        let equipment = equipment![
+            "EF(M) -> M42(F)",
+            "M42(M) -> 52(F)",
+            "EF(M) -> EF(M)",
+            "EF(F) -> 52(M)",
+            "52(F) -> 52(F)",
+        ];
+        let shortest = shortest_chain(f("EF"), m("52"), &equipment).unwrap();
+        assert_eq!(shortest.adapters().len(), 2);
+        let fewest = fewest_threads_chain(f("EF"), m("52"), &equipment).unwrap();
+        assert_eq!(fewest.adapters().len(), 3);
+        let names: HashSet<_> = fewest.thread_path().iter().map(|t| t.name()).collect();
+        assert_eq!(names.len(), 2);
+    }
+
+    #[test]
+    fn gender_letters_parse_in_either_case() {
+        assert_eq!("EF(m)".parse(), Ok(m("EF")));
+        assert_eq!("EF(M)".parse(), Ok(m("EF")));
+        assert_eq!("EF(f)".parse(), Ok(f("EF")));
+        assert_eq!("EF(F)".parse(), Ok(f("EF")));
+        assert_eq!("EF(x)".parse::<Thread>(), Err(ParseThreadError("EF(x)".to_owned())));
+    }
+
+    #[test]
+    fn shortest_chain_can_use_a_second_copy() {
+        // Up to 58mm, flip the gender, and back down with another of the same ring.
+        let ring = Adapter::new(m("52"), f("58"));
+        let equipment = [ring.clone(), Adapter::new(m("58"), m("58")), ring];
+        let chain = shortest_chain(f("52"), f("52"), &equipment).unwrap();
+        assert_eq!(chain.to_string(),
+            "[start: 52(F)] [52(M) -> 58(F)] [58(M) -> 58(M)] [58(F) -> 52(M)] [end: 52(F)] ");
+        assert!(shortest_chain(f("52"), f("52"), &equipment[..2]).is_none());
+    }
+
+    #[test]
+    fn best_chain_can_use_a_second_copy() {
+        let ring = Adapter::new(m("52"), f("58"));
+        let equipment = [ring.clone(), Adapter::new(m("58"), m("58")), ring];
+        let options = SearchOptions::default();
+        let (best, stats) = best_chain(f("52"), f("52"), &equipment, &options, None);
+        assert_eq!(best.unwrap().to_string(),
+            "[start: 52(F)] [52(M) -> 58(F)] [58(M) -> 58(M)] [58(F) -> 52(M)] [end: 52(F)] ");
+        assert_eq!(stats.best_score, Some(3.5));
+    }
+
+    #[test]
+    fn instructions_call_out_reversed_items() {
+        let equipment = equipment![
+            "EF(M) -> 40.5(M)",
+            "LTM(M) -> 40.5(F)" as "Rodagon" @ Lens,
+        ];
+        let chains = make_chain(f("EF"), f("LTM"), &equipment);
+        let steps = chains[0].instructions();
+        let steps: Vec<_> = steps.lines().collect();
+        assert_eq!(steps, [
+            "1. Fit the EF(M) side of the EF(M) -> 40.5(M) adapter into the EF(F) you're \
+             starting from.",
+            "2. Screw the 40.5(M) side of the EF(M) -> 40.5(M) adapter into the 40.5(F) side of \
+             the Rodagon. The Rodagon goes on backwards, with its 40.5(F) side toward the start.",
+            "3. Fit the LTM(M) side of the Rodagon into the LTM(F) you're ending at.",
+        ]);
+
+        // Unnamed adapters are described as listed, even when they go on the other way round.
+        let equipment = equipment!["EF(M) -> 52(M)", "58(M) -> 52(F)" @ StepDown];
+        let chains = make_chain(f("EF"), f("58"), &equipment);
+        let steps = chains[0].instructions();
+        assert_eq!(steps.lines().nth(1), Some(
+            "2. Screw the 52(M) side of the EF(M) -> 52(M) adapter into the 52(F) side of the \
+             58(M) -> 52(F) step-down ring. The 58(M) -> 52(F) step-down ring goes on backwards, \
+             with its 52(F) side toward the start."
+        ));
+    }
+
+    #[test]
+    fn lenses_couple_through_a_same_gender_coupler() {
+        let equipment = sample();
+        let chains = couple_lenses(f("58"), f("58"), &equipment);
+        assert_eq!(strings(&chains), ["[start: 58(F)] [58(M) -> 58(M)] [end: 58(F)] "]);
+
+        let without_coupler: Vec<_> = equipment.into_iter()
+            .filter(|a| a.threads() != (m("58"), m("58")))
+            .collect();
+        assert!(couple_lenses(f("58"), f("58"), &without_coupler).is_empty());
+    }
+
+    #[test]
+    fn long_linear_chain_neither_overflows_nor_runs_away() {
+        // `n` adapters in a line, each going onto the one before, and the ends of the line.
+        let line = |n: usize| {
+            let thread = |i: usize| format!("stress{}-{}", n, i);
+            let equipment = (0..n)
+                .map(|i| Adapter::new(m(&thread(i)), f(&thread(i + 1))))
+                .collect::<Vec<_>>();
+            (f(&thread(0)), m(&thread(n)), equipment)
+        };
+
+        // Far too deep for recursion, but found in full when there's no cap.
+        let (start, end, equipment) = line(2_000);
+        let options = SearchOptions { max_states: None, ..SearchOptions::default() };
+        let (chains, stats) = make_chain_with_options(start, end, &equipment, &options);
+        assert_eq!(chains.len(), 1);
+        assert_eq!(chains[0].adapters().len(), 2_000);
+        assert!(!stats.truncated);
+
+        // With a small cap, it gives up promptly and says so.
+        let (start, end, equipment) = line(20_000);
+        let options = SearchOptions { max_states: Some(50), ..SearchOptions::default() };
+        let begun = Instant::now();
+        let (chains, stats) = make_chain_with_options(start, end, &equipment, &options);
+        assert!(begun.elapsed() < Duration::from_secs(10));
+        assert!(chains.is_empty());
+        assert!(stats.truncated);
+        assert_eq!(stats.states_explored, 50);
+    }
+
+    #[test]
+    fn joints_show_both_genders() {
+        let equipment = equipment!["EF(M) -> 58(F)", "58(M) -> 52(F)"];
+        let chains = make_chain(f("EF"), m("52"), &equipment);
+        assert_eq!(chains[0].joints_display(),
+            "[start: EF(F)] EF(F)|EF(M) [EF(M) -> 58(F)] 58(F)|58(M) [58(M) -> 52(F)] \
+             52(F)|52(M) [end: 52(M)] ");
+        // The plain display is unchanged.
+        assert_eq!(chains[0].to_string(),
+            "[start: EF(F)] [EF(M) -> 58(F)] [58(M) -> 52(F)] [end: 52(M)] ");
+    }
+
+    #[test]
+    fn assembly_order_follows_the_chain() {
+        let equipment = equipment!["EF(M) -> 58(F)", "58(M) -> 52(F)", "52(M) -> 77(F)"];
+        let chain = &make_chain(f("EF"), m("77"), &equipment)[0];
+        let threads = |order: Vec<&Adapter>| order.iter().map(|a| a.threads()).collect::<Vec<_>>();
+        let body_first = [(m("EF"), f("58")), (m("58"), f("52")), (m("52"), f("77"))];
+        assert_eq!(threads(chain.assembly_order()), body_first);
+        let mut lens_first = body_first;
+        lens_first.reverse();
+        assert_eq!(threads(chain.assembly_order_reversed()), lens_first);
+    }
+
+    #[test]
+    fn diameter_timeline_of_a_mixed_chain() {
+        let equipment = equipment![
+            "EF(M) -> 58(F)",
+            "58(M) -> 52(F)",
+            "52(M) -> M42(F)",
+            "M42(M) -> 40.5(F)",
+        ];
+        let chain = &make_chain(f("EF"), m("40.5"), &equipment)[0];
+        // The start and end threads aren't junctions, so EF and 40.5 aren't in it.
+        assert_eq!(chain.diameter_timeline(), [Some(58.), Some(52.), None]);
+    }
+
+    #[test]
+    fn each_chain_gets_its_own_dot_color() {
+        let equipment = equipment!["EF(M) -> 58(F)", "58(M) -> 52(F)", "EF(M) -> 52(F)"];
+        let chains = make_chain(f("EF"), m("52"), &equipment);
+        assert_eq!(chains.len(), 2);
+        let dot = chains_to_dot(&chains);
+        let color_of = |label: &str| {
+            let line = dot.lines().find(|line| line.contains(label)).unwrap();
+            line.split("color=").nth(1).unwrap().split(',').next().unwrap().to_owned()
+        };
+        let (one, other) = (color_of("\"EF(M) -> 52(F)\""), color_of("\"EF(M) -> 58(F)\""));
+        assert_ne!(one, other);
+        assert_eq!(color_of("\"58(M) -> 52(F)\""), color_of("\"EF(M) -> 58(F)\""));
+        // Threads are shared nodes: the 52 both chains end at is the same one.
+        assert_eq!(dot.matches("-> \"52\"").count(), 2);
+    }
+
+    #[test]
+    fn left_hand_threads_only_mate_left_hand_ones() {
+        assert_eq!("58LH(M)".parse(), Ok(m("58LH")));
+        assert!(m("58LH").is_left_handed() && !m("58").is_left_handed());
+        assert_eq!(m("58LH").diameter(), Some(58.));
+        assert!(m("58LH").mates(f("58LH")));
+        assert!(!m("58LH").mates(f("58")));
+
+        assert!(Adapter::new(m("58LH"), f("58")).mixes_handedness());
+        assert!(!Adapter::new(m("58LH"), f("52LH")).mixes_handedness());
+        assert!(!Adapter::new(m("58LH"), f("52")).mixes_handedness());
+    }
+
+    #[test]
+    fn clamps_grip_sizes_but_not_each_other() {
+        assert_eq!(m("49-58").range(), Some((49., 58.)));
+        assert!(m("49-58").mates(f("52")));
+        assert!(f("52").mates(m("49-58")));
+        assert!(m("49-58").mates(f("58")));
+        assert!(!m("49-58").mates(f("62")));
+        assert!(!m("49-58").mates(f("49-58")));
+        assert!(!f("49-58").mates(m("52-77")));
+
+        // Two clamps, each on a coupler, don't make a chain between them.
+        let equipment = equipment!["62(M) -> 49-58(F)", "49-58(M) -> 77(F)"];
+        assert!(make_chain(f("62"), m("77"), &equipment).is_empty());
+        let equipment = equipment!["62(M) -> 49-58(F)", "52(M) -> 77(F)"];
+        let chains = make_chain(f("62"), m("77"), &equipment);
+        assert_eq!(strings(&chains), [
+            "[start: 62(F)] [62(M) -> 49-58(F)] [52(M) -> 77(F)] [end: 77(M)] ",
+        ]);
+    }
+
+    #[test]
+    fn utilization_ranks_the_workhorses() {
+        let equipment = equipment![
+            "EF(M) -> 58(F)",
+            "58(M) -> 52(F)",
+            "58(M) -> 77(F)",
+            "46(M) -> 52(F)",
+            "EF(M) -> 58(F)",
+        ];
+        let shortest = |end| shortest_chain(f("EF"), end, &equipment).unwrap();
+        let chains = [shortest(m("52")), shortest(m("77"))];
+        let used = adapter_utilization(&chains, &equipment)
+            .into_iter()
+            .map(|(a, count)| (a.to_string(), count))
+            .collect::<Vec<_>>();
+        // Both copies of the mount adapter are one item.
+        assert_eq!(used, [
+            ("EF(M) -> 58(F)".to_owned(), 2),
+            ("58(M) -> 52(F)".to_owned(), 1),
+            ("58(M) -> 77(F)".to_owned(), 1),
+            ("46(M) -> 52(F)".to_owned(), 0),
+        ]);
+    }
+
+    #[test]
+    fn chain_using_an_item_both_ways_round() {
+        // Down to 52, a male-male coupler, and a second copy of the ring back up the other way.
+        let ring = Adapter::new(m("58"), f("52"));
+        let coupler = Adapter::new(m("52"), m("52"));
+        let start = Chain::new(Adapter::new(NIL_THREAD, f("58")).with_name("start"));
+        let chain = start.add(ring.clone()).unwrap()
+            .add(coupler).unwrap()
+            .add(ring.clone()).unwrap();
+        assert_eq!(chain.to_string(),
+            "[start: 58(F)] [58(M) -> 52(F)] [52(M) -> 52(M)] [52(F) -> 58(M)] ");
+        assert!(chain.uses_both_orientations());
+
+        // Two copies the same way round are fine, and so is a different item reversed.
+        let forwards = start.add(ring.clone()).unwrap()
+            .add(Adapter::new(m("52"), f("58"))).unwrap()
+            .add(ring.clone()).unwrap();
+        assert!(!forwards.adapters()[2].is_reversed());
+        assert!(!forwards.uses_both_orientations());
+        let other = Adapter::new(m("58"), f("52")).with_name("another ring");
+        let chain = start.add(ring).unwrap()
+            .add(Adapter::new(m("52"), m("52"))).unwrap()
+            .add(other).unwrap();
+        assert!(chain.adapters()[2].is_reversed());
+        assert!(!chain.uses_both_orientations());
+    }
+
+    #[test]
+    fn weights_change_the_optimal_chain() {
+        // Straight there with a long, dear adapter, or in two short, cheap steps.
+        let equipment = [
+            Adapter::new(m("EF"), f("52")).with_cost(Some(40.)).with_length(Some(5.)),
+            Adapter::new(m("EF"), f("58")).with_cost(Some(5.)).with_length(Some(20.)),
+            Adapter::new(m("58"), f("52")).with_cost(Some(5.)).with_length(Some(20.)),
+        ];
+        let optimal = |w_cost, w_len| optimal_chain(f("EF"), m("52"), &equipment, w_cost, w_len);
+        let direct = "[start: EF(F)] [EF(M) -> 52(F)] [end: 52(M)] ";
+        let stepped = "[start: EF(F)] [EF(M) -> 58(F)] [58(M) -> 52(F)] [end: 52(M)] ";
+        assert_eq!(optimal(1., 0.).unwrap().to_string(), stepped);
+        assert_eq!(optimal(0., 1.).unwrap().to_string(), direct);
+        assert_eq!(optimal(1., 1.).unwrap().to_string(), direct);
+        // With nothing to weigh, the fewest adapters win.
+        assert_eq!(optimal(0., 0.).unwrap().to_string(), direct);
+    }
+
+    #[test]
+    fn optimal_chain_can_use_a_second_copy() {
+        let ring = Adapter::new(m("52"), f("58"));
+        let equipment = [ring.clone(), Adapter::new(m("58"), m("58")), ring];
+        let chain = optimal_chain(f("52"), f("52"), &equipment, 1., 1.).unwrap();
+        assert_eq!(chain.to_string(),
+            "[start: 52(F)] [52(M) -> 58(F)] [58(M) -> 58(M)] [58(F) -> 52(M)] [end: 52(F)] ");
+        assert!(optimal_chain(f("52"), f("52"), &equipment[..2], 1., 1.).is_none());
+    }
+
+    #[test]
+    fn body_only_thread_is_kept_out_of_the_middle() {
+        let equipment = equipment![
+            "EF(M) -> M42(F)",
+            "M42(M) -> 52(F)",
+            "EF(M) -> 58(F)",
+            "58(M) -> 52(F)",
+        ];
+        let via_m42 = "[start: EF(F)] [EF(M) -> M42(F)] [M42(M) -> 52(F)] [end: 52(M)] ";
+        let via_58 = "[start: EF(F)] [EF(M) -> 58(F)] [58(M) -> 52(F)] [end: 52(M)] ";
+        let search = |start, options: &SearchOptions| {
+            strings(&make_chain_with_options(start, m("52"), &equipment, options).0)
+        };
+        let anywhere = SearchOptions::default();
+        assert_eq!(search(f("EF"), &anywhere), [via_m42, via_58]);
+
+        let body_only = SearchOptions {
+            roles: vec![("M42", ThreadRole::Body)],
+            ..SearchOptions::default()
+        };
+        assert_eq!(search(f("EF"), &body_only), [via_58]);
+        // It can still be where a chain starts.
+        assert_eq!(search(f("M42"), &body_only),
+            ["[start: M42(F)] [M42(M) -> 52(F)] [end: 52(M)] "]);
+    }
+
+    #[test]
+    fn pivot_adapter_has_the_highest_betweenness() {
+        // Two ways to 58 and two ways on from 52, with only the one ring in between.
+        let equipment = equipment![
+            "EF(M) -> M42(F)",
+            "M42(M) -> 58(F)",
+            "EF(M) -> LTM(F)",
+            "LTM(M) -> 58(F)",
+            "58(M) -> 52(F)",
+            "52(M) -> 62(F)",
+            "62(M) -> 77(F)",
+            "52(M) -> 67(F)",
+            "67(M) -> 77(F)",
+            "46(M) -> 77(F)",
+        ];
+        let counts = adapter_betweenness(f("EF"), m("77"), &equipment);
+        let pivot = Adapter::new(m("58"), f("52"));
+        assert_eq!(counts[&pivot], 4);
+        for (a, count) in &counts {
+            match a.threads() {
+                _ if a == &pivot => (),
+                (t, _) if t == m("46") => assert_eq!(*count, 0),
+                _ => assert_eq!(*count, 2, "{}", a),
+            }
+        }
+    }
+
+    #[test]
+    fn filter_steps_only_proposes_neighboring_step_rings() {
+        let equipment = equipment![
+            "EF(M) -> 52(F)",
+            "46(M) -> 58(F)",
+            "LTM(M) -> 40.5(F)" @ Lens,
+            "77(M) -> M42(F)",
+        ];
+        let (results, _) =
+            find_useful_additions_with_mode(&equipment, AdditionMode::FilterSteps, None, None);
+        let proposed = results.iter().map(|(a, _)| a.threads()).collect::<BTreeSet<_>>();
+        // 40.5, 46, 52, 58, and 77, each with the next size up, both ways.
+        let sizes = ["40.5", "46", "52", "58", "77"];
+        let expected = sizes.windows(2)
+            .flat_map(|w| [(m(w[0]), f(w[1])), (m(w[1]), f(w[0]))])
+            .collect::<BTreeSet<_>>();
+        assert_eq!(proposed, expected);
+        assert!(results.iter().all(|(a, _)| a.step().is_some()));
+    }
+
+    #[test]
+    fn hermaphroditic_thread_connects_male_to_male() {
+        let equipment = equipment!["EF(M) -> PushLug(M)", "PushLug(M) -> 52(F)"];
+        assert!(!m("PushLug").mates(m("PushLug")));
+        assert!(make_chain(f("EF"), m("52"), &equipment).is_empty());
+
+        let options = SearchOptions { hermaphroditic: vec!["PushLug"], ..SearchOptions::default() };
+        assert!(options.mates(m("PushLug"), m("PushLug")));
+        assert!(options.mates(m("PushLug"), f("PushLug")));
+        assert!(!options.mates(m("PushLug"), m("52")));
+        let (chains, _) = make_chain_with_options(f("EF"), m("52"), &equipment, &options);
+        assert_eq!(strings(&chains),
+            ["[start: EF(F)] [EF(M) -> PushLug(M)] [PushLug(M) -> 52(F)] [end: 52(M)] "]);
+        let start = Chain::new(Adapter::new(NIL_THREAD, f("EF")));
+        let chain = start.add(equipment[0].clone()).unwrap();
+        assert!(chain.add(equipment[1].clone()).is_none());
+        assert!(chain.add_with_options(equipment[1].clone(), &options).is_some());
+        // Nothing else is told about it.
+        assert!(!m("PushLug").mates(m("PushLug")));
+        assert!(make_chain(f("EF"), m("52"), &equipment).is_empty());
+    }
+
+    #[test]
+    fn percentage_gain_over_the_baseline() {
+        assert_eq!(percent_gain(8, 2), Some(25.));
+        assert_eq!(percent_gain(4, 6), Some(150.));
+        assert_eq!(percent_gain(5, 0), Some(0.));
+        assert_eq!(percent_gain(0, 3), None);
+
+        let before = equipment!["EF(M) -> 58(F)", "58(M) -> 52(F)"];
+        let after = equipment!["EF(M) -> 58(F)", "58(M) -> 52(F)", "52(M) -> 77(F)"];
+        let (baseline, _) = count_connected_pairs(&before, None);
+        let (now, _) = count_connected_pairs(&after, None);
+        // EF, 58 and 52 were connected every way; 77 joins all three, and 52 now meets itself.
+        assert_eq!((baseline, now), (4, 8));
+        assert_eq!(percent_gain(baseline, now - baseline), Some(100.));
+    }
+
+    #[test]
+    fn items_only_ever_used_together() {
+        let equipment = equipment!["Bay1(M) -> 46(F)", "M42(M) -> 46(F)", "46(M) -> 52(F)"];
+        let found = |pairs: &[(Thread, Thread)]| {
+            let (together, _) = always_together(pairs, &equipment, None, None);
+            together.iter()
+                .map(|t| (t.adapter.to_string(), t.with.to_string(), t.mutual, t.chains))
+                .collect::<Vec<_>>()
+        };
+        let (bay1, m42, ring) = ("Bay1(M) -> 46(F)", "M42(M) -> 46(F)", "46(M) -> 52(F)");
+
+        // The Bay1 piece is no use without the ring, and here the ring is only used with it.
+        assert_eq!(found(&[(f("Bay1"), m("52"))]), [
+            (bay1.to_owned(), ring.to_owned(), true, 1),
+        ]);
+        // Once the M42 piece needs the ring too, it only goes one way.
+        assert_eq!(found(&[(f("Bay1"), m("52")), (f("M42"), m("52"))]), [
+            (bay1.to_owned(), ring.to_owned(), false, 1),
+            (m42.to_owned(), ring.to_owned(), false, 1),
+        ]);
+        // A single adapter on its own says nothing.
+        assert!(found(&[(f("Bay1"), m("46"))]).is_empty());
+    }
+
+    #[test]
+    fn avoiding_a_hub_thread_removes_most_chains() {
+        // Everything goes through 58 but the one chain through M42.
+        let equipment = equipment![
+            "EF(M) -> 58(F)",
+            "58(M) -> 77(F)",
+            "58(M) -> 52(F)",
+            "52(M) -> 77(F)",
+            "58(M) -> 62(F)",
+            "62(M) -> 77(F)",
+            "EF(M) -> M42(F)",
+            "M42(M) -> 77(F)",
+        ];
+        assert_eq!(make_chain(f("EF"), m("77"), &equipment).len(), 4);
+        let via_m42 = "[start: EF(F)] [EF(M) -> M42(F)] [M42(M) -> 77(F)] [end: 77(M)] ";
+        for hub in [m("58"), f("58")] {
+            let chains = make_chain_avoiding_thread(f("EF"), m("77"), &equipment, hub);
+            assert_eq!(strings(&chains), [via_m42]);
+        }
+        // Avoiding one of the ends leaves nothing.
+        assert!(make_chain_avoiding_thread(f("EF"), m("77"), &equipment, m("77")).is_empty());
+    }
+
+    #[test]
+    fn detour_back_through_the_same_size_is_pruned() {
+        let equipment = equipment!["52(M) -> 77(F)", "77(M) -> 52(F)", "52(M) -> 58(F)"];
+        let direct = "[start: 52(F)] [52(M) -> 58(F)] [end: 58(M)] ";
+        let detour = "[start: 52(F)] [52(M) -> 77(F)] [77(M) -> 52(F)] [52(M) -> 58(F)] \
+                      [end: 58(M)] ";
+        let search = |options: &SearchOptions| {
+            let (chains, stats) = make_chain_with_options(f("52"), m("58"), &equipment, options);
+            (strings(&chains), stats)
+        };
+        let (chains, stats) = search(&SearchOptions::default());
+        assert_eq!(chains, [direct]);
+        assert!(stats.repeat_pruned > 0);
+
+        let (chains, stats) = search(&SearchOptions { allow_repeats: true, ..Default::default() });
+        assert_eq!(chains, [direct, detour]);
+        assert_eq!(stats.repeat_pruned, 0);
+    }
+
+    #[test]
+    fn mating_ends_of_a_sample_adapter() {
+        let mount = &sample()[0];
+        assert_eq!(mount.threads(), (m("EF"), f("58")));
+        assert_eq!(mount.mating_ends(), (f("EF"), m("58")));
+        let (before, after) = mount.mating_ends();
+        assert!(before.mates(mount.0) && after.mates(mount.1));
+        assert_eq!(mount.clone().reverse().mating_ends(), (m("58"), f("EF")));
+    }
+
+    #[test]
+    fn minimal_chains_drops_a_dominated_chain() {
+        let equipment = equipment![
+            "EF(M) -> 58(F)",
+            "58(M) -> 52(F)",
+            "58(M) -> 62(F)",
+            "62(M) -> 52(F)",
+            "EF(M) -> 46(F)",
+            "46(M) -> 77(F)",
+            "77(M) -> 52(F)",
+        ];
+        let chains = make_chain(f("EF"), m("52"), &equipment);
+        let padded = "[start: EF(F)] [EF(M) -> 58(F)] [58(M) -> 62(F)] [62(M) -> 52(F)] \
+                      [end: 52(M)] ";
+        assert!(strings(&chains).contains(&padded.to_owned()));
+        assert_eq!(chains.len(), 3);
+        // Through 58 and on to 62 is the short way through 58 with an extra hop, but going by 46
+        // and 77 is a different way round, even though it's as long.
+        assert_eq!(strings(&minimal_chains(chains)), [
+            "[start: EF(F)] [EF(M) -> 58(F)] [58(M) -> 52(F)] [end: 52(M)] ",
+            "[start: EF(F)] [EF(M) -> 46(F)] [46(M) -> 77(F)] [77(M) -> 52(F)] [end: 52(M)] ",
+        ]);
+    }
+
+    #[test]
+    fn streamed_json_is_a_valid_array() {
+        let chains = make_chain(f("EF"), m("52"), &sample());
+        assert!(chains.len() > 1);
+        let mut out = Vec::<u8>::new();
+        write_chains_json(chains.clone().into_iter(), &mut out).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(value.as_array().unwrap().len(), chains.len());
+        let read: Vec<Chain> = serde_json::from_slice(&out).unwrap();
+        assert_eq!(strings(&read), strings(&chains));
+
+        let mut out = Vec::<u8>::new();
+        write_chains_json(std::iter::empty(), &mut out).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(value, serde_json::json!([]));
+    }
+
+    #[test]
+    fn chain_iter_finds_what_make_chain_does() {
+        let equipment = sample();
+        let options = SearchOptions::default();
+        let (chains, stats) = make_chain_with_options(f("EF"), m("52"), &equipment, &options);
+        let mut iter = make_chain_iter(f("EF"), m("52"), &equipment, &options);
+        let mut found = iter.by_ref().collect::<Vec<_>>();
+        found.sort_by(Chain::default_cmp);
+        assert_eq!(strings(&found), strings(&chains));
+        assert_eq!(iter.stats(), &stats);
+
+        let mut out = Vec::<u8>::new();
+        write_chains_json(make_chain_iter(f("EF"), m("52"), &equipment, &options), &mut out)
+            .unwrap();
+        let read: Vec<Chain> = serde_json::from_slice(&out).unwrap();
+        assert_eq!(read.len(), chains.len());
+    }
+
+    #[test]
+    fn reachable_and_unreachable_partition_the_threads() {
+        let equipment = sample();
+        let reachable = reachable_threads(f("EF"), &equipment);
+        let unreachable = unreachable_from(f("EF"), &equipment);
+        let all = equipment.iter().flat_map(|a| [a.0, a.1]).collect::<BTreeSet<_>>();
+        let reached = reachable.iter().copied().collect::<BTreeSet<_>>();
+        let stranded = unreachable.iter().copied().collect::<BTreeSet<_>>();
+        assert!(reached.is_disjoint(&stranded));
+        assert_eq!(reached.union(&stranded).copied().collect::<BTreeSet<_>>(), all);
+        assert_eq!(reached.len() + stranded.len(), all.len());
+        // Nothing from EF leaves anything exposed that these go onto: the step rings to 77 and 52
+        // from 55, 62 and 72, and the Bay1 adapter, are stranded.
+        assert_eq!(unreachable, [m("55"), m("62"), m("72"), m("Bay1"), f("46mm")]);
+        assert!(reachable.contains(&f("77")));
+    }
+
+    #[test]
+    fn three_adapter_chain_splits_at_its_middle_thread() {
+        let equipment = equipment!["EF(M) -> 58(F)", "58(M) -> 46(F)", "46(M) -> 52(F)"];
+        let chains = make_chain(f("EF"), m("52"), &equipment);
+        assert_eq!(chains.len(), 1);
+        let chain = &chains[0];
+        assert_eq!(chain.adapters().len(), 3);
+
+        let (first, rest) = chain.split_at_thread(f("58")).unwrap();
+        assert_eq!(first.to_string(), "[start: EF(F)] [EF(M) -> 58(F)] [end: 58(M)] ");
+        assert_eq!(rest.to_string(),
+            "[start: 58(F)] [58(M) -> 46(F)] [46(M) -> 52(F)] [end: 52(M)] ");
+        assert!(first.is_valid() && rest.is_valid());
+        // Either side of the joint names it.
+        let (first_too, rest_too) = chain.split_at_thread(m("58")).unwrap();
+        assert_eq!((first_too.to_string(), rest_too.to_string()),
+            (first.to_string(), rest.to_string()));
+        // The ends aren't joints, and nor is anything not in the chain.
+        assert!(chain.split_at_thread(f("EF")).is_none());
+        assert!(chain.split_at_thread(m("52")).is_none());
+        assert!(chain.split_at_thread(f("77")).is_none());
+    }
+
+    #[test]
+    fn reachable_pairs_of_the_sample() {
+        let pairs = reachable_pairs(&sample());
+        let expected = [
+            ("40.5(M)", "40.5(F)"), ("40.5(M)", "EF(F)"), ("40.5(M)", "LTM(F)"),
+            ("43(M)", "EF(F)"), ("43(M)", "LTM(F)"),
+            ("46(M)", "40.5(F)"), ("46(M)", "46(F)"), ("46(M)", "EF(F)"), ("46(M)", "LTM(F)"),
+            ("46mm(M)", "Bay1(F)"),
+            ("52(M)", "40.5(F)"), ("52(M)", "46(F)"), ("52(M)", "52(F)"), ("52(M)", "58(F)"),
+            ("52(M)", "72(F)"), ("52(M)", "EF(F)"), ("52(M)", "LTM(F)"),
+            ("58(M)", "58(F)"), ("58(M)", "EF(F)"),
+            ("77(M)", "40.5(F)"), ("77(M)", "46(F)"), ("77(M)", "52(F)"), ("77(M)", "55(F)"),
+            ("77(M)", "58(F)"), ("77(M)", "62(F)"), ("77(M)", "72(F)"), ("77(M)", "EF(F)"),
+            ("77(M)", "LTM(F)"),
+            ("FD(M)", "EF(F)"), ("LTM(M)", "EF(F)"), ("LTM(M)", "LTM(F)"), ("M42(M)", "EF(F)"),
+            // The double male 58 ring makes 58(F) a thread the equipment offers, too.
+            ("58(F)", "58(F)"), ("58(F)", "EF(F)"),
+        ];
+        let expected = expected.iter()
+            .map(|(a, b)| (a.parse().unwrap(), b.parse().unwrap()))
+            .collect::<BTreeSet<(Thread, Thread)>>();
+        assert_eq!(pairs, expected);
+        // Each pair is there once, lower thread first.
+        assert!(pairs.iter().all(|(a, b)| a <= b));
+        assert!(pairs.contains(&(m("52"), f("EF"))));
+        assert!(!pairs.contains(&(m("46mm"), f("EF"))));
+    }
+
+    #[test]
+    fn connect_all_bridges_two_components_once() {
+        // EF, 58 and 52 on one side; LTM, 40.5 and 46 on the other.
+        let equipment = equipment![
+            "EF(M) -> 58(F)",
+            "58(M) -> 52(F)",
+            "LTM(M) -> 40.5(F)",
+            "40.5(M) -> 46(F)",
+        ];
+        let priced = |adapter: &str, cost| {
+            adapter.parse::<Adapter>().unwrap().with_cost(Some(cost))
+        };
+        let candidates = [
+            priced("46(M) -> 52(F)", 10.),
+            priced("40.5(M) -> 58(F)", 5.),
+            priced("EF(M) -> LTM(F)", 20.),
+            // Cheapest, but it only brings in a new thread.
+            priced("52(M) -> 77(F)", 1.),
+            // Joins what's already joined.
+            priced("EF(M) -> 52(F)", 2.),
+        ];
+        let bought = connect_all(&equipment, &candidates, |a| a.info().cost.unwrap_or(0.));
+        assert_eq!(bought, [priced("40.5(M) -> 58(F)", 5.)]);
+    }
+
+    #[test]
+    fn interned_and_parsed_threads_find_the_same_chains() {
+        let pairs = [
+            ("EF", "58"), ("EF", "LTM"), ("58", "77"), ("58", "52"), ("46", "52"), ("46", "77"),
+            ("52", "77"), ("LTM", "40.5"), ("40.5", "46"),
+        ];
+        // Symbols interned up front, and threads built straight from them...
+        let interned = pairs.iter()
+            .map(|&(a, b)| (ThreadSym::new(a), ThreadSym::new(b)))
+            .map(|(a, b)| Adapter::new(Thread::M(a), Thread::F(b)))
+            .collect::<Vec<_>>();
+        // ...or everything parsed from the display form.
+        let parsed = pairs.iter()
+            .map(|(a, b)| format!("{}(M) -> {}(F)", a, b).parse::<Adapter>().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(interned, parsed);
+
+        let (from, to) = (Thread::F(ThreadSym::new("EF")), Thread::M(ThreadSym::new("77")));
+        assert_eq!(from, "EF(F)".parse().unwrap());
+        assert_eq!(from.to_string().parse::<Thread>().unwrap(), from);
+        let interned = make_chain(from, to, &interned);
+        let parsed = make_chain("EF(F)".parse().unwrap(), "77(M)".parse().unwrap(), &parsed);
+        assert!(interned.len() > 1);
+        assert_eq!(strings(&interned), strings(&parsed));
+        assert_eq!(format!("{:?}", interned), format!("{:?}", parsed));
+    }
+}
@@ -1,253 +1,2077 @@
 use std::{
-    borrow::Cow,
-    cmp::PartialEq,
-    collections::HashSet,
-    fmt::{self, Display, Formatter},
-    hash::{Hash, Hasher},
+    collections::{BTreeSet, HashSet},
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::exit,
+    time::{Duration, Instant, SystemTime},
 };
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Hash)]
-enum Thread {
-    M(&'static str),
-    F(&'static str),
+use adapter_party::{
+    adapter_utilization,
+    always_together,
+    best_chain,
+    candidate_adapters,
+    chains_to_dot,
+    count_connected_pairs,
+    equipment,
+    find_gaps,
+    find_swaps,
+    find_useful_additions_with_mode,
+    inventory::{load_equipment, load_wants, Format, Inventory, LoadError, LoadOptions, PriceSource,
+        Selection},
+    journal::{self, JournalEntry},
+    make_chain,
+    make_chain_iter,
+    make_chain_to_prefix,
+    make_chain_with_checkpoints,
+    make_chain_with_stats,
+    make_chain_with_tree,
+    mating_threads,
+    minimal_chains,
+    optimal_chain,
+    percent_gain,
+    plan_purchases,
+    rank_loans,
+    registry::{self, Registry, ThreadKind},
+    scad::{self, ScadOptions},
+    shortest_chain,
+    shortest_chains_for_pairs,
+    suggest_for,
+    thread_pairs,
+    threads_with_prefix,
+    unreachable_from,
+    write_chains_json,
+    Adapter,
+    AdditionMode,
+    Chain,
+    Coupling,
+    DEFAULT_MAX_STATES,
+    EXACT_PLAN_LIMIT,
+    JunctionWhitelist,
+    Neighbor,
+    SearchCheckpoint,
+    SearchOptions,
+    SearchStats,
+    SortKey,
+    SortSpec,
+    Terminal,
+    Thread,
+};
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match args.first().map(String::as_str) {
+        Some("chain") => chain(&args[1..]),
+        Some("pairs") => pairs(&args[1..]),
+        Some("fmt") => fmt(&args[1..]),
+        Some("rename-thread") => rename_thread(&args[1..]),
+        Some("equivalents") => equivalents(&args[1..]),
+        Some("list") => list(&args[1..]),
+        Some("threads") => threads(&args[1..]),
+        Some("loan") => loan(&args[1..]),
+        Some("return") => return_loan(&args[1..]),
+        Some("loans") => loans(&args[1..]),
+        Some("log") => log(&args[1..]),
+        Some("undo") => undo(&args[1..]),
+        Some("suggest") => suggest(&args[1..]),
+        Some("kit") => kit(&args[1..]),
+        Some("lint") => lint(&args[1..]),
+        Some("stats") => stats(&args[1..]),
+        Some("utilization") => utilization(&args[1..]),
+        Some("value") => value_report(&args[1..]),
+        Some("plan") => plan(&args[1..]),
+        Some("swaps") => swaps(&args[1..]),
+        Some("together") => together(&args[1..]),
+        Some("stranded") => stranded(&args[1..]),
+        Some("analyze") => analyze(&args[1..]),
+        _ => demo(&args),
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("usage: adapter-party [--infer-gender] [--max-states <n>] [--deadline <time>]");
+    eprintln!("                     [--profile] [--filter-steps] [<inventory.toml>]");
+    eprintln!("       adapter-party chain --from <thread|item>");
+    eprintln!("                       (--to <thread|item|size(*)> | --to-size <size>)");
+    eprintln!("                       [--via <name>]...");
+    eprintln!("                       [--avoid-thread <name>]... [--collapse] [--joints] [--dot]");
+    eprintln!("                       [--only-threads <name>,<name>...]");
+    eprintln!("                       [--only-junctions filter_threads|mounts]");
+    eprintln!("                       [--format text|json|dot] [--output <file>]");
+    eprintln!("                       [--pick <n> [--instructions]] [--best [--deadline <time>]]");
+    eprintln!("                       [--include-loaned] [--loops] [--allow-repeats]");
+    eprintln!("                       [--minimal-only] [--first <item>] [--last <item>]");
+    eprintln!("                       [--adjacent <item>,<item>|start|end]...");
+    eprintln!("                       [--sort <key>[,<key>...]] [--limit <n>] [--kit <name>]...");
+    eprintln!("                       [--without-kit <name>]... [--max-states <n>] [--strict-fit]");
+    eprintln!("                       [--strict-seating] [--optimal <w_cost>,<w_len>]");
+    eprintln!("                       [--checkpoint <file> [--checkpoint-every <time>]]");
+    eprintln!("                       [--resume <file>] [--dump-search-tree <out.dot>]");
+    eprintln!("                       [--profile] <inventory.toml>");
+    eprintln!("       adapter-party pairs [--show-chains] [--thread <name>]...");
+    eprintln!("                       [--format text|markdown]");
+    eprintln!("                       [--kit <name>]... [--without-kit <name>]...");
+    eprintln!("                       <inventory.toml>");
+    eprintln!("       adapter-party fmt [--check] [--infer-gender] [--dedup-reversed]");
+    eprintln!("                       <inventory.toml>");
+    eprintln!("       adapter-party rename-thread [--force] <old> <new> <inventory.toml>");
+    eprintln!("       adapter-party equivalents <inventory.toml>");
+    eprintln!("       adapter-party list [--verbose] <inventory.toml>");
+    eprintln!("       adapter-party threads [--thread <name>]... <inventory.toml>");
+    eprintln!("       adapter-party loan <item> --to <who> [--on <YYYY-MM-DD>] <inventory.toml>");
+    eprintln!("       adapter-party return <item> <inventory.toml>");
+    eprintln!("       adapter-party loans <inventory.toml>");
+    eprintln!("       adapter-party log [--since <YYYY-MM-DD>] <inventory.toml>");
+    eprintln!("       adapter-party undo [--steps <n>] [--dry-run] <inventory.toml>");
+    eprintln!("       adapter-party suggest --from <thread|item> --to <thread|item>");
+    eprintln!("                       [--max-buy <n>] [--kit <name>]... [--without-kit <name>]...");
+    eprintln!("                       [--allow-mixed-handedness] [--shapes]");
+    eprintln!("                       [--format text|json|dot] [--output <file>]");
+    eprintln!("                       [--export-scad <out.scad> [--wall <mm>] [--length <mm>]]");
+    eprintln!("                       <inventory.toml>");
+    eprintln!("       adapter-party suggest --lend-to <friend.toml> [--rigs <wants.toml>]");
+    eprintln!("                       [--kit <name>]... [--without-kit <name>]...");
+    eprintln!("                       <inventory.toml>");
+    eprintln!("       adapter-party suggest --adapter \"<thread> -> <thread>\"");
+    eprintln!("                       --export-scad <out.scad> [--wall <mm>] [--length <mm>]");
+    eprintln!("                       [<inventory.toml>]");
+    eprintln!("       adapter-party kit list <inventory.toml>");
+    eprintln!("       adapter-party kit show <name> <inventory.toml>");
+    eprintln!("       adapter-party lint <inventory.toml>");
+    eprintln!("       adapter-party stats <inventory.toml>");
+    eprintln!("       adapter-party utilization [--thread <name>]...");
+    eprintln!("                       [--pair <thread>,<thread>]... [--format text|csv]");
+    eprintln!("                       [--kit <name>]... [--without-kit <name>]...");
+    eprintln!("                       <inventory.toml>");
+    eprintln!("       adapter-party value [--format text|csv|json] [--replacement <catalog.toml>]");
+    eprintln!("                       <inventory.toml>");
+    eprintln!("       adapter-party plan --wants <wants.toml> --catalog <catalog.toml>");
+    eprintln!("                       [--kit <name>]... [--without-kit <name>]...");
+    eprintln!("                       <inventory.toml>");
+    eprintln!("       adapter-party swaps [--filter-steps] [--max-states <n>] [--deadline <time>]");
+    eprintln!("                       [--kit <name>]... [--without-kit <name>]...");
+    eprintln!("                       <inventory.toml>");
+    eprintln!("       adapter-party stranded --from <thread|item> [--kit <name>]...");
+    eprintln!("                       [--without-kit <name>]... <inventory.toml>");
+    eprintln!("       adapter-party together [--thread <name>]... [--pair <thread>,<thread>]...");
+    eprintln!("                       [--max-len <n>] [--max-states <n>] [--kit <name>]...");
+    eprintln!("                       [--without-kit <name>]... <inventory.toml>");
+    eprintln!("       adapter-party analyze gaps [--max-buy <n>] [--shapes] [--kit <name>]...");
+    eprintln!("                       [--without-kit <name>]... <inventory.toml>");
+    eprintln!();
+    eprintln!("sort keys: {}", SortKey::NAMES.join(", "));
+    eprintln!("  smallest first, or largest first with a - in front (-diameter); ties go to");
+    eprintln!("  fewest adapters, then fewest step-downs. \"default\" is just that, and");
+    eprintln!("  \"min-diameter\" is the same as -diameter.");
+    exit(2);
+}
+
+fn load(path: &str, options: &LoadOptions) -> Inventory {
+    match load_equipment(path, options) {
+        Ok(inventory) => {
+            for warning in inventory.warnings() {
+                eprintln!("{}: warning: {}", path, warning);
+            }
+            inventory
+        }
+        Err(LoadError::Diagnostics { diagnostics, omitted }) => {
+            // `path:line:column: message`, the way compilers do it.
+            for diagnostic in &diagnostics {
+                let sep = if diagnostic.line == 0 { " " } else { "" };
+                eprintln!("{}:{}{}", path, sep, diagnostic);
+            }
+            if omitted > 0 {
+                eprintln!("{}: ...and {} more", path, omitted);
+            }
+            exit(1);
+        }
+        Err(e) => {
+            eprintln!("failed to load {}: {}", path, e);
+            exit(1);
+        }
+    }
+}
+
+/// Commands that rewrite the inventory file only know how to write TOML.
+fn require_toml(path: &str) {
+    if Format::from_path(Path::new(path)).is_some_and(|format| format != Format::Toml) {
+        eprintln!("{}: only TOML inventory files can be rewritten", path);
+        exit(1);
+    }
+}
+
+/// The value following an option, or a usage error if there isn't one.
+fn value<'a>(args: &mut impl Iterator<Item = &'a String>) -> &'a str {
+    match args.next() {
+        Some(value) => value,
+        None => usage(),
+    }
+}
+
+/// `println!`, but to wherever `--output` says, giving up if that can't be written to.
+macro_rules! outln {
+    ($out:expr $(, $arg:expr)* $(,)?) => {
+        if let Err(e) = writeln!($out $(, $arg)*) {
+            eprintln!("writing output: {}", e);
+            exit(1);
+        }
+    };
+}
+
+/// How `chain` and `suggest` write their results (`--format`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    Json,
+    Dot,
+}
+
+fn parse_output_format(s: &str) -> OutputFormat {
+    match s {
+        "text" => OutputFormat::Text,
+        "json" => OutputFormat::Json,
+        "dot" => OutputFormat::Dot,
+        _ => usage(),
+    }
+}
+
+/// Where `--output` says to write results: to the file, or to stdout if there isn't one.
+fn output(path: Option<&str>) -> Box<dyn Write> {
+    let Some(path) = path else { return Box::new(io::stdout()) };
+    match fs::File::create(path) {
+        Ok(file) => Box::new(io::BufWriter::new(file)),
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            exit(1);
+        }
+    }
+}
+
+/// Make sure everything written to an output has got there.
+fn finish(mut out: Box<dyn Write>) {
+    if let Err(e) = out.flush() {
+        eprintln!("writing output: {}", e);
+        exit(1);
+    }
+}
+
+/// Pretty-printed JSON of a result.
+fn to_json(value: &impl serde::Serialize) -> String {
+    serde_json::to_string_pretty(value).expect("results always serialize")
+}
+
+/// Write chains as a JSON array (see `write_chains_json`).
+fn write_json(out: &mut dyn Write, chains: impl Iterator<Item = Chain>) {
+    if let Err(e) = write_chains_json(chains, out) {
+        eprintln!("writing output: {}", e);
+        exit(1);
+    }
+}
+
+/// Point out anything in a chain that's lent out (which only happens with `--include-loaned`).
+fn print_loans(out: &mut dyn Write, chain: &Chain) {
+    for adapter in chain.adapters() {
+        if let Some(who) = adapter.loaned_to() {
+            outln!(out, "    [{}] is currently with {}", adapter, who);
+        }
+    }
+}
+
+/// Warn about pieces in a chain that look like they won't seat in each other, and say what size
+/// any clamps are gripping.
+fn print_fit_notes(out: &mut dyn Write, chain: &Chain) {
+    for grip in chain.grips() {
+        outln!(out, "    {}", grip);
+    }
+    if chain.uses_both_orientations() {
+        outln!(out, "    note: uses the same item both ways round");
+    }
+    for interference in chain.interferences() {
+        outln!(out, "    warning: {}", interference);
+    }
+    for problem in chain.seating_problems() {
+        outln!(out, "    warning: {}", problem);
+    }
+}
+
+/// Say which gender of thread a chain leaves for the end, for `--to-size`, where it could be
+/// either.
+fn print_exposed(out: &mut dyn Write, chain: &Chain) {
+    let path = chain.thread_path();
+    if let [.., exposed, _end] = path[..] {
+        outln!(out, "    exposes {}", exposed);
+    }
+}
+
+/// Say which kits to fetch for a chain, if any of it is kept in kits.
+fn print_kits(out: &mut dyn Write, chain: &Chain) {
+    let kits = chain.adapters().iter().filter_map(Adapter::kit).collect::<BTreeSet<_>>();
+    if !kits.is_empty() {
+        outln!(out, "    kits: {}", kits.into_iter().collect::<Vec<_>>().join(", "));
+    }
 }
 
-const NIL_THREAD: Thread = Thread::M("nil");
+/// One chain, with everything worth pointing out about it, in the format asked for.
+fn print_chain(out: &mut dyn Write, chain: &Chain, format: OutputFormat) {
+    match format {
+        OutputFormat::Text => {
+            outln!(out, "{}", chain);
+            print_fit_notes(out, chain);
+            print_loans(out, chain);
+            print_kits(out, chain);
+        }
+        OutputFormat::Json => outln!(out, "{}", to_json(chain)),
+        OutputFormat::Dot => {
+            outln!(out, "{}", chains_to_dot(std::slice::from_ref(chain)).trim_end());
+        }
+    }
+}
 
-impl Thread {
-    pub fn opposite(self) -> Self {
-        match self {
-            Self::M(x) => Self::F(x),
-            Self::F(x) => Self::M(x),
+/// The equipment to search with, after checking that any kits asked for exist.
+fn select(inventory: &Inventory, selection: &Selection) -> Vec<Adapter> {
+    let kits = inventory.kits();
+    for kit in selection.kits.iter().chain(&selection.without_kits) {
+        if !kits.contains_key(kit.as_str()) {
+            eprintln!("there's no kit named {:?}", kit);
+            exit(1);
         }
     }
+    inventory.select(selection)
+}
+
+/// Check an inventory file for anything suspicious, failing if there is.
+fn lint(args: &[String]) {
+    let [path] = args else { usage() };
+    // Loading prints the warnings.
+    let inventory = load(path, &LoadOptions::default());
+    let typos = registry::suspicious_thread_names(&inventory.all_equipment());
+    for (a, b) in &typos {
+        eprintln!("{}: warning: threads {:?} and {:?} are very alike; is one a typo?", path, a, b);
+    }
+    let lonely = lonely_left_hand_threads(&inventory);
+    for thread in &lonely {
+        eprintln!("{}: warning: nothing else in the inventory mates with left-hand thread {}", path,
+            thread);
+    }
+    // Not a problem, but seating checks only happen where the measurements are there.
+    let measured = inventory.entries().iter()
+        .map(|e| e.adapter.info())
+        .filter(|info| info.male_length.is_some() || info.female_depth.is_some())
+        .count();
+    println!("{} of {} entries have thread length or depth measurements", measured,
+        inventory.entries().len());
+    if !inventory.warnings().is_empty() || !typos.is_empty() || !lonely.is_empty() {
+        exit(1);
+    }
 }
 
-impl Display for Thread {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::M(x) => {
-                f.write_str(x)?;
-                f.write_str("(M)")
+/// Left-hand threads on adapters that no other adapter or terminal item in the inventory can mate
+/// with, since a left-hand thread only fits another left-hand one.
+fn lonely_left_hand_threads(inventory: &Inventory) -> BTreeSet<Thread> {
+    let entries = inventory.entries();
+    let mut lonely = BTreeSet::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let (a, b) = entry.adapter.threads();
+        for thread in [a, b].into_iter().filter(|t| t.is_left_handed()) {
+            let mate = thread.opposite();
+            let on_adapter = entries.iter().enumerate().any(|(j, other)| {
+                let (x, y) = other.adapter.threads();
+                (i != j || entry.quantity > 1) && (x == mate || y == mate)
+            });
+            if !on_adapter && !inventory.terminals().iter().any(|t| t.thread == mate) {
+                lonely.insert(thread);
             }
-            Self::F(x) => {
-                f.write_str(x)?;
-                f.write_str("(F)")
+        }
+    }
+    lonely
+}
+
+/// How much of each category of equipment there is.
+fn stats(args: &[String]) {
+    let [path] = args else { usage() };
+    let inventory = load(path, &LoadOptions::default());
+    for (category, stats) in inventory.stats_by_category() {
+        print!("{}: {} in {} entr{}", category, stats.count, stats.entries,
+            if stats.entries == 1 { "y" } else { "ies" });
+        if stats.loaned != 0 {
+            print!(", {} lent out", stats.loaned);
+        }
+        if stats.total_price != 0. {
+            print!(", total price {:.2}", stats.total_price);
+        }
+        if stats.total_length != 0. {
+            print!(", {} mm long", stats.total_length);
+        }
+        println!();
+    }
+}
+
+/// `kit list` shows the kits and how many things are in each; `kit show <name>` lists one.
+fn kit(args: &[String]) {
+    let inventory;
+    match args {
+        [command, path] if command == "list" => {
+            inventory = load(path, &LoadOptions::default());
+            for (name, entries) in inventory.kits() {
+                let count = entries.iter().map(|e| e.quantity).sum::<u32>();
+                println!("{}: {} item{}", name, count, if count == 1 { "" } else { "s" });
+            }
+        }
+        [command, name, path] if command == "show" => {
+            inventory = load(path, &LoadOptions::default());
+            let kits = inventory.kits();
+            let Some(entries) = kits.get(name.as_str()) else {
+                eprintln!("there's no kit named {:?}", name);
+                exit(1);
+            };
+            for entry in entries {
+                println!("{}x [{}]", entry.quantity, entry.adapter);
             }
         }
+        _ => usage(),
     }
 }
 
-#[derive(Debug, Clone, Eq)]
-struct Adapter(Thread, Thread, Cow<'static, str>);
+/// Parse a duration like "2s" or "500ms".
+fn parse_duration(s: &str) -> Duration {
+    let parsed = if let Some(ms) = s.strip_suffix("ms") {
+        ms.parse().ok().map(Duration::from_millis)
+    } else {
+        s.strip_suffix('s').unwrap_or(s).parse().ok().map(Duration::from_secs_f64)
+    };
+    parsed.unwrap_or_else(|| usage())
+}
 
-impl Adapter {
-    pub fn new(a: Thread, b: Thread) -> Self {
-        Self(a, b, Cow::Borrowed(""))
+/// Parse a thread given on the command line, normalizing its name like the inventory's are.
+fn parse_thread(s: &str, registry: &Registry) -> Thread {
+    match s.parse() {
+        Ok(thread) => registry.canonical_thread(thread),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(2);
+        }
     }
+}
 
-    pub fn with_name(self, name: &'static str) -> Self {
-        Self(self.0, self.1, Cow::Borrowed(name))
+/// What to buy to connect two things that can't be connected yet.
+fn suggest(args: &[String]) {
+    let mut from = None;
+    let mut to = None;
+    let mut max_buy = 1;
+    let mut selection = Selection::default();
+    let mut adapter = None;
+    let mut export = None;
+    let mut scad_options = ScadOptions::default();
+    let mut allow_mixed_handedness = false;
+    let mut shapes = false;
+    let mut lend_to = None;
+    let mut rigs = None;
+    let mut format = OutputFormat::Text;
+    let mut output_path = None;
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--from" => from = Some(value(&mut args)),
+            "--to" => to = Some(value(&mut args)),
+            "--max-buy" => max_buy = value(&mut args).parse().unwrap_or_else(|_| usage()),
+            "--kit" => selection.kits.push(value(&mut args).to_owned()),
+            "--without-kit" => selection.without_kits.push(value(&mut args).to_owned()),
+            "--allow-mixed-handedness" => allow_mixed_handedness = true,
+            "--shapes" => shapes = true,
+            "--lend-to" => lend_to = Some(value(&mut args)),
+            "--rigs" => rigs = Some(value(&mut args)),
+            "--format" => format = parse_output_format(value(&mut args)),
+            "--output" => output_path = Some(value(&mut args)),
+            "--adapter" => adapter = Some(value(&mut args)),
+            "--export-scad" => export = Some(value(&mut args)),
+            "--wall" => scad_options.wall = value(&mut args).parse().unwrap_or_else(|_| usage()),
+            "--length" => {
+                scad_options.length = value(&mut args).parse().unwrap_or_else(|_| usage());
+            }
+            _ if path.is_none() => path = Some(arg.as_str()),
+            _ => usage(),
+        }
     }
+    if let Some(adapter) = adapter {
+        // Nothing to search for: just model the adapter given.
+        let Some(export) = export else { usage() };
+        let registry = path.map(|path| load(path, &LoadOptions::default()).registry().clone());
+        let registry = registry.unwrap_or_default();
+        let Some((a, b)) = adapter.split_once("->") else { usage() };
+        let adapter = Adapter::new(parse_thread(a, &registry), parse_thread(b, &registry));
+        export_scad(&[&adapter], export, &scad_options);
+        return;
+    }
+    if let Some(friend) = lend_to {
+        let Some(path) = path else { usage() };
+        suggest_loans(path, friend, &selection, rigs);
+        return;
+    }
+    let (Some(from), Some(to), Some(path)) = (from, to, path) else { usage() };
 
-    pub fn reverse(self) -> Self {
-        let name = if self.2.is_empty() {
-            self.2
-        } else if let Some(s) = self.2.strip_suffix(" (reversed)") {
-            Cow::Owned(s.to_owned())
-        } else {
-            Cow::Owned(format!("{} (reversed)", self.2))
+    let inventory = load(path, &LoadOptions::default());
+    let (from, _) = parse_end(from, &inventory);
+    let (to, _) = parse_end(to, &inventory);
+    let equipment = select(&inventory, &selection);
+    let mut out = output(output_path);
+    // Messages about there being nothing to buy would spoil JSON or DOT, so they go to stderr then.
+    let mut say = |message: String| match format {
+        OutputFormat::Text => outln!(out, "{}", message),
+        _ => eprintln!("{}", message),
+    };
+    let suggestions = if shortest_chain(from, to, &equipment).is_some() {
+        say(format!("{} and {} can already be connected", from, to));
+        vec![]
+    } else {
+        let candidates = candidate_adapters(from, to, &equipment, allow_mixed_handedness);
+        let suggestions = suggest_for(from, to, &equipment, &candidates, max_buy);
+        if suggestions.is_empty() {
+            say(format!("no way to connect {} and {} by buying {} or fewer adapters", from, to,
+                max_buy));
+        }
+        suggestions
+    };
+    match format {
+        OutputFormat::Text => {
+            for (buy, chain) in &suggestions {
+                let buy = buy.iter().map(|a| shopping(a, shapes)).collect::<Vec<_>>().join(" + ");
+                outln!(out, "buy {}:\n    {}", buy, chain);
+            }
+        }
+        OutputFormat::Json => {
+            let suggestions = suggestions.iter()
+                .map(|(buy, chain)| serde_json::json!({ "buy": buy, "chain": chain }))
+                .collect::<Vec<_>>();
+            outln!(out, "{}", to_json(&suggestions));
+        }
+        OutputFormat::Dot => {
+            let chains = suggestions.iter().map(|(_, chain)| chain.clone()).collect::<Vec<_>>();
+            outln!(out, "{}", chains_to_dot(&chains).trim_end());
+        }
+    }
+    finish(out);
+    if let (Some(export), Some((buy, _))) = (export, suggestions.first()) {
+        export_scad(&buy.iter().collect::<Vec<_>>(), export, &scad_options);
+    }
+}
+
+/// `suggest --lend-to`: which of my things would do the most for a friend's equipment, flagging
+/// any that one of my rigs (the connections in a wants file, as for `plan`) can't do without.
+fn suggest_loans(path: &str, friend: &str, selection: &Selection, rigs: Option<&str>) {
+    let inventory = load(path, &LoadOptions::default());
+    let mine = select(&inventory, selection);
+    let theirs = load(friend, &LoadOptions::default()).equipment();
+    let rigs = rigs.map_or_else(Vec::new, |rigs| {
+        load_wants(rigs, &inventory).unwrap_or_else(|e| {
+            eprintln!("failed to load {}: {}", rigs, e);
+            exit(1);
+        })
+    });
+    let (loans, stats) = rank_loans(&mine, &theirs, Some(DEFAULT_MAX_STATES));
+    let baseline = count_connected_pairs(&theirs, Some(DEFAULT_MAX_STATES)).0;
+    let loans = loans.into_iter().filter(|(_, count)| *count > 0).collect::<Vec<_>>();
+    if loans.is_empty() {
+        println!("nothing of yours would connect anything new for them");
+    }
+    for (lent, count) in loans {
+        println!("[{}]: {} new pair{} for them{}", lent, count, if count == 1 { "" } else { "s" },
+            percent(baseline, count));
+        // Lending one copy leaves any others.
+        let mut without = mine.clone();
+        without.remove(mine.iter().position(|a| *a == lent).unwrap());
+        for &(from, to) in &rigs {
+            let connects = |equipment: &[Adapter]| shortest_chain(from, to, equipment).is_some();
+            if connects(&mine) && !connects(&without) {
+                println!("    needed for your rig from {} to {}", from, to);
+            }
+        }
+    }
+    warn_truncated(&stats);
+}
+
+/// How to describe an adapter to buy: with `shapes`, what a shop would sell it as (see
+/// `registry::shape`) where there's such a thing, and otherwise its exact threads.
+fn shopping(adapter: &Adapter, shapes: bool) -> String {
+    match registry::shape(adapter) {
+        Some(shape) if shapes => shape.to_string(),
+        _ => format!("[{}]", adapter),
+    }
+}
+
+/// Write OpenSCAD models of adapters to print. With more than one, they go in numbered files:
+/// `out.scad` becomes `out-1.scad`, `out-2.scad`, and so on.
+fn export_scad(adapters: &[&Adapter], path: &str, options: &ScadOptions) {
+    for (i, adapter) in adapters.iter().enumerate() {
+        let model = scad::export(adapter, options).unwrap_or_else(|e| {
+            eprintln!("can't model [{}]: {}", adapter, e);
+            exit(1);
+        });
+        let path = match adapters.len() {
+            1 => PathBuf::from(path),
+            _ => {
+                let path = Path::new(path);
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                path.with_file_name(format!("{}-{}.scad", stem, i + 1))
+            }
         };
-        Self(self.1, self.0, name)
+        if let Err(e) = fs::write(&path, model) {
+            eprintln!("{}: {}", path.display(), e);
+            exit(1);
+        }
+        println!("wrote a model of [{}] to {}", adapter, path.display());
+    }
+}
+
+/// A chain end given on the command line: the name of a terminal item in the inventory, or else a
+/// thread.
+fn parse_end<'a>(s: &str, inventory: &'a Inventory) -> (Thread, Option<&'a Terminal>) {
+    match inventory.terminal(s) {
+        Some(terminal) => (terminal.thread, Some(terminal)),
+        None => (parse_thread(s, inventory.registry()), None),
+    }
+}
+
+/// `--sort`'s argument: keys separated by commas, or one of the names for the older fixed orders.
+fn parse_sort(arg: &str) -> Vec<SortSpec> {
+    match arg {
+        "default" => vec![],
+        "min-diameter" => vec![SortSpec { key: SortKey::MinDiameter, descending: true }],
+        _ => arg.split(',').map(|key| key.trim().parse()).collect::<Result<_, _>>()
+            .unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                exit(2);
+            }),
+    }
+}
+
+/// A cost weight and a length weight for `optimal_chain`, like "1,0.5".
+fn parse_weights(s: &str) -> (f64, f64) {
+    let Some((cost, len)) = s.split_once(',') else { usage() };
+    match (cost.trim().parse(), len.trim().parse()) {
+        (Ok(cost), Ok(len)) => (cost, len),
+        _ => usage(),
+    }
+}
+
+/// Find all the ways to connect two threads using the equipment in an inventory. The name given
+/// to `--to` can be just the start of a name, to get chains to all the threads it matches.
+/// Either end can instead be the name of a terminal item, like a camera body.
+fn chain(args: &[String]) {
+    let mut from = None;
+    let mut to = None;
+    let mut via = vec![];
+    let mut avoid = vec![];
+    let mut collapse = false;
+    let mut joints = false;
+    let mut format = OutputFormat::Text;
+    let mut output_path = None;
+    let mut pick = None;
+    let mut instructions = false;
+    let mut best = false;
+    let mut optimal = None;
+    let mut deadline = None;
+    let mut selection = Selection::default();
+    let mut loops = false;
+    let mut allow_repeats = false;
+    let mut only_threads = None::<Vec<&str>>;
+    let mut only_kind = None;
+    let mut minimal_only = false;
+    let mut sort = None;
+    let mut limit = None;
+    let mut max_states = DEFAULT_MAX_STATES;
+    let mut first = None;
+    let mut last = None;
+    let mut adjacent = vec![];
+    let mut strict_fit = false;
+    let mut strict_seating = false;
+    let mut checkpoint = None;
+    let mut checkpoint_every = Duration::from_secs(30);
+    let mut resume = None;
+    let mut dump_tree = None;
+    let mut profile = false;
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--from" => from = Some(value(&mut args)),
+            "--to" => to = Some(value(&mut args).to_owned()),
+            "--to-size" => to = Some(format!("{}(*)", value(&mut args))),
+            "--via" => via.push(value(&mut args)),
+            "--avoid-thread" => avoid.push(value(&mut args)),
+            "--collapse" => collapse = true,
+            "--joints" => joints = true,
+            "--dot" => format = OutputFormat::Dot,
+            "--format" => format = parse_output_format(value(&mut args)),
+            "--output" => output_path = Some(value(&mut args)),
+            "--best" => best = true,
+            "--optimal" => optimal = Some(parse_weights(value(&mut args))),
+            "--include-loaned" => selection.include_loaned = true,
+            "--kit" => selection.kits.push(value(&mut args).to_owned()),
+            "--without-kit" => selection.without_kits.push(value(&mut args).to_owned()),
+            "--loops" => loops = true,
+            "--allow-repeats" => allow_repeats = true,
+            "--only-threads" => only_threads = Some(value(&mut args).split(',').collect()),
+            "--only-junctions" => only_kind = Some(match value(&mut args) {
+                "filter_threads" => ThreadKind::FilterThread,
+                "mounts" => ThreadKind::Mount,
+                _ => usage(),
+            }),
+            "--minimal-only" => minimal_only = true,
+            "--sort" => sort = Some(parse_sort(value(&mut args))),
+            "--limit" => {
+                limit = Some(value(&mut args).parse::<usize>().unwrap_or_else(|_| usage()));
+            }
+            "--max-states" => max_states = value(&mut args).parse().unwrap_or_else(|_| usage()),
+            "--first" => first = Some(value(&mut args)),
+            "--last" => last = Some(value(&mut args)),
+            "--adjacent" => match value(&mut args).split_once(',') {
+                Some((item, neighbor)) => adjacent.push((item, neighbor)),
+                None => usage(),
+            },
+            "--deadline" => deadline = Some(parse_duration(value(&mut args))),
+            "--pick" => pick = Some(value(&mut args).parse::<usize>().unwrap_or_else(|_| usage())),
+            "--instructions" => instructions = true,
+            "--strict-fit" => strict_fit = true,
+            "--strict-seating" => strict_seating = true,
+            "--checkpoint" => checkpoint = Some(value(&mut args)),
+            "--checkpoint-every" => checkpoint_every = parse_duration(value(&mut args)),
+            "--resume" => resume = Some(value(&mut args)),
+            "--dump-search-tree" => dump_tree = Some(value(&mut args)),
+            "--profile" => profile = true,
+            _ if path.is_none() => path = Some(arg.as_str()),
+            _ => usage(),
+        }
+    }
+    let (Some(from), Some(to), Some(path)) = (from, to, path) else { usage() };
+    // `52(*)` is either gender of 52. The search is for the female one, but will take either.
+    let any_gender_end = to.ends_with("(*)");
+    let to = match to.strip_suffix("(*)") {
+        Some(size) => format!("{}(F)", size),
+        None => to,
+    };
+    // Resuming carries on checkpointing to the same file unless told otherwise.
+    let checkpoint = checkpoint.or(resume);
+    if instructions && pick.is_none() {
+        usage();
+    }
+    // The tree is of the exhaustive search, and only one of it.
+    if dump_tree.is_some() && (best || optimal.is_some() || checkpoint.is_some()) {
+        usage();
+    }
+
+    let inventory = load(path, &LoadOptions::default());
+    let registry = inventory.registry();
+    let (from, from_terminal) = parse_end(from, &inventory);
+    let (to, to_terminal) = parse_end(&to, &inventory);
+    let show_ends = |mut chain: Chain| {
+        if let Some(terminal) = from_terminal {
+            chain = chain.starting_at(terminal);
+        }
+        if let Some(terminal) = to_terminal {
+            chain = chain.ending_at(terminal);
+        }
+        chain
+    };
+    let options = SearchOptions {
+        via: via.into_iter().map(|name| registry.canonical(name)).collect(),
+        avoid: avoid.into_iter().map(|name| registry.canonical(name)).collect(),
+        loops,
+        max_states: Some(max_states),
+        first: first.map(|item| item_adapter(&inventory, item)),
+        last: last.map(|item| item_adapter(&inventory, item)),
+        adjacent: adjacent.into_iter()
+            .map(|(item, neighbor)| {
+                let neighbor = match neighbor.trim() {
+                    "start" => Neighbor::Start,
+                    "end" => Neighbor::End,
+                    other => Neighbor::Adapter(item_adapter(&inventory, other)),
+                };
+                (item_adapter(&inventory, item), neighbor)
+            })
+            .collect(),
+        strict_fit,
+        strict_seating,
+        any_gender_end,
+        allow_repeats,
+        only_junctions: match (only_threads, only_kind) {
+            (Some(_), Some(_)) => usage(),
+            (Some(names), None) => Some(JunctionWhitelist::Threads(
+                names.into_iter().map(|name| registry.canonical(name.trim())).collect(),
+            )),
+            (None, kind) => kind.map(JunctionWhitelist::Kind),
+        },
+        ..inventory.search_options()
+    };
+    let equipment = select(&inventory, &selection);
+    if let Err(e) = options.check(&equipment) {
+        eprintln!("{}", e);
+        exit(1);
+    }
+
+    let mut out = output(output_path);
+    if let Some((w_cost, w_len)) = optimal {
+        match optimal_chain(from, to, &equipment, w_cost, w_len).map(show_ends) {
+            Some(chain) => print_chain(&mut out, &chain, format),
+            None => {
+                eprintln!("no chain found");
+                exit(1);
+            }
+        }
+        finish(out);
+        return;
+    }
+
+    // `--to` can be a prefix of several threads' names; group the results if so.
+    if best {
+        let deadline = deadline.map(|d| Instant::now() + d);
+        let (chain, stats) = best_chain(from, to, &equipment, &options, deadline);
+        match chain.map(show_ends) {
+            Some(chain) => {
+                print_chain(&mut out, &chain, format);
+                if any_gender_end && format == OutputFormat::Text {
+                    print_exposed(&mut out, &chain);
+                }
+            }
+            None if format == OutputFormat::Json => outln!(out, "null"),
+            None => (),
+        }
+        finish(out);
+        if stats.timed_out {
+            eprintln!("stopped at the deadline: best found {}, lower bound {}",
+                stats.best_score.map_or("none".to_owned(), |s| s.to_string()),
+                stats.lower_bound.map_or("none".to_owned(), |s| s.to_string()));
+        }
+        warn_truncated(&stats);
+        if profile {
+            eprintln!("profile: {}", stats);
+        }
+        return;
+    }
+
+    // JSON of every chain from one search, in no particular order, is written as the chains are
+    // found instead of being held on to until the search is done.
+    let streams = format == OutputFormat::Json && sort.is_none() && limit.is_none()
+        && !minimal_only && pick.is_none() && checkpoint.is_none() && dump_tree.is_none();
+    if streams && threads_with_prefix(to.name(), &equipment).len() <= 1 {
+        let end = single_end(to, &equipment, "streamed");
+        let mut chains = make_chain_iter(from, end, &equipment, &options);
+        write_json(&mut out, chains.by_ref().map(show_ends));
+        finish(out);
+        warn_truncated(chains.stats());
+        if profile {
+            eprintln!("profile: {}", chains.stats());
+        }
+        return;
+    }
+
+    // Results are numbered in the order they're printed, across groups, for `--pick`.
+    let (groups, stats) = match checkpoint {
+        Some(checkpoint) => {
+            let (end, chains, stats) =
+                chain_with_checkpoints(from, to, &equipment, &options, checkpoint,
+                    checkpoint_every, resume);
+            (vec![(end, chains)], stats)
+        }
+        None => match dump_tree {
+            Some(dump) => {
+                let end = single_end(to, &equipment, "dumped");
+                let (chains, stats, tree) = make_chain_with_tree(from, end, &equipment, &options);
+                if let Err(e) = fs::write(dump, tree.to_dot()) {
+                    eprintln!("{}: {}", dump, e);
+                    exit(1);
+                }
+                (vec![(end, chains)], stats)
+            }
+            None => make_chain_to_prefix(from, to, &equipment, &options),
+        },
+    };
+    let sort = sort.unwrap_or_default();
+    let mut groups = groups.into_iter()
+        .map(|(end, chains)| {
+            let chains = if minimal_only { minimal_chains(chains) } else { chains };
+            (end, chains.into_iter().map(show_ends).collect::<Vec<_>>())
+        })
+        .collect::<Vec<_>>();
+    for (_end, chains) in &mut groups {
+        if !sort.is_empty() {
+            chains.sort_by(|a, b| a.cmp_by(b, &sort));
+        }
+        if let Some(limit) = limit {
+            chains.truncate(limit);
+        }
+    }
+    let show_diameter = sort.iter().any(|spec| spec.key == SortKey::MinDiameter);
+    if let Some(pick) = pick {
+        match groups.iter().flat_map(|(_end, chains)| chains).nth(pick.wrapping_sub(1)) {
+            Some(chain) if instructions => outln!(out, "{}", chain.instructions().trim_end()),
+            Some(chain) if format == OutputFormat::Text => outln!(out, "{}", chain.detailed()),
+            Some(chain) => print_chain(&mut out, chain, format),
+            None => {
+                eprintln!("there is no result number {}", pick);
+                exit(1);
+            }
+        }
+    } else if format == OutputFormat::Dot {
+        let chains = groups.into_iter().flat_map(|(_end, chains)| chains).collect::<Vec<_>>();
+        outln!(out, "{}", chains_to_dot(&chains).trim_end());
+    } else if format == OutputFormat::Json {
+        write_json(&mut out, groups.into_iter().flat_map(|(_end, chains)| chains));
+    } else {
+        let mut n = 0;
+        for (end, chains) in &groups {
+            if groups.len() > 1 {
+                outln!(out, "== {} ==", end);
+            }
+            for chain in chains {
+                n += 1;
+                let shown = if joints {
+                    chain.joints_display()
+                } else if collapse {
+                    chain.collapsed_display()
+                } else {
+                    chain.to_string()
+                };
+                match chain.metrics().min_diameter {
+                    Some((_, diameter)) if show_diameter => {
+                        outln!(out, "{}. {} (min {} mm)", n, shown.trim_end(), diameter);
+                    }
+                    _ => outln!(out, "{}. {}", n, shown),
+                }
+                if any_gender_end {
+                    print_exposed(&mut out, chain);
+                }
+                print_fit_notes(&mut out, chain);
+                print_loans(&mut out, chain);
+                print_kits(&mut out, chain);
+            }
+        }
+    }
+    finish(out);
+    warn_truncated(&stats);
+    if profile {
+        eprintln!("profile: {}", stats);
     }
 }
 
-impl Hash for Adapter {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        // To allow it to be reversed freely without changing its hash value:
-        if self.0 < self.1 {
-            self.0.hash(state);
-            self.1.hash(state);
+/// The exhaustive search for `chain --checkpoint` and `--resume`, saving its progress to a file as
+/// it goes, and, if it stops at `--max-states`, saying how to carry on. The checkpoint is JSON.
+/// Only one search can be checkpointed, so the `--to` thread mustn't be a prefix of several.
+fn chain_with_checkpoints(
+    from: Thread,
+    to: Thread,
+    equipment: &[Adapter],
+    options: &SearchOptions,
+    checkpoint: &str,
+    every: Duration,
+    resume: Option<&str>,
+) -> (Thread, Vec<Chain>, SearchStats) {
+    let end = single_end(to, equipment, "checkpointed");
+    let resume = resume.map(|file| {
+        let saved = fs::read_to_string(file).and_then(|json| Ok(serde_json::from_str(&json)?));
+        saved.unwrap_or_else(|e| {
+            eprintln!("{}: {}", file, e);
+            exit(1);
+        })
+    });
+    let mut save = |state: &SearchCheckpoint| {
+        // Write it alongside and rename it into place, so a checkpoint is never half written.
+        let temp = format!("{}.tmp", checkpoint);
+        let json = serde_json::to_string(state).expect("checkpoints always serialize");
+        if let Err(e) = fs::write(&temp, json).and_then(|()| fs::rename(&temp, checkpoint)) {
+            eprintln!("{}: {}", checkpoint, e);
+            exit(1);
+        }
+    };
+    match make_chain_with_checkpoints(from, end, equipment, options, resume, every, &mut save) {
+        Ok((chains, stats)) => {
+            if stats.truncated {
+                eprintln!("progress was saved to {}; to carry on, run the same command with \
+                    --resume {} and a higher --max-states", checkpoint, checkpoint);
+            }
+            (end, chains, stats)
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    }
+}
+
+/// The one thread `--to` is a prefix of, for things that only work on one search, which are
+/// `done` to it.
+fn single_end(to: Thread, equipment: &[Adapter], done: &str) -> Thread {
+    match threads_with_prefix(to.name(), equipment)[..] {
+        [name] => to.renamed(name),
+        [] => to,
+        _ => {
+            eprintln!("--to {} matches several threads, but only one search can be {}", to.name(),
+                done);
+            exit(2);
+        }
+    }
+}
+
+/// Explain a search that hit `--max-states`, and what to do about it.
+fn warn_truncated(stats: &SearchStats) {
+    if stats.truncated {
+        eprintln!("partial results: the search stopped after {} states (the --max-states",
+            stats.states_explored);
+        eprintln!("limit), so there may be chains it didn't find. Raise the limit, or narrow the");
+        eprintln!("search with --via, --avoid-thread, or --kit.");
+    }
+}
+
+/// A reference sheet of every pair of threads that can be connected, with the fewest adapters it
+/// takes, and optionally one such chain. `--thread` limits it to pairs between the given threads.
+fn pairs(args: &[String]) {
+    let mut show_chains = false;
+    let mut only = vec![];
+    let mut markdown = false;
+    let mut selection = Selection::default();
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--show-chains" => show_chains = true,
+            "--thread" => only.push(value(&mut args)),
+            "--format" => match value(&mut args) {
+                "text" => markdown = false,
+                "markdown" => markdown = true,
+                _ => usage(),
+            },
+            "--kit" => selection.kits.push(value(&mut args).to_owned()),
+            "--without-kit" => selection.without_kits.push(value(&mut args).to_owned()),
+            _ if path.is_none() => path = Some(arg.as_str()),
+            _ => usage(),
+        }
+    }
+    let Some(path) = path else { usage() };
+
+    let inventory = load(path, &LoadOptions::default());
+    let registry = inventory.registry();
+    let only = only.into_iter().map(|name| registry.canonical(name)).collect::<Vec<_>>();
+    let equipment = select(&inventory, &selection);
+    let threads = mating_threads(&equipment)
+        .into_iter()
+        .filter(|t| only.is_empty() || only.contains(&t.name()))
+        .collect::<Vec<_>>();
+
+    if markdown {
+        if show_chains {
+            println!("| From | To | Adapters | Chain |");
+            println!("|---|---|---|---|");
         } else {
-            self.1.hash(state);
-            self.0.hash(state);
+            println!("| From | To | Adapters |");
+            println!("|---|---|---|");
+        }
+    }
+    // Threads that mate directly don't need a chain, so they aren't listed.
+    for (a, b, chain) in shortest_chains_for_pairs(&thread_pairs(&threads), &equipment) {
+        let len = chain.adapters().len();
+        match (markdown, show_chains) {
+            (false, false) => println!("{} -> {}: {}", a, b, len),
+            (false, true) => println!("{} -> {}: {}\n    {}", a, b, len, chain),
+            (true, false) => println!("| {} | {} | {} |", a, b, len),
+            (true, true) => {
+                println!("| {} | {} | {} | {} |", a, b, len, chain.to_string().trim_end());
+            }
         }
     }
 }
 
-impl PartialEq for Adapter {
-    fn eq(&self, other: &Self) -> bool {
-        // Matches the same adapter reversed:
-        (self.0 == other.0 && self.1 == other.1)
-            || (self.0 == other.1 && self.1 == other.0)
+/// The `--pair`s asked for, or if none were, every pair of threads the equipment has (only those
+/// named with `--thread`, if any were).
+fn select_pairs(
+    only: Vec<&str>,
+    wanted: Vec<(&str, &str)>,
+    inventory: &Inventory,
+    equipment: &[Adapter],
+) -> Vec<(Thread, Thread)> {
+    if wanted.is_empty() {
+        let registry = inventory.registry();
+        let only = only.into_iter().map(|name| registry.canonical(name)).collect::<Vec<_>>();
+        let threads = mating_threads(equipment)
+            .into_iter()
+            .filter(|t| only.is_empty() || only.contains(&t.name()))
+            .collect::<Vec<_>>();
+        thread_pairs(&threads)
+    } else {
+        wanted.into_iter()
+            .map(|(a, b)| (parse_end(a, inventory).0, parse_end(b, inventory).0))
+            .collect()
     }
 }
 
-impl Display for Adapter {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        if !self.2.is_empty() {
-            f.write_str(&self.2)?;
-            if self.0 != NIL_THREAD && self.1 != NIL_THREAD {
-                return Ok(());
-            } else {
-                f.write_str(": ")?;
+/// How often each item is used in the shortest chains between pairs of threads: every pair the
+/// equipment can attach to, or just the ones given with `--pair`.
+fn utilization(args: &[String]) {
+    let mut only = vec![];
+    let mut wanted = vec![];
+    let mut csv = false;
+    let mut selection = Selection::default();
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--thread" => only.push(value(&mut args)),
+            "--pair" => match value(&mut args).split_once(',') {
+                Some(pair) => wanted.push(pair),
+                None => usage(),
+            },
+            "--format" => match value(&mut args) {
+                "text" => csv = false,
+                "csv" => csv = true,
+                _ => usage(),
+            },
+            "--kit" => selection.kits.push(value(&mut args).to_owned()),
+            "--without-kit" => selection.without_kits.push(value(&mut args).to_owned()),
+            _ if path.is_none() => path = Some(arg.as_str()),
+            _ => usage(),
+        }
+    }
+    let Some(path) = path else { usage() };
+
+    let inventory = load(path, &LoadOptions::default());
+    let equipment = select(&inventory, &selection);
+    let pairs = select_pairs(only, wanted, &inventory, &equipment);
+    let chains = shortest_chains_for_pairs(&pairs, &equipment)
+        .into_iter()
+        .map(|(_, _, chain)| chain)
+        .collect::<Vec<_>>();
+    let counts = adapter_utilization(&chains, &equipment);
+
+    if csv {
+        let mut writer = csv::Writer::from_writer(std::io::stdout());
+        let rows = std::iter::once(["item".to_owned(), "chains".to_owned(), "of".to_owned()])
+            .chain(counts.iter().map(|(adapter, count)| {
+                [adapter.to_string(), count.to_string(), chains.len().to_string()]
+            }));
+        for row in rows {
+            writer.write_record(&row).expect("writing to stdout");
+        }
+        writer.flush().expect("writing to stdout");
+    } else {
+        for (adapter, count) in &counts {
+            println!("[{}] appears in {} of {} shortest chains", adapter, count, chains.len());
+        }
+    }
+}
+
+/// What the inventory is worth, from each entry's price and quantity: the total, by category, the
+/// ten most valuable entries, and how many pieces have no price. `--replacement` prices those from
+/// a catalog (any inventory file) where it has the same threads. CSV gives a row per entry instead.
+fn value_report(args: &[String]) {
+    let mut format = "text";
+    let mut replacement = None;
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => format = value(&mut args),
+            "--replacement" => replacement = Some(value(&mut args)),
+            _ if path.is_none() => path = Some(arg.as_str()),
+            _ => usage(),
+        }
+    }
+    let Some(path) = path else { usage() };
+
+    let inventory = load(path, &LoadOptions::default());
+    let catalog = replacement.map(|path| load(path, &LoadOptions::default()));
+    let valuation = inventory.valuation(catalog.as_ref());
+    match format {
+        "text" => {
+            println!("total: {:.2}", valuation.total);
+            match valuation.unpriced {
+                0 => (),
+                1 => println!("(1 piece has no price, so isn't counted)"),
+                n => println!("({} pieces have no price, so aren't counted)", n),
+            }
+            if !valuation.by_category.is_empty() {
+                println!("by category:");
+                for (category, total) in &valuation.by_category {
+                    println!("    {}: {:.2}", category, total);
+                }
+            }
+            let priced = valuation.items.iter().filter(|item| item.price.is_some());
+            if priced.clone().next().is_some() {
+                println!("most valuable:");
+            }
+            for (n, item) in priced.clone().take(10).enumerate() {
+                println!("{:4}. [{}] {}x {:.2} = {:.2}", n + 1, item.item, item.quantity,
+                    item.price.unwrap_or_default(), item.value);
+            }
+            let replaced = priced.filter(|item| item.source == PriceSource::Replacement)
+                .collect::<Vec<_>>();
+            if !replaced.is_empty() {
+                println!("priced from the replacement catalog:");
+                for item in replaced {
+                    println!("    [{}]", item.item);
+                }
             }
         }
-        if self.0 != NIL_THREAD {
-            self.0.fmt(f)?;
-            if self.1 != NIL_THREAD {
-                f.write_str(" -> ")?;
+        "csv" => {
+            let mut writer = csv::Writer::from_writer(std::io::stdout());
+            let header = ["item", "category", "quantity", "price", "value", "source"]
+                .map(String::from);
+            let rows = std::iter::once(header).chain(valuation.items.iter().map(|item| [
+                item.item.clone(),
+                item.category.clone(),
+                item.quantity.to_string(),
+                item.price.map_or_else(String::new, |price| price.to_string()),
+                item.value.to_string(),
+                item.source.to_string(),
+            ]));
+            for row in rows {
+                writer.write_record(&row).expect("writing to stdout");
             }
+            writer.flush().expect("writing to stdout");
         }
-        if self.1 != NIL_THREAD {
-            self.1.fmt(f)?;
+        "json" => {
+            let json = serde_json::to_string_pretty(&valuation);
+            println!("{}", json.expect("valuations always serialize"));
         }
-        Ok(())
+        _ => usage(),
     }
 }
 
-#[derive(Debug, Clone)]
-struct Chain(Vec<Adapter>);
+/// The cheapest things to buy from a catalog (any inventory file, with prices) so that every
+/// connection in a wants file can be made, with the chain for each.
+fn plan(args: &[String]) {
+    let mut wants = None;
+    let mut catalog = None;
+    let mut selection = Selection::default();
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--wants" => wants = Some(value(&mut args)),
+            "--catalog" => catalog = Some(value(&mut args)),
+            "--kit" => selection.kits.push(value(&mut args).to_owned()),
+            "--without-kit" => selection.without_kits.push(value(&mut args).to_owned()),
+            _ if path.is_none() => path = Some(arg.as_str()),
+            _ => usage(),
+        }
+    }
+    let (Some(wants), Some(catalog), Some(path)) = (wants, catalog, path) else { usage() };
+
+    let inventory = load(path, &LoadOptions::default());
+    let wants = load_wants(wants, &inventory).unwrap_or_else(|e| {
+        eprintln!("failed to load {}: {}", wants, e);
+        exit(1);
+    });
+    let catalog = load(catalog, &LoadOptions::default());
+    let catalog = catalog.entries().iter().map(|e| e.adapter.clone()).collect::<Vec<_>>();
+    let unpriced = catalog.iter().filter(|a| a.info().price.is_none()).count();
+    match unpriced {
+        0 => (),
+        1 => eprintln!("1 catalog item has no price, so isn't considered"),
+        n => eprintln!("{} catalog items have no price, so aren't considered", n),
+    }
+    let equipment = select(&inventory, &selection);
+    let plan = plan_purchases(&wants, &equipment, &catalog);
 
-impl Chain {
-    pub fn new(adapter: Adapter) -> Self {
-        Self(vec![adapter])
+    if plan.buy.is_empty() {
+        println!("nothing needs buying");
+    } else {
+        println!("buy:");
+        for adapter in &plan.buy {
+            println!("    [{}] {:.2}", adapter, adapter.info().price.unwrap_or_default());
+        }
+        println!("total: {:.2}", plan.cost);
+    }
+    for (from, to, chain) in &plan.chains {
+        println!("{} to {}:\n    {}", from, to, chain);
     }
+    if !plan.impossible.is_empty() {
+        println!("can't be connected with anything in the catalog:");
+        for (from, to) in &plan.impossible {
+            println!("    {} to {}", from, to);
+        }
+    }
+    if !plan.optimal {
+        eprintln!("note: the catalog has more than {} items that could help, so this was planned \
+            greedily; a cheaper set of purchases may exist", EXACT_PLAN_LIMIT);
+    }
+}
 
-    pub fn add(&self, next: Adapter) -> Option<Self> {
-        let last = self.0.last().unwrap().1;
-        if last == next.0.opposite() {
-            let mut new = self.clone();
-            new.0.push(next);
-            Some(new)
-        } else if last == next.1.opposite() {
-            let mut new = self.clone();
-            new.0.push(next.reverse());
-            Some(new)
+/// How much a suggestion adds to what's connected already, like " (+12.5%)", for the end of a line.
+fn percent(baseline: usize, gain: usize) -> String {
+    match percent_gain(baseline, gain) {
+        Some(percent) => format!(" (+{:.1}%)", percent),
+        None => String::new(),
+    }
+}
+
+/// Owned adapters that would be better traded for something else, each with what the trade keeps
+/// connected and what it newly connects.
+fn swaps(args: &[String]) {
+    let mut mode = AdditionMode::Any;
+    let mut max_states = Some(DEFAULT_MAX_STATES);
+    let mut deadline = None;
+    let mut selection = Selection::default();
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--filter-steps" => mode = AdditionMode::FilterSteps,
+            "--max-states" => {
+                max_states = Some(value(&mut args).parse().unwrap_or_else(|_| usage()));
+            }
+            "--deadline" => deadline = Some(parse_duration(value(&mut args))),
+            "--kit" => selection.kits.push(value(&mut args).to_owned()),
+            "--without-kit" => selection.without_kits.push(value(&mut args).to_owned()),
+            _ if path.is_none() => path = Some(arg.as_str()),
+            _ => usage(),
+        }
+    }
+    let Some(path) = path else { usage() };
+
+    let inventory = load(path, &LoadOptions::default());
+    let equipment = select(&inventory, &selection);
+    let deadline = deadline.map(|d| Instant::now() + d);
+    let (swaps, mut stats) = find_swaps(&equipment, mode, max_states, deadline);
+    let (baseline, baseline_stats) = count_connected_pairs(&equipment, max_states);
+    stats.merge(&baseline_stats);
+
+    if swaps.is_empty() {
+        println!("no swaps would connect anything more");
+    }
+    for swap in &swaps {
+        let more = swap.enabled.len();
+        println!("retire [{}], buy [{}]: {} more connection{}{}",
+            swap.retire, swap.buy, more, if more == 1 { "" } else { "s" }, percent(baseline, more));
+        for (a, b) in &swap.preserved {
+            println!("    keeps {} -> {}", a, b);
+        }
+        for (a, b) in &swap.enabled {
+            println!("    adds {} -> {}", a, b);
+        }
+    }
+    if stats.timed_out {
+        eprintln!("partial results: stopped at the deadline before trying every swap");
+    }
+    if stats.truncated {
+        eprintln!("partial results: some searches stopped at the state limit, so some swaps may be \
+            missing");
+    }
+}
+
+/// Items that are never used without some other item, and might as well be kept as one.
+fn together(args: &[String]) {
+    let mut only = vec![];
+    let mut wanted = vec![];
+    let mut max_len = None;
+    let mut max_states = Some(DEFAULT_MAX_STATES);
+    let mut selection = Selection::default();
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--thread" => only.push(value(&mut args)),
+            "--pair" => match value(&mut args).split_once(',') {
+                Some(pair) => wanted.push(pair),
+                None => usage(),
+            },
+            "--max-len" => max_len = Some(value(&mut args).parse().unwrap_or_else(|_| usage())),
+            "--max-states" => {
+                max_states = Some(value(&mut args).parse().unwrap_or_else(|_| usage()));
+            }
+            "--kit" => selection.kits.push(value(&mut args).to_owned()),
+            "--without-kit" => selection.without_kits.push(value(&mut args).to_owned()),
+            _ if path.is_none() => path = Some(arg.as_str()),
+            _ => usage(),
+        }
+    }
+    let Some(path) = path else { usage() };
+
+    let inventory = load(path, &LoadOptions::default());
+    let equipment = select(&inventory, &selection);
+    let pairs = select_pairs(only, wanted, &inventory, &equipment);
+    let (together, stats) = always_together(&pairs, &equipment, max_len, max_states);
+
+    if together.is_empty() {
+        println!("nothing is only ever used together with something else");
+    }
+    let chains = |n: usize| if n == 1 { "1 chain".to_owned() } else { format!("{} chains", n) };
+    for pair in &together {
+        if pair.mutual {
+            println!("[{}] and [{}] are always used together, in {}; could be kept as one item",
+                pair.adapter, pair.with, chains(pair.chains));
         } else {
-            None
+            println!("[{}] is never used without [{}], in {}; could be kept on it",
+                pair.adapter, pair.with, chains(pair.chains));
+        }
+    }
+    if stats.truncated {
+        eprintln!("partial results: some searches stopped at the state limit");
+    }
+}
+
+/// Rewrite an inventory file in canonical form: aliases resolved, entries sorted, and duplicates
+/// merged (including ones listed backwards, with `--dedup-reversed`). Comments are not preserved.
+/// With `--check`, print what would change instead, and exit with status 1 if anything would.
+fn fmt(args: &[String]) {
+    let mut check = false;
+    let mut options = LoadOptions::default();
+    let mut path = None;
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            "--infer-gender" => options.infer_gender = true,
+            "--dedup-reversed" => options.dedup_reversed = true,
+            _ if path.is_none() => path = Some(arg.as_str()),
+            _ => usage(),
+        }
+    }
+    let Some(path) = path else { usage() };
+    require_toml(path);
+
+    let original = fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("failed to read {}: {}", path, e);
+        exit(1);
+    });
+    let mut inventory = load(path, &options);
+    inventory.normalize();
+    let formatted = inventory.to_toml_string();
+
+    // Never write out something that doesn't load back as exactly what we meant to write.
+    match Inventory::from_toml_str(&formatted, &LoadOptions::default()) {
+        Ok(reloaded) if reloaded == inventory => (),
+        _ => {
+            eprintln!("{}: formatted output doesn't round-trip; not writing it", path);
+            exit(1);
         }
     }
+
+    if formatted == original {
+        return;
+    }
+    if check {
+        print!("{}", line_diff(&original, &formatted));
+        exit(1);
+    }
+    write_inventory(path, &formatted, "fmt", "reformatted");
 }
 
-impl Display for Chain {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        for a in &self.0 {
-            write!(f, "[{}] ", a)?;
+/// Rename a thread throughout an inventory file. Renaming onto a thread that's already in use
+/// merges the two, which changes what can connect to what, so that needs `--force`.
+fn rename_thread(args: &[String]) {
+    let mut force = false;
+    let mut positional = vec![];
+    for arg in args {
+        match arg.as_str() {
+            "--force" => force = true,
+            _ => positional.push(arg.as_str()),
         }
-        Ok(())
     }
+    let [old, new, path] = positional[..] else { usage() };
+    require_toml(path);
+
+    let mut inventory = load(path, &LoadOptions::default());
+    let registry = inventory.registry();
+    let (old_name, new_name) = (registry.canonical(old), registry.canonical(new));
+    let names = inventory.thread_names();
+    let merging = old_name != new_name && names.contains(old_name) && names.contains(new_name);
+    if merging && !force {
+        eprintln!("{} is already used in {}; renaming {} would merge the two threads", new_name,
+            path, old_name);
+        eprintln!("use --force to do it anyway");
+        exit(1);
+    }
+
+    let before = merging.then(|| count_connections(&inventory.all_equipment()));
+    let changed = inventory.entries().iter()
+        .filter(|e| {
+            let (a, b) = e.adapter.threads();
+            a.name() == old_name || b.name() == old_name
+        })
+        .count();
+    inventory.rename_thread(old, new);
+    let summary = format!("renamed {} to {} in {} entries", old_name, new_name, changed);
+    println!("{}", summary);
+    if let Some(before) = before {
+        let after = count_connections(&inventory.all_equipment());
+        println!("connectable thread pairs: {} before, {} after", before, after);
+    }
+
+    save(path, &inventory, "rename-thread", &summary);
 }
 
-fn make_chain(start: Thread, end: Thread, equipment: &[Adapter]) -> Vec<Chain> {
-    #[derive(Debug, Clone)]
-    struct State {
-        used: HashSet<Adapter>,
-        chain: Chain,
+fn save(path: &str, inventory: &Inventory, command: &str, summary: &str) {
+    write_inventory(path, &inventory.to_toml_string(), command, summary);
+}
+
+/// Replace an inventory file's contents, recording the change in its journal.
+fn write_inventory(path: &str, contents: &str, command: &str, summary: &str) {
+    let before = fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("failed to read {}: {}", path, e);
+        exit(1);
+    });
+    if let Err(e) = journal::write_atomically(Path::new(path), contents) {
+        eprintln!("failed to write {}: {}", path, e);
+        exit(1);
+    }
+    // The inventory is what matters; a journal that can't be written is worth a warning, no more.
+    let journal_path = journal::journal_path(Path::new(path));
+    let entry = JournalEntry {
+        timestamp: journal::timestamp(SystemTime::now()),
+        command: command.to_owned(),
+        summary: summary.to_owned(),
+        before,
+        after_hash: journal::content_hash(contents),
+        undone: false,
+    };
+    if let Err(e) = journal::append(&journal_path, &entry) {
+        eprintln!("{}: warning: failed to update journal: {}", journal_path.display(), e);
     }
+}
 
-    let mut states = vec![State {
-        used: HashSet::new(),
-        chain: Chain::new(Adapter::new(NIL_THREAD, start).with_name("start")),
-    }];
+/// The entry an item description on the command line refers to. It has to be exactly one.
+fn find_entry(inventory: &Inventory, item: &str) -> usize {
+    match inventory.find(item)[..] {
+        [idx] => idx,
+        [] => {
+            eprintln!("nothing in the inventory matches {:?}", item);
+            exit(1);
+        }
+        _ => {
+            eprintln!("{:?} matches more than one entry; use its threads or a unique name", item);
+            exit(1);
+        }
+    }
+}
 
-    let mut found = vec![];
+/// The adapter an item description on the command line refers to.
+fn item_adapter(inventory: &Inventory, item: &str) -> Adapter {
+    inventory.entries()[find_entry(inventory, item.trim())].adapter.clone()
+}
 
-    while let Some(state) = states.pop() {
-        for a in equipment {
-            if state.used.contains(a) {
-                continue;
+/// Today's date (UTC) as YYYY-MM-DD.
+fn today() -> String {
+    journal::timestamp(SystemTime::now())[..10].to_owned()
+}
+
+/// Mark an item as lent to someone. Lent items are left out of searches until they're returned.
+fn loan(args: &[String]) {
+    let mut to = None;
+    let mut on = None;
+    let mut positional = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--to" => to = Some(value(&mut args)),
+            "--on" => on = Some(value(&mut args).to_owned()),
+            _ => positional.push(arg.as_str()),
+        }
+    }
+    let (&[item, path], Some(to)) = (&positional[..], to) else { usage() };
+    require_toml(path);
+
+    let mut inventory = load(path, &LoadOptions::default());
+    let idx = find_entry(&inventory, item);
+    let entry = &mut inventory.entries_mut()[idx];
+    if let Some(who) = entry.adapter.loaned_to() {
+        eprintln!("[{}] is already with {}", entry.adapter, who);
+        exit(1);
+    }
+    let on = on.unwrap_or_else(today);
+    entry.adapter = entry.adapter.clone().with_loan(Some(to.to_owned()), Some(on));
+    let summary = format!("[{}] is now with {}", entry.adapter, to);
+    println!("{}", summary);
+    save(path, &inventory, "loan", &summary);
+}
+
+fn return_loan(args: &[String]) {
+    let [item, path] = args else { usage() };
+    require_toml(path);
+
+    let mut inventory = load(path, &LoadOptions::default());
+    let idx = find_entry(&inventory, item);
+    let entry = &mut inventory.entries_mut()[idx];
+    let Some(who) = entry.adapter.loaned_to().map(str::to_owned) else {
+        eprintln!("[{}] isn't lent out", entry.adapter);
+        exit(1);
+    };
+    entry.adapter = entry.adapter.clone().with_loan(None, None);
+    let summary = format!("[{}] is back from {}", entry.adapter, who);
+    println!("{}", summary);
+    save(path, &inventory, "return", &summary);
+}
+
+/// Show the history of changes made to an inventory file, optionally only those from some date
+/// (or month, or year) onward.
+fn log(args: &[String]) {
+    let mut since = None;
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--since" => since = Some(value(&mut args)),
+            _ if path.is_none() => path = Some(arg.as_str()),
+            _ => usage(),
+        }
+    }
+    let Some(path) = path else { usage() };
+
+    let (_, entries) = read_journal(path);
+    // Timestamps sort as strings, and a partial date like "2024-01" sorts before every timestamp
+    // in that month.
+    for entry in &entries {
+        if since.is_some_and(|since| entry.timestamp.as_str() < since) {
+            continue;
+        }
+        let undone = if entry.undone { " (undone)" } else { "" };
+        println!("{}  {}: {}{}", entry.timestamp, entry.command, entry.summary, undone);
+    }
+}
+
+/// The journal for an inventory file, and where it is.
+fn read_journal(path: &str) -> (PathBuf, Vec<JournalEntry>) {
+    let journal_path = journal::journal_path(Path::new(path));
+    match journal::read(&journal_path) {
+        Ok((entries, warnings)) => {
+            for warning in warnings {
+                eprintln!("{}: warning: {}", journal_path.display(), warning);
             }
-            if let Some(mut chain) = state.chain.add(a.clone()) {
-                if chain.0.last().unwrap().1.opposite() == end {
-                    chain.0.push(Adapter::new(end, NIL_THREAD).with_name("end"));
-                    found.push(chain);
-                } else {
-                    let mut used = state.used.clone();
-                    used.insert(a.clone());
-                    states.push(State { used, chain });
-                }
+            (journal_path, entries)
+        }
+        Err(e) => {
+            eprintln!("failed to read {}: {}", journal_path.display(), e);
+            exit(1);
+        }
+    }
+}
+
+/// Put an inventory file back how it was before the last few changes recorded in its journal.
+fn undo(args: &[String]) {
+    let mut steps = 1;
+    let mut dry_run = false;
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--steps" => steps = value(&mut args).parse().unwrap_or_else(|_| usage()),
+            "--dry-run" => dry_run = true,
+            _ if path.is_none() => path = Some(arg.as_str()),
+            _ => usage(),
+        }
+    }
+    let Some(path) = path else { usage() };
+    require_toml(path);
+
+    let current = fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("failed to read {}: {}", path, e);
+        exit(1);
+    });
+    let (journal_path, mut entries) = read_journal(path);
+    let (stack, edited) = journal::undo_stack(&entries, &current);
+    if stack.len() < steps {
+        let available = match stack.len() {
+            0 => "nothing can be undone".to_owned(),
+            1 => "only 1 change can be undone".to_owned(),
+            n => format!("only {} changes can be undone", n),
+        };
+        if edited {
+            // The change the stack stopped at, which is the last one the file still matched.
+            let before = stack.last().map_or(entries.len(), |&i| i);
+            let after = entries[..before].iter().rev().find(|e| !e.undone).unwrap();
+            eprintln!("{}: {}: the file was edited outside adapter-party after {} ({}: {})", path,
+                available, after.timestamp, after.command, after.summary);
+            eprintln!("undoing further would lose that edit");
+        } else {
+            eprintln!("{}: {}", path, available);
+        }
+        exit(1);
+    }
+
+    let stack = &stack[..steps];
+    for &i in stack {
+        let entry = &entries[i];
+        println!("undo {}  {}: {}", entry.timestamp, entry.command, entry.summary);
+    }
+    let restored = entries[*stack.last().unwrap()].before.clone();
+    if dry_run {
+        print!("{}", line_diff(&current, &restored));
+        return;
+    }
+    if let Err(e) = journal::write_atomically(Path::new(path), &restored) {
+        eprintln!("failed to write {}: {}", path, e);
+        exit(1);
+    }
+    for &i in stack {
+        entries[i].undone = true;
+    }
+    if let Err(e) = journal::write(&journal_path, &entries) {
+        eprintln!("{}: warning: failed to update journal: {}", journal_path.display(), e);
+    }
+}
+
+/// List everything that's lent out.
+fn loans(args: &[String]) {
+    let [path] = args else { usage() };
+    let inventory = load(path, &LoadOptions::default());
+    for entry in inventory.entries() {
+        let info = entry.adapter.info();
+        if let Some(who) = &info.loaned_to {
+            match &info.loaned_on {
+                Some(on) => println!("[{}]: with {} since {}", entry.adapter, who, on),
+                None => println!("[{}]: with {}", entry.adapter, who),
             }
         }
     }
+}
 
-    found
+/// List groups of items that are interchangeable with each other, so the extras can live in the
+/// bag as spares.
+fn equivalents(args: &[String]) {
+    let [path] = args else { usage() };
+    let inventory = load(path, &LoadOptions::default());
+    for class in inventory.equivalence_classes() {
+        let total = class.iter().map(|e| e.quantity).sum::<u32>();
+        let (a, b) = class[0].adapter.threads();
+        println!("{} -> {}: {} interchangeable", a, b, total);
+        for entry in &class {
+            let name = match entry.adapter.name() {
+                "" => "(unnamed)",
+                name => name,
+            };
+            println!("  {}x {}", entry.quantity, name);
+        }
+        println!("  {} could be kept as spares", total - 1);
+        if class.iter().any(|e| e.adapter.category() != class[0].adapter.category()) {
+            println!("  note: these have different categories; check they really are the same \
+                thing");
+        }
+    }
 }
 
-/// For all possible adapters (using threads present on existing equipment), how many new chains do
-/// they make possible if they are added?
-fn find_useful_additions(equipment: &[Adapter]) -> Vec<(Adapter, usize)> {
-    let all_threads = equipment.iter()
-        .map(|adapter| adapter.0)
-        .chain(equipment.iter().map(|adapter| adapter.1))
-        .map(|thread| thread.opposite())
-        .collect::<HashSet<Thread>>();
-    
-    let all_adapters = all_threads.iter()
-        .flat_map(|a| {
-            all_threads.iter()
-                .map(|b| Adapter::new(*a, *b))
-        })
-        .collect::<HashSet<Adapter>>();
+/// List everything in the inventory; with `--verbose`, also categories and notes.
+fn list(args: &[String]) {
+    let (verbose, path) = match args {
+        [path] => (false, path),
+        [flag, path] if flag == "--verbose" => (true, path),
+        _ => usage(),
+    };
+    let inventory = load(path, &LoadOptions::default());
+    for entry in inventory.entries() {
+        println!("{}x [{}]", entry.quantity, entry.adapter);
+        if verbose {
+            let (a, b) = entry.adapter.threads();
+            let is_bayonet = |t: Thread| registry::coupling(t) == Coupling::Bayonet;
+            if !entry.adapter.name().is_empty() || is_bayonet(a) || is_bayonet(b) {
+                println!("    threads: {} -> {}", verbose_thread(a), verbose_thread(b));
+            }
+            if !entry.adapter.is_reversible() {
+                println!("    not reversible");
+            }
+            if let Some(diameter) = entry.adapter.info().outer_diameter {
+                println!("    outer diameter: {} mm", diameter);
+            }
+            if let Some(diameter) = entry.adapter.info().recess_diameter {
+                println!("    recess diameter: {} mm", diameter);
+            }
+            if let Some(length) = entry.adapter.info().male_length {
+                println!("    male thread length: {} mm", length);
+            }
+            if let Some(depth) = entry.adapter.info().female_depth {
+                println!("    female thread depth: {} mm", depth);
+            }
+            if let Some(cost) = entry.adapter.info().cost {
+                println!("    cost: {}", cost);
+            }
+            if let Some(length) = entry.adapter.info().length {
+                println!("    length: {} mm", length);
+            }
+            if let Some(price) = entry.adapter.info().price {
+                println!("    price: {}", price);
+            }
+            if !entry.adapter.info().collides_with.is_empty() {
+                println!("    collides with: {}", entry.adapter.info().collides_with.join(", "));
+            }
+            if let Some(category) = entry.adapter.category() {
+                println!("    category: {}", category);
+            }
+            if !entry.adapter.notes().is_empty() {
+                println!("    notes: {}", entry.adapter.notes());
+            }
+            if let Some(kit) = entry.adapter.kit() {
+                println!("    kit: {}", kit);
+            }
+        }
+    }
+    for terminal in inventory.terminals() {
+        println!("[{}]", terminal);
+        if verbose {
+            println!("    kind: {}", terminal.kind);
+        }
+    }
+}
 
-    fn count_chains(pairs: impl Iterator<Item=(Thread, Thread)>, equipment: &[Adapter]) -> usize {
-        pairs.map(|(a, b)| make_chain(a, b, equipment).len())
-            .map(|count| if count == 0 { 0 } else { 1 })
-            .sum()
+/// Every thread in the inventory, numbers in numeric order first, then the rest alphabetically,
+/// each with what has it on either end.
+fn threads(args: &[String]) {
+    let mut only = vec![];
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--thread" => only.push(value(&mut args)),
+            _ if path.is_none() => path = Some(arg.as_str()),
+            _ => usage(),
+        }
     }
+    let Some(path) = path else { usage() };
 
-    let start = count_chains(all_adapters.iter().map(|a| (a.0, a.1)), equipment);
+    let inventory = load(path, &LoadOptions::default());
+    let registry = inventory.registry();
+    let only = only.into_iter().map(|name| registry.canonical(name)).collect::<Vec<_>>();
+    let mut names = inventory.thread_names().into_iter()
+        .filter(|name| only.is_empty() || only.contains(name))
+        .collect::<Vec<_>>();
+    if names.is_empty() && !only.is_empty() {
+        eprintln!("nothing in {} has {}", path, only.join(" or "));
+        exit(1);
+    }
+    names.sort_by(|a, b| match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(x), Ok(y)) => x.total_cmp(&y),
+        (x, y) => x.is_err().cmp(&y.is_err()).then_with(|| a.cmp(b)),
+    });
+    for name in names {
+        println!("{}:", name);
+        for (i, entry) in inventory.entries().iter().enumerate() {
+            let (a, b) = entry.adapter.threads();
+            let ends = [a, b].into_iter().filter(|t| t.name() == name).collect::<BTreeSet<_>>();
+            if !ends.is_empty() {
+                let ends = ends.iter().map(Thread::to_string).collect::<Vec<_>>().join(" and ");
+                println!("  {}: [{}] (entry {})", ends, entry.adapter, i + 1);
+            }
+        }
+        for terminal in inventory.terminals().iter().filter(|t| t.thread.name() == name) {
+            println!("  {}: {} ({})", terminal.thread, terminal.name, terminal.kind);
+        }
+    }
+}
 
-    let mut results = vec![];
-    let mut new_equip = equipment.to_vec();
-    for new in &all_adapters {
-        new_equip.push(new.clone());
-        let count = count_chains(all_adapters.iter().map(|a| (a.0, a.1)), &new_equip);
-        results.push((new.clone(), count - start));
-        new_equip.pop();
+/// The threads no chain from a body (or whatever) gets to, with the gear that has them.
+fn stranded(args: &[String]) {
+    let mut from = None;
+    let mut selection = Selection::default();
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--from" => from = Some(value(&mut args)),
+            "--kit" => selection.kits.push(value(&mut args).to_owned()),
+            "--without-kit" => selection.without_kits.push(value(&mut args).to_owned()),
+            _ if path.is_none() => path = Some(arg.as_str()),
+            _ => usage(),
+        }
     }
+    let (Some(from), Some(path)) = (from, path) else { usage() };
 
-    results.sort_by_key(|(_a, n)| *n);
-    results
+    let inventory = load(path, &LoadOptions::default());
+    let equipment = select(&inventory, &selection);
+    let (start, _) = parse_end(from, &inventory);
+    let stranded = unreachable_from(start, &equipment);
+    if stranded.is_empty() {
+        println!("everything can be reached from {}", from);
+    }
+    for thread in stranded {
+        println!("{}:", thread);
+        for a in equipment.iter().filter(|a| <[Thread; 2]>::from(a.threads()).contains(&thread)) {
+            println!("  [{}]", a);
+        }
+    }
 }
 
-fn main() {
-    use Thread::*;
+/// Analyses of the whole inventory. So far there's `gaps`: the pairs of threads that can't be
+/// connected, closest first, with what to buy to connect each.
+fn analyze(args: &[String]) {
+    let Some((command, args)) = args.split_first() else { usage() };
+    if command != "gaps" {
+        usage();
+    }
+    let mut max_buy = 2;
+    let mut shapes = false;
+    let mut selection = Selection::default();
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--max-buy" => max_buy = value(&mut args).parse().unwrap_or_else(|_| usage()),
+            "--shapes" => shapes = true,
+            "--kit" => selection.kits.push(value(&mut args).to_owned()),
+            "--without-kit" => selection.without_kits.push(value(&mut args).to_owned()),
+            _ if path.is_none() => path = Some(arg.as_str()),
+            _ => usage(),
+        }
+    }
+    let Some(path) = path else { usage() };
+
+    let inventory = load(path, &LoadOptions::default());
+    let equipment = select(&inventory, &selection);
+    let gaps = find_gaps(&equipment, max_buy);
+    if gaps.is_empty() {
+        println!("every pair of threads can be connected");
+    }
+    let mut heading = None;
+    for gap in &gaps {
+        let distance = gap.distance();
+        if heading != Some(distance) {
+            match distance {
+                Some(n) => println!("{} adapter{} away:", n, if n == 1 { "" } else { "s" }),
+                None => println!("far apart (more than {} adapters):", max_buy),
+            }
+            heading = Some(distance);
+        }
+        match &gap.fix {
+            Some((buy, _)) => {
+                let buy = buy.iter().map(|a| shopping(a, shapes)).collect::<Vec<_>>().join(" + ");
+                println!("    {} to {}: buy {}", gap.start, gap.end, buy);
+            }
+            None => println!("    {} to {}", gap.start, gap.end),
+        }
+    }
+}
+
+/// How many pairs of threads (among those the equipment could connect to) have a chain between
+/// them.
+fn count_connections(equipment: &[Adapter]) -> usize {
+    let threads = equipment.iter()
+        .flat_map(|a| <[Thread; 2]>::from(a.mating_ends()))
+        .collect::<HashSet<_>>();
+    threads.iter()
+        .flat_map(|a| threads.iter().map(move |b| (*a, *b)))
+        .filter(|(a, b)| a != b && !make_chain(*a, *b, equipment).is_empty())
+        .count()
+}
+
+/// A thread with its coupling marked if it's a bayonet, like `EF[bayonet](F)`.
+fn verbose_thread(thread: Thread) -> String {
+    match registry::coupling(thread) {
+        Coupling::Bayonet => {
+            format!("{}[bayonet]({})", thread.name(), if thread.is_male() { "M" } else { "F" })
+        }
+        Coupling::Screw => thread.to_string(),
+    }
+}
+
+/// `2nd`, `3rd`, and so on.
+fn ordinal(n: usize) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+/// Minimal line-based diff, good enough for showing what `fmt` would change.
+fn line_diff(old: &str, new: &str) -> String {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+
+    // Longest common subsequence table, filled from the end.
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            out += &format!(" {}\n", old[i]);
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out += &format!("-{}\n", old[i]);
+            i += 1;
+        } else {
+            out += &format!("+{}\n", new[j]);
+            j += 1;
+        }
+    }
+    out
+}
+
+fn demo(args: &[String]) {
+    #[allow(non_snake_case)]
+    let (M, F) = (Thread::male, Thread::female);
+
+    let mut infer_gender = false;
+    let mut profile = false;
+    let mut max_states = Some(DEFAULT_MAX_STATES);
+    let mut deadline = None;
+    let mut mode = AdditionMode::Any;
+    let mut path = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--infer-gender" => infer_gender = true,
+            "--filter-steps" => mode = AdditionMode::FilterSteps,
+            "--profile" => profile = true,
+            "--max-states" => {
+                max_states = Some(value(&mut args).parse().unwrap_or_else(|_| usage()));
+            }
+            "--deadline" => deadline = Some(parse_duration(value(&mut args))),
+            _ => path = Some(arg),
+        }
+    }
+    let report = |stats: SearchStats| {
+        if profile {
+            eprintln!("profile: {}", stats);
+        }
+    };
 
     // All the random crap I own:
-    let mut equipment = vec![
+    let mut equipment = equipment![
         // Mount adapters:
-        Adapter::new(M("EF"), F("58")),
-        Adapter::new(M("EF"), F("LTM")),
-        Adapter::new(M("EF"), F("M42")),
-        Adapter::new(M("EF"), F("FD")),
+        "EF(M) -> 58(F)",
+        "EF(M) -> LTM(F)",
+        "EF(M) -> M42(F)",
+        "EF(M) -> FD(F)",
 
         // Gender / thread changers:
-        Adapter::new(M("58"), M("58")),
-        Adapter::new(M("Bay1"), F("46mm")),
+        "58(M) -> 58(M)",
+        "Bay1(M) -> 46mm(F)",
 
         // Step-up rings:
-        Adapter::new(M("40.5"), F("46")),
-        Adapter::new(M("46"), F("52")),
-        Adapter::new(M("46"), F("77")),
-        Adapter::new(M("52"), F("77")),
-        Adapter::new(M("55"), F("77")),
-        Adapter::new(M("58"), F("77")),
-        Adapter::new(M("62"), F("77")),
-        Adapter::new(M("72"), F("77")),
+        "40.5(M) -> 46(F)",
+        "46(M) -> 52(F)",
+        "46(M) -> 77(F)",
+        "52(M) -> 77(F)",
+        "55(M) -> 77(F)",
+        "58(M) -> 77(F)",
+        "62(M) -> 77(F)",
+        "72(M) -> 77(F)",
 
         // Step-down rings:
-        Adapter::new(M("72"), F("52")),
-        Adapter::new(M("58"), F("52")),
+        "72(M) -> 52(F)",
+        "58(M) -> 52(F)",
 
         // Lenses:
-        Adapter::new(M("LTM"), F("40.5")).with_name("Rodenstock Rodagon 50mm f/2.8"),
-        Adapter::new(M("LTM"), F("43")).with_name("Schneider Componon-S 80mm f/4"),
+        "LTM(M) -> 40.5(F)" as "Rodenstock Rodagon 50mm f/2.8" @ Lens,
+        "LTM(M) -> 43(F)" as "Schneider Componon-S 80mm f/4" @ Lens,
     ];
 
+    if let Some(path) = path {
+        equipment = load(path, &LoadOptions { infer_gender, ..LoadOptions::default() }).equipment();
+    }
+
     // EF camera body -> [?? some shit ??] -> 52mm male thread on a slide copier.
     // The correct chain should hopefully involve an enlarger lens.
-    let chains = make_chain(
+    let (chains, stats) = make_chain_with_stats(
         F("EF"),
         M("52"),
         &equipment,
@@ -255,11 +2079,12 @@ fn main() {
     for chain in chains {
         println!("{}", chain);
     }
+    report(stats);
 
     println!("---");
     // If I add this new piece, can I get one of the enlarger lenses on backwards?
-    equipment.push(Adapter::new(M("43"), F("58")).with_name("new 43-58"));
-    let chains = make_chain(
+    equipment.extend(equipment!["43(M) -> 58(F)" as "new 43-58"]);
+    let (chains, stats) = make_chain_with_stats(
         F("EF"),
         F("LTM"),
         &equipment,
@@ -267,10 +2092,31 @@ fn main() {
     for chain in chains {
         println!("{}", chain);
     }
+    report(stats);
 
     println!("---");
     equipment.pop(); // remove fake piece added earlier
-    for (adapter, count) in find_useful_additions(&equipment) {
-        println!("{}: {} new chains", adapter, count);
+    let deadline = deadline.map(|d| Instant::now() + d);
+    let (additions, mut stats) =
+        find_useful_additions_with_mode(&equipment, mode, max_states, deadline);
+    let (baseline, baseline_stats) = count_connected_pairs(&equipment, max_states);
+    for (adapter, count) in additions {
+        let owned = equipment.iter().filter(|a| **a == adapter).count();
+        let gain = percent(baseline, count);
+        if owned == 0 {
+            println!("{}: {} new chains{}", adapter, count, gain);
+        } else {
+            println!("{} copy of: {}: {} new chains{}", ordinal(owned + 1), adapter, count, gain);
+        }
+    }
+    println!("({} pairs of threads connected now)", baseline);
+    stats.merge(&baseline_stats);
+    if stats.timed_out {
+        eprintln!("partial results: stopped at the deadline before trying every adapter");
+    }
+    if stats.truncated {
+        eprintln!("partial results: some searches stopped at the state limit, so counts may be \
+            low");
     }
+    report(stats);
 }
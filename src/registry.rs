@@ -0,0 +1,348 @@
+//! What we know about thread names in general, independent of anyone's inventory.
+
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Display, Formatter},
+};
+
+use crate::{intern, Adapter, Category, Coupling, Gender, Thread, NIL_THREAD};
+
+/// Lens mounts whose gender can be guessed from which end of an adapter they're on.
+///
+/// An adapter's camera-facing end stands in for a lens, so a mount there is the lens side of the
+/// mount (male). Its far end stands in for a camera body, so a mount there is the body side
+/// (female). Screw threads like filter sizes are used both ways around, so they aren't listed.
+pub const KNOWN_MOUNTS: &[&str] = &[
+    "EF", "EF-M", "RF", "FD", "F", "K", "M42", "LTM", "E", "MFT", "X", "Z",
+];
+
+/// The category an adapter between two threads is evidently in, going by the threads alone.
+///
+/// Anything with a known mount on it is a mount adapter. Between two filter sizes, a male and a
+/// female end make a step ring, up or down going from `a` to `b`, and two ends the same size and
+/// gender make a gender changer. Anything else isn't obvious.
+pub fn infer_category(a: Thread, b: Thread) -> Option<Category> {
+    let is_mount = |t: Thread| KNOWN_MOUNTS.contains(&t.name());
+    if is_mount(a) || is_mount(b) {
+        return Some(Category::MountAdapter);
+    }
+    let (da, db) = (a.diameter()?, b.diameter()?);
+    if a.is_male() != b.is_male() {
+        match da.partial_cmp(&db)? {
+            Ordering::Less => Some(Category::StepUp),
+            Ordering::Greater => Some(Category::StepDown),
+            Ordering::Equal => None,
+        }
+    } else if da == db {
+        Some(Category::GenderChanger)
+    } else {
+        None
+    }
+}
+
+/// The kind of thing a shop sells an adapter as, with the numbers needed to find it: what to
+/// search for, rather than the exact threads on each end.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    /// A ring from a smaller male filter thread to a bigger female one.
+    StepUp { from: f64, to: f64 },
+    /// A ring from a bigger male filter thread to a smaller female one.
+    StepDown { from: f64, to: f64 },
+    /// A ring with the same size filter thread on both ends, both of one gender.
+    GenderChanger { size: f64, gender: Gender },
+    /// Something that lets a lens of one mount go on a body of another.
+    MountAdapter { lens: &'static str, body: &'static str },
+}
+
+impl Display for Shape {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StepUp { from, to } => write!(f, "{}-{}mm step-up ring", from, to),
+            Self::StepDown { from, to } => write!(f, "{}-{}mm step-down ring", from, to),
+            Self::GenderChanger { size, gender } => write!(f, "{}mm double {} ring", size, gender),
+            Self::MountAdapter { lens, body } => {
+                write!(f, "{} lens to {} body adapter", lens, body)
+            }
+        }
+    }
+}
+
+/// What a shop would sell an adapter as, if it's one of the usual kinds (see `infer_category`).
+///
+/// Step rings are named by their male end first, so either orientation of the same ring gives the
+/// same shape. A mount adapter's male end goes on the body, and its female end takes the lens.
+/// Anything else, like a mount to a filter thread, doesn't have a shape to shop for.
+pub fn shape(adapter: &Adapter) -> Option<Shape> {
+    let (a, b) = adapter.threads();
+    let (male, female) = if a.is_male() { (a, b) } else { (b, a) };
+    let is_mount = |t: Thread| KNOWN_MOUNTS.contains(&t.name());
+    if is_mount(a) || is_mount(b) {
+        return (is_mount(a) && is_mount(b) && a.is_male() != b.is_male() && a.name() != b.name())
+            .then(|| Shape::MountAdapter { lens: female.name(), body: male.name() });
+    }
+    match infer_category(male, female)? {
+        Category::StepUp => {
+            Some(Shape::StepUp { from: male.diameter()?, to: female.diameter()? })
+        }
+        Category::StepDown => {
+            Some(Shape::StepDown { from: male.diameter()?, to: female.diameter()? })
+        }
+        Category::GenderChanger => {
+            let gender = if a.is_male() { Gender::Male } else { Gender::Female };
+            Some(Shape::GenderChanger { size: a.diameter()?, gender })
+        }
+        _ => None,
+    }
+}
+
+/// Broad kinds of thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ThreadKind {
+    /// Named by a size filter threads come in (see `is_filter_size`).
+    FilterThread,
+    /// In `KNOWN_MOUNTS` or `KNOWN_BAYONETS`.
+    Mount,
+}
+
+/// Which kind of thread a thread is, if it's evidently either. Names should be canonical already.
+pub fn thread_kind(thread: Thread) -> Option<ThreadKind> {
+    let name = thread.name();
+    if KNOWN_MOUNTS.contains(&name) || KNOWN_BAYONETS.contains(&name) {
+        Some(ThreadKind::Mount)
+    } else if thread.diameter().is_some_and(is_filter_size) {
+        Some(ThreadKind::FilterThread)
+    } else {
+        None
+    }
+}
+
+/// Mounts that lock with a twist rather than screwing in. The rest of `KNOWN_MOUNTS` (M42, LTM)
+/// are screw mounts.
+const KNOWN_BAYONETS: &[&str] = &[
+    "EF", "EF-M", "RF", "FD", "F", "K", "E", "MFT", "X", "Z", "Bay1",
+];
+
+/// How a thread joins its other half. Anything not known to be a bayonet is taken to be a screw
+/// thread.
+pub fn coupling(thread: Thread) -> Coupling {
+    if KNOWN_BAYONETS.contains(&thread.name()) {
+        Coupling::Bayonet
+    } else {
+        Coupling::Screw
+    }
+}
+
+/// Throat diameters of lens mounts, in mm: the widest light path they let through.
+const KNOWN_APERTURES: &[(&str, f64)] = &[
+    ("EF", 54.), ("EF-M", 47.), ("RF", 54.), ("FD", 48.), ("F", 44.), ("K", 48.), ("M42", 42.),
+    ("LTM", 39.), ("E", 46.1), ("MFT", 38.), ("X", 43.5), ("Z", 55.),
+];
+
+/// How wide a light path a thread leaves, in mm: its size for filter threads and the like, or the
+/// throat diameter for known mounts. Names should be canonical already.
+pub fn clear_diameter(thread: Thread) -> Option<f64> {
+    thread.diameter().or_else(|| {
+        KNOWN_APERTURES.iter().find(|(name, _)| *name == thread.name()).map(|(_, d)| *d)
+    })
+}
+
+/// Screw mounts' major diameters and pitches, in mm. LTM is 26 threads per inch.
+const KNOWN_SCREW_MOUNTS: &[(&str, f64, f64)] = &[
+    ("M42", 42., 1.), ("LTM", 39., 25.4 / 26.),
+];
+
+/// Pitches of the usual filter thread sizes, in mm. Other sizes exist, but their pitches vary by
+/// maker.
+const FILTER_PITCHES: &[(f64, f64)] = &[
+    (25., 0.5), (27., 0.5), (28., 0.5), (30.5, 0.5), (34., 0.5), (37., 0.75), (39., 0.5),
+    (40.5, 0.5), (43., 0.75), (46., 0.75), (49., 0.75), (52., 0.75), (55., 0.75), (58., 0.75),
+    (62., 0.75), (67., 0.75), (72., 0.75), (77., 0.75), (82., 0.75), (86., 1.), (95., 1.),
+    (105., 1.),
+];
+
+/// Whether a filter thread could be this size, in mm: a multiple of 0.5 somewhere between the
+/// smallest and largest of the usual sizes.
+pub fn is_filter_size(size: f64) -> bool {
+    let (smallest, _) = FILTER_PITCHES[0];
+    let (largest, _) = FILTER_PITCHES[FILTER_PITCHES.len() - 1];
+    (smallest..=largest).contains(&size) && (size * 2.).fract() == 0.
+}
+
+/// The major diameter and pitch of a screw thread, in mm, if they're known. Names should be
+/// canonical already. Bayonets have neither.
+pub fn screw_thread(thread: Thread) -> Option<(f64, f64)> {
+    let mount = KNOWN_SCREW_MOUNTS.iter().find(|(name, ..)| *name == thread.nominal_name());
+    if let Some((_, diameter, pitch)) = mount {
+        return Some((*diameter, *pitch));
+    }
+    let diameter = thread.diameter()?;
+    let (_, pitch) = FILTER_PITCHES.iter().find(|(size, _)| *size == diameter)?;
+    Some((diameter, *pitch))
+}
+
+/// Other names people commonly use for the same thread.
+const BUILTIN_ALIASES: &[(&str, &str)] = &[
+    ("EOS", "EF"),
+    ("L39", "LTM"),
+    ("M39", "LTM"),
+    ("M42x1", "M42"),
+];
+
+/// Maps alternate spellings of thread names to one canonical name.
+///
+/// Besides the built-in table and any aliases added by the user, filter sizes written with a unit
+/// ("46mm") are normalized to the bare number ("46").
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Registry {
+    aliases: BTreeMap<String, String>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_alias(&mut self, alias: impl Into<String>, canonical: impl Into<String>) {
+        self.aliases.insert(alias.into(), canonical.into());
+    }
+
+    /// Make any user aliases for `old` point at `new` instead.
+    pub fn repoint_aliases(&mut self, old: &str, new: &str) {
+        for canonical in self.aliases.values_mut() {
+            if canonical == old {
+                *canonical = new.to_owned();
+            }
+        }
+    }
+
+    /// Aliases added by the user; the built-in ones aren't included.
+    pub fn aliases(&self) -> &BTreeMap<String, String> {
+        &self.aliases
+    }
+
+    /// The canonical form of a thread name. A left-hand mark ("LH", in any case, after the size)
+    /// is kept, and what's before it is normalized as usual: `58mm lh` becomes `58LH`.
+    pub fn canonical(&self, name: &str) -> &'static str {
+        let left_hand = name.strip_suffix("LH").or_else(|| name.strip_suffix("lh"));
+        if let Some(base) = left_hand.map(str::trim_end).filter(|base| !base.is_empty()) {
+            return intern(&format!("{}LH", self.canonical(base)));
+        }
+        if let Some(canonical) = self.aliases.get(name) {
+            return intern(canonical);
+        }
+        if let Some((_, canonical)) = BUILTIN_ALIASES.iter().find(|(alias, _)| *alias == name) {
+            return canonical;
+        }
+        // A clamp's range, like "49mm-58mm".
+        if let Some((low, high)) = name.split_once('-') {
+            let (low, high) = (self.canonical(low.trim()), self.canonical(high.trim()));
+            if low.parse::<f64>().is_ok() && high.parse::<f64>().is_ok() {
+                return intern(&format!("{}-{}", low, high));
+            }
+        }
+        match name.strip_suffix("mm") {
+            Some(size) if size.parse::<f64>().is_ok() => intern(size),
+            _ => intern(name),
+        }
+    }
+
+    pub fn canonical_thread(&self, thread: Thread) -> Thread {
+        thread.renamed(self.canonical(thread.name()))
+    }
+}
+
+/// Pairs of thread names used in `equipment` that are so alike one may be a typo for the other,
+/// like "M42" and "M24": they're one edit apart (a changed, added, dropped, or swapped character,
+/// ignoring case), and never on the same adapter. Pairs of filter sizes are left out, since
+/// those are often one digit apart on purpose, as are pairs of known mounts. Each pair is in
+/// alphabetical order, and the list is sorted.
+pub fn suspicious_thread_names(equipment: &[Adapter]) -> Vec<(String, String)> {
+    let names = equipment.iter()
+        .flat_map(|a| [a.0.name(), a.1.name()])
+        .filter(|name| *name != NIL_THREAD.name())
+        .collect::<BTreeSet<_>>();
+    let is_size = |name: &str| name.parse::<f64>().is_ok();
+    let is_known = |name: &str| KNOWN_MOUNTS.contains(&name) || KNOWN_BAYONETS.contains(&name);
+    let together = |a: &str, b: &str| {
+        equipment.iter().any(|adapter| {
+            let ends = [adapter.0.name(), adapter.1.name()];
+            ends.contains(&a) && ends.contains(&b)
+        })
+    };
+    let mut pairs = vec![];
+    for (i, a) in names.iter().enumerate() {
+        for b in names.iter().skip(i + 1) {
+            if (is_size(a) && is_size(b)) || (is_known(a) && is_known(b)) {
+                continue;
+            }
+            if edit_distance(&a.to_lowercase(), &b.to_lowercase()) <= 1 && !together(a, b) {
+                pairs.push((a.to_string(), b.to_string()));
+            }
+        }
+    }
+    pairs
+}
+
+/// How many single-character insertions, deletions, substitutions, or swaps of neighbouring
+/// characters it takes to turn one string into the other.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+    // d[i][j] is the distance between the first i characters of `a` and the first j of `b`.
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    d[0] = (0..=b.len()).collect();
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn near_duplicate_names_are_flagged() {
+        let equipment = crate::equipment![
+            "EF(M) -> M42(F)",
+            "M24(M) -> 52(F)",
+            "58(M) -> 52(F)",
+            "Bay1(M) -> BayI(F)",
+        ];
+        // 52 and 58 are both real sizes, and Bay1 and BayI are on the same adapter, so might
+        // well both be meant.
+        let pairs = suspicious_thread_names(&equipment);
+        assert_eq!(pairs, [("M24".to_owned(), "M42".to_owned())]);
+        assert_eq!(edit_distance("M24", "M42"), 1);
+        assert_eq!(edit_distance("M42", "M52"), 1);
+        assert_eq!(edit_distance("M42", "EF"), 3);
+    }
+
+    #[test]
+    fn proposed_adapters_map_to_what_to_shop_for() {
+        let described = |adapter: &str| {
+            let adapter = adapter.parse::<Adapter>().unwrap();
+            shape(&adapter).map(|shape| shape.to_string())
+        };
+        // Either way round, a ring from 46 male to 58 female is the same thing in a shop.
+        assert_eq!(shape(&"46(M) -> 58(F)".parse().unwrap()),
+            Some(Shape::StepUp { from: 46., to: 58. }));
+        assert_eq!(described("46(M) -> 58(F)").as_deref(), Some("46-58mm step-up ring"));
+        assert_eq!(described("58(F) -> 46(M)").as_deref(), Some("46-58mm step-up ring"));
+        assert_eq!(described("77(M) -> 52(F)").as_deref(), Some("77-52mm step-down ring"));
+        assert_eq!(described("52(M) -> 52(M)").as_deref(), Some("52mm double male ring"));
+        assert_eq!(described("EF(M) -> M42(F)").as_deref(),
+            Some("M42 lens to EF body adapter"));
+        // A mount straight to a filter thread isn't something shops sell as such.
+        assert_eq!(described("EF(M) -> 58(F)"), None);
+    }
+}
@@ -0,0 +1,164 @@
+//! OpenSCAD models of adapters, for printing one nobody sells.
+//!
+//! The model is a tube with one threaded section per end and a shoulder between them, with every
+//! size a variable at the top of the file so it can be adjusted in OpenSCAD. The threads are a
+//! rough helical approximation that's fine for a test fit; the comments in the output say where
+//! tuning is likely to be needed.
+
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+};
+
+use crate::{registry, Adapter, Coupling, Thread};
+
+/// Sizes that aren't determined by the threads, in mm.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScadOptions {
+    /// Thickness of material behind each thread.
+    pub wall: f64,
+    /// Length of each threaded section.
+    pub length: f64,
+}
+
+impl Default for ScadOptions {
+    fn default() -> Self {
+        Self { wall: 2., length: 6. }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScadError {
+    /// A bayonet can't be modelled from a diameter and pitch.
+    Bayonet(Thread),
+    /// There's no diameter or pitch known for the thread (see `registry::screw_thread`).
+    UnknownThread(Thread),
+    /// With walls that thick, there's no hole left through the middle.
+    NoBore { bore: f64 },
+}
+
+impl Display for ScadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bayonet(t) => {
+                write!(f, "{} is a bayonet, which can't be generated from a diameter and pitch; \
+                    its lugs would have to be measured and modelled by hand", t.name())
+            }
+            Self::UnknownThread(t) => {
+                write!(f, "the diameter and pitch of {} aren't known, so it can't be modelled",
+                    t.name())
+            }
+            Self::NoBore { bore } => {
+                write!(f, "walls that thick leave a hole {:.2} mm across through the middle; \
+                    try a thinner wall", bore)
+            }
+        }
+    }
+}
+
+impl Error for ScadError {}
+
+/// ISO metric thread depth for a pitch, external thread.
+fn thread_depth(pitch: f64) -> f64 {
+    0.6134 * pitch
+}
+
+/// The major diameter and pitch of a thread, or why it can't be modelled.
+fn dimensions(thread: Thread) -> Result<(f64, f64), ScadError> {
+    if registry::coupling(thread) == Coupling::Bayonet {
+        return Err(ScadError::Bayonet(thread));
+    }
+    registry::screw_thread(thread).ok_or(ScadError::UnknownThread(thread))
+}
+
+/// How wide a hole is left through an end: inside the wall behind a male thread, or the minor
+/// diameter of a female one.
+fn bore(thread: Thread, diameter: f64, pitch: f64, wall: f64) -> f64 {
+    let minor = diameter - 2. * thread_depth(pitch);
+    if thread.is_male() { minor - 2. * wall } else { minor }
+}
+
+/// An OpenSCAD file modelling the adapter, camera-facing end at the bottom. Both ends have to be
+/// screw threads with a known diameter and pitch.
+pub fn export(adapter: &Adapter, options: &ScadOptions) -> Result<String, ScadError> {
+    let (a, b) = adapter.threads();
+    let (a_diameter, a_pitch) = dimensions(a)?;
+    let (b_diameter, b_pitch) = dimensions(b)?;
+    let bore = bore(a, a_diameter, a_pitch, options.wall)
+        .min(bore(b, b_diameter, b_pitch, options.wall));
+    if bore <= 0. {
+        return Err(ScadError::NoBore { bore });
+    }
+    let title = match adapter.name() {
+        "" => format!("{} -> {}", a, b),
+        name => format!("{}: {} -> {}", name, a, b),
+    };
+    Ok(format!(r#"// {title}
+// Generated by adapter-party. Print a test piece first: the values marked TUNE depend on your
+// printer and material, and the threads are an approximation, not a true thread profile.
+
+wall = {wall}; // mm of material behind each thread
+length = {length}; // mm of thread at each end
+clearance = 0.2; // TUNE: gap per side between mating threads; raise it if the fit is too tight
+$fn = 128;
+
+// Camera-facing end, at the bottom.
+a_male = {a_male};
+a_left_hand = {a_left_hand};
+a_diameter = {a_diameter}; // mm, major diameter
+a_pitch = {a_pitch}; // mm
+
+// Far end, at the top.
+b_male = {b_male};
+b_left_hand = {b_left_hand};
+b_diameter = {b_diameter}; // mm, major diameter
+b_pitch = {b_pitch}; // mm
+
+// ISO metric thread depth. TUNE: printed threads often need a little less.
+function thread_depth(pitch) = 0.6134 * pitch;
+function end_bore(male, d, pitch) =
+    male ? d - 2 * thread_depth(pitch) - 2 * wall : d - 2 * thread_depth(pitch);
+function end_outer(male, d) = male ? d : d + 2 * wall;
+
+// The hole through the middle, as wide as the narrower end allows.
+bore = min(end_bore(a_male, a_diameter, a_pitch), end_bore(b_male, b_diameter, b_pitch));
+assert(bore > 0, "no hole left through the middle; use a thinner wall");
+
+// A thread's ridge, approximated by twisting an off-centre circle once per pitch.
+// TUNE: for a proper profile, swap this for a thread library's module.
+module helix(d, pitch, h, left_hand) {{
+    twist = (left_hand ? 360 : -360) * h / pitch;
+    linear_extrude(height = h, twist = twist, slices = ceil(h / pitch) * 16)
+        translate([thread_depth(pitch) / 2, 0]) circle(d = d - thread_depth(pitch));
+}}
+
+module end(male, d, pitch, left_hand) {{
+    if (male) {{
+        difference() {{
+            helix(d - 2 * clearance, pitch, length, left_hand);
+            translate([0, 0, -1]) cylinder(d = bore, h = length + 2);
+        }}
+    }} else {{
+        difference() {{
+            cylinder(d = end_outer(male, d), h = length);
+            translate([0, 0, -0.01]) helix(d + 2 * clearance, pitch, length + 0.02, left_hand);
+        }}
+    }}
+}}
+
+end(a_male, a_diameter, a_pitch, a_left_hand);
+// Shoulder joining the two ends. TUNE: make it thicker if it flexes.
+translate([0, 0, length]) difference() {{
+    cylinder(d = max(end_outer(a_male, a_diameter), end_outer(b_male, b_diameter)), h = wall);
+    translate([0, 0, -1]) cylinder(d = bore, h = wall + 2);
+}}
+translate([0, 0, length + wall]) end(b_male, b_diameter, b_pitch, b_left_hand);
+"#,
+        wall = options.wall,
+        length = options.length,
+        a_male = a.is_male(),
+        a_left_hand = a.is_left_handed(),
+        b_male = b.is_male(),
+        b_left_hand = b.is_left_handed(),
+    ))
+}
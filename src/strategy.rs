@@ -0,0 +1,119 @@
+//! proptest strategies for generating equipment, for fuzzing code built on top of this crate.
+//!
+//! Thread names are drawn from a pool of real-world filter sizes and mounts, so generated
+//! equipment looks like someone's actual drawer: lots of shared threads, and therefore lots of
+//! chains. Smaller pools mean more connections.
+
+use proptest::{
+    arbitrary::Arbitrary,
+    collection::vec,
+    prelude::*,
+    sample::SizeRange,
+};
+
+use crate::{Adapter, Thread};
+
+const THREAD_NAMES: &[&str] = &[
+    "52", "58", "77", "46", "EF", "LTM", "40.5", "49", "55", "62", "M42", "67", "72", "43", "FD",
+    "Bay1",
+];
+
+/// A thread with a name drawn from the first `pool_size` names. Pools bigger than the built-in
+/// list of names get made-up names.
+pub fn thread(pool_size: usize) -> impl Strategy<Value = Thread> {
+    let pool_size = pool_size.max(1);
+    (0..pool_size, any::<bool>()).prop_map(|(idx, male)| {
+        let name = match THREAD_NAMES.get(idx) {
+            Some(name) => name.to_string(),
+            None => format!("T{}", idx),
+        };
+        if male { Thread::male(&name) } else { Thread::female(&name) }
+    })
+}
+
+/// An unnamed adapter between two threads from the pool. The two ends may be the same thread.
+pub fn adapter(pool_size: usize) -> impl Strategy<Value = Adapter> {
+    (thread(pool_size), thread(pool_size)).prop_map(|(a, b)| Adapter::new(a, b))
+}
+
+/// A set of adapters, `count` of them, all using threads from the pool.
+pub fn equipment(
+    pool_size: usize,
+    count: impl Into<SizeRange>,
+) -> impl Strategy<Value = Vec<Adapter>> {
+    vec(adapter(pool_size), count)
+}
+
+impl Arbitrary for Thread {
+    /// Thread pool size.
+    type Parameters = usize;
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(pool_size: usize) -> Self::Strategy {
+        let pool_size = if pool_size == 0 { THREAD_NAMES.len() } else { pool_size };
+        thread(pool_size).boxed()
+    }
+}
+
+impl Arbitrary for Adapter {
+    /// Thread pool size.
+    type Parameters = usize;
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(pool_size: usize) -> Self::Strategy {
+        let pool_size = if pool_size == 0 { THREAD_NAMES.len() } else { pool_size };
+        adapter(pool_size).boxed()
+    }
+}
+
+#[cfg(all(test, feature = "proptest"))]
+mod tests {
+    use std::hash::{BuildHasher, RandomState};
+
+    use proptest::proptest;
+
+    use super::*;
+    use crate::make_chain;
+
+    proptest! {
+        #[test]
+        fn chains_are_valid(
+            equipment in equipment(6, 0..6),
+            start in thread(6),
+            end in thread(6),
+        ) {
+            for chain in make_chain(start, end, &equipment) {
+                prop_assert!(chain.is_valid(), "{}", chain);
+            }
+        }
+
+        #[test]
+        fn reversing_twice_undoes_itself(
+            equipment in equipment(6, 0..6),
+            start in thread(6),
+            end in thread(6),
+        ) {
+            for adapter in &equipment {
+                let twice = adapter.clone().reverse().reverse();
+                prop_assert_eq!(format!("{:?}", twice), format!("{:?}", adapter));
+            }
+            for chain in make_chain(start, end, &equipment) {
+                let reversed = chain.clone().reverse();
+                prop_assert!(reversed.is_valid(), "{}", reversed);
+                prop_assert_eq!(format!("{:?}", reversed.reverse()), format!("{:?}", chain));
+            }
+        }
+
+        #[test]
+        fn hash_agrees_with_eq_when_reversed(a in any::<Adapter>(), b in any::<Adapter>()) {
+            let hasher = RandomState::new();
+            let reversed = a.clone().reverse();
+            prop_assert_eq!(&reversed, &a);
+            prop_assert_eq!(hasher.hash_one(&reversed), hasher.hash_one(&a));
+            if a == b {
+                prop_assert_eq!(hasher.hash_one(&a), hasher.hash_one(&b));
+                prop_assert_eq!(hasher.hash_one(&a), hasher.hash_one(b.reverse()));
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! The C interface, through `ffi/example.c`: builds the shared library, compiles the example
+//! against the header, and runs it. Some cases call the functions directly instead.
+
+#![cfg(all(feature = "ffi", unix))]
+
+use std::{
+    env,
+    ffi::{CStr, CString},
+    path::Path,
+    process::Command,
+    ptr,
+};
+
+use adapter_party::{
+    ffi::{ap_find_chains, ap_free, ap_inventory_free, ap_inventory_parse},
+    Chain,
+};
+
+/// Run a command, failing the test with its output if it fails, and return its stdout.
+fn run(command: &mut Command) -> String {
+    let output = command.output().unwrap_or_else(|e| panic!("{:?}: {}", command, e));
+    assert!(output.status.success(), "{:?} failed: {}{}", command,
+        String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn example_program_finds_chains() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    // A target directory of its own, so as not to wait on the lock cargo holds on the one this
+    // test is running from.
+    let target = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ffi");
+    run(Command::new(env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned()))
+        .current_dir(root)
+        .args(["rustc", "--lib", "--features", "ffi", "--crate-type", "cdylib", "--target-dir"])
+        .arg(&target));
+    let lib = target.join("debug");
+
+    let example = target.join("example");
+    run(Command::new(env::var("CC").unwrap_or_else(|_| "cc".to_owned()))
+        .current_dir(root)
+        .args(["ffi/example.c", "-Iffi", "-L"])
+        .arg(&lib)
+        .args(["-ladapter_party", "-o"])
+        .arg(&example));
+
+    let stdout = run(Command::new(&example)
+        .env("LD_LIBRARY_PATH", &lib)
+        .env("DYLD_LIBRARY_PATH", &lib));
+    let json: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    let chains: Vec<Chain> = serde_json::from_value(json["chains"].clone()).unwrap();
+    let chains = chains.iter().map(Chain::to_string).collect::<Vec<_>>();
+    assert_eq!(chains, ["[start: EF(F)] [EF(M) -> 58(F)] [58(M) -> 52(F)] [end: 52(M)] "]);
+    assert!(json["stats"].is_object());
+}
+
+#[test]
+fn find_chains_keeps_to_the_inventory_roles() {
+    // 58 can only be on the body, and 52 only at the target, so neither can be a junction.
+    let toml = CString::new(r#"
+        [roles]
+        58 = "body"
+        52 = "terminal"
+
+        [[adapter]]
+        threads = ["EF(M)", "58(F)"]
+        [[adapter]]
+        threads = ["58(M)", "46(F)"]
+        [[adapter]]
+        threads = ["EF(M)", "52(F)"]
+        [[adapter]]
+        threads = ["52(M)", "46(F)"]
+        [[adapter]]
+        threads = ["EF(M)", "46(F)"]
+    "#).unwrap();
+    let (from, to) = (CString::new("EF(F)").unwrap(), CString::new("46(M)").unwrap());
+    let chains = unsafe {
+        let inventory = ap_inventory_parse(toml.as_ptr());
+        assert!(!inventory.is_null());
+        let json = ap_find_chains(inventory, from.as_ptr(), to.as_ptr(), ptr::null());
+        let chains = CStr::from_ptr(json).to_str().unwrap().to_owned();
+        ap_free(json);
+        ap_inventory_free(inventory);
+        chains
+    };
+    let json: serde_json::Value = serde_json::from_str(&chains).unwrap();
+    let chains: Vec<Chain> = serde_json::from_value(json["chains"].clone()).unwrap();
+    let chains = chains.iter().map(Chain::to_string).collect::<Vec<_>>();
+    assert_eq!(chains, ["[start: EF(F)] [EF(M) -> 46(F)] [end: 46(M)] "]);
+}
@@ -0,0 +1,84 @@
+//! Writing results to a file with `--output`, through the command line.
+
+use std::{fs, path::PathBuf, process::Command};
+
+use adapter_party::{inventory::{Inventory, LoadOptions}, make_chain, Chain, Thread};
+
+const INVENTORY: &str = r#"[[adapter]]
+threads = ["EF(M)", "58(F)"]
+
+[[adapter]]
+threads = ["58(M)", "52(F)"]
+
+[[adapter]]
+threads = ["EF(M)", "46(F)"]
+"#;
+
+/// A scratch directory holding `INVENTORY`, deleted when dropped.
+struct Scratch(PathBuf);
+
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir()
+            .join(format!("adapter-party-output-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("inventory.toml"), INVENTORY).unwrap();
+        Self(dir)
+    }
+
+    /// Run a command on the inventory, with `--output` pointing at `out.json`, and return what it
+    /// wrote there.
+    fn run(&self, args: &[&str]) -> String {
+        let out = self.0.join("out.json");
+        let output = Command::new(env!("CARGO_BIN_EXE_adapter-party"))
+            .args(args)
+            .args(["--format", "json", "--output"])
+            .arg(&out)
+            .arg(self.0.join("inventory.toml"))
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?} failed: {}", args,
+            String::from_utf8_lossy(&output.stderr));
+        assert!(output.stdout.is_empty(), "{:?} wrote to stdout too", args);
+        fs::read_to_string(out).unwrap()
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn chains_written_as_json_read_back() {
+    let scratch = Scratch::new("chain");
+    let json = scratch.run(&["chain", "--from", "EF(F)", "--to", "52(M)"]);
+    let chains: Vec<Chain> = serde_json::from_str(&json).unwrap();
+
+    let inventory = Inventory::from_toml_str(INVENTORY, &LoadOptions::default()).unwrap();
+    let expected = make_chain(Thread::female("EF"), Thread::male("52"), &inventory.equipment());
+    let strings = |chains: &[Chain]| chains.iter().map(Chain::to_string).collect::<Vec<_>>();
+    assert_eq!(strings(&chains), strings(&expected));
+    assert_eq!(chains.len(), 1);
+
+    // Sorted, they're all found before any are written, and come out the same.
+    let json = scratch.run(&["chain", "--from", "EF(F)", "--to", "52(M)", "--sort", "default"]);
+    let sorted: Vec<Chain> = serde_json::from_str(&json).unwrap();
+    assert_eq!(strings(&sorted), strings(&expected));
+}
+
+#[test]
+fn suggestions_written_as_json_read_back() {
+    let scratch = Scratch::new("suggest");
+    let json = scratch.run(&["suggest", "--from", "EF(F)", "--to", "77(M)"]);
+    let suggestions: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let suggestions = suggestions.as_array().unwrap();
+    assert!(!suggestions.is_empty());
+    for suggestion in suggestions {
+        assert_eq!(suggestion["buy"].as_array().unwrap().len(), 1);
+        let chain: Chain = serde_json::from_value(suggestion["chain"].clone()).unwrap();
+        assert!(chain.is_valid());
+        assert!(chain.to_string().ends_with("[end: 77(M)] "));
+    }
+}
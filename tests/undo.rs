@@ -0,0 +1,134 @@
+//! Undoing each kind of change to an inventory file, through the command line.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+const INVENTORY: &str = r#"[[adapter]]
+threads = ["EF(M)", "58(F)"]
+
+[[adapter]]
+name = "Rodagon"
+threads = ["LTM(M)", "40.5(F)"]
+category = "lens"
+notes = "aperture ring is stiff"
+
+[[adapter]]
+threads = ["46mm(M)", "52(F)"]
+"#;
+
+/// A scratch copy of `INVENTORY`, deleted (with its journal) when dropped.
+struct Scratch(PathBuf);
+
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("adapter-party-undo-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.toml", name));
+        fs::write(&path, INVENTORY).unwrap();
+        let _ = fs::remove_file(path.with_extension("journal.jsonl"));
+        Self(path)
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+
+    fn contents(&self) -> String {
+        fs::read_to_string(&self.0).unwrap()
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        let output = Command::new(env!("CARGO_BIN_EXE_adapter-party"))
+            .args(args)
+            .arg(&self.0)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?} failed: {}", args,
+            String::from_utf8_lossy(&output.stderr));
+        output
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+        let _ = fs::remove_file(self.0.with_extension("journal.jsonl"));
+    }
+}
+
+/// Make a change, check it changed the file, undo it, and check the file is back how it was.
+fn round_trip(name: &str, change: &[&str], setup: &[&[&str]]) {
+    let scratch = Scratch::new(name);
+    for args in setup {
+        scratch.run(args);
+    }
+    let before = scratch.contents();
+    scratch.run(change);
+    assert_ne!(scratch.contents(), before, "{:?} changed nothing", change);
+    scratch.run(&["undo"]);
+    assert_eq!(scratch.contents(), before);
+}
+
+#[test]
+fn undo_loan() {
+    round_trip("loan", &["loan", "Rodagon", "--to", "Sam", "--on", "2024-05-01"], &[]);
+}
+
+#[test]
+fn undo_return() {
+    round_trip("return", &["return", "Rodagon"],
+        &[&["loan", "Rodagon", "--to", "Sam", "--on", "2024-05-01"]]);
+}
+
+#[test]
+fn undo_rename_thread() {
+    round_trip("rename", &["rename-thread", "46mm", "46"], &[]);
+}
+
+#[test]
+fn undo_fmt() {
+    round_trip("fmt", &["fmt"], &[]);
+}
+
+#[test]
+fn undo_several_steps() {
+    let scratch = Scratch::new("steps");
+    scratch.run(&["loan", "Rodagon", "--to", "Sam"]);
+    scratch.run(&["rename-thread", "46mm", "46"]);
+    scratch.run(&["undo", "--steps", "2"]);
+    assert_eq!(scratch.contents(), INVENTORY);
+
+    let log = scratch.run(&["log"]);
+    let log = String::from_utf8(log.stdout).unwrap();
+    assert_eq!(log.matches("(undone)").count(), 2, "{}", log);
+}
+
+#[test]
+fn dry_run_changes_nothing() {
+    let scratch = Scratch::new("dry-run");
+    scratch.run(&["loan", "Rodagon", "--to", "Sam"]);
+    let loaned = scratch.contents();
+    scratch.run(&["undo", "--dry-run"]);
+    assert_eq!(scratch.contents(), loaned);
+}
+
+#[test]
+fn refuses_to_undo_past_an_external_edit() {
+    let scratch = Scratch::new("edited");
+    scratch.run(&["loan", "Rodagon", "--to", "Sam"]);
+    let edited = scratch.contents() + "\n[[adapter]]\nthreads = [\"58(M)\", \"77(F)\"]\n";
+    fs::write(scratch.path(), &edited).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_adapter-party"))
+        .args(["undo"])
+        .arg(scratch.path())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("edited outside adapter-party"), "{}", stderr);
+    assert_eq!(scratch.contents(), edited);
+}